@@ -0,0 +1,589 @@
+//! A small blocking HTTP/1.1 client. Requests and responses are the same `http_message` types the server uses,
+//! so a response read here can be checked just like one the server built.
+
+extern crate uri;
+extern crate http_message;
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpStream, SocketAddr};
+use std::sync::Mutex;
+use std::time::Duration;
+use uri::{Host, Uri};
+
+use http_message::model::*;
+use http_message::limits::Limits;
+use http_message::parser::HttpRequestParser;
+
+/// How many idle connections are kept for each server.
+const MAX_IDLE_PER_HOST: usize = 8;
+
+#[derive(Debug)]
+pub enum ClientError {
+	/// The URL isn't an absolute http:// URL.
+	InvalidUrl(String),
+	/// No connection could be made to the server.
+	Connect(io::Error),
+	/// The connection failed part way through the exchange.
+	Io(io::Error),
+	/// The server took longer than the client's timeout to answer.
+	Timeout,
+	/// The response could not be parsed.
+	InvalidResponse(String),
+	/// Redirects went on for longer than the client follows them.
+	TooManyRedirects(usize)
+}
+
+impl Display for ClientError {
+	fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+		match *self {
+			ClientError::InvalidUrl(ref url) => write!(f, "'{}' is not an http:// URL", url),
+			ClientError::Connect(ref e) => write!(f, "Failed to connect: {}", e),
+			ClientError::Io(ref e) => write!(f, "Connection failed: {}", e),
+			ClientError::Timeout => write!(f, "Timed out waiting for the response"),
+			ClientError::InvalidResponse(ref reason) => write!(f, "Invalid response: {}", reason),
+			ClientError::TooManyRedirects(n) => write!(f, "Gave up after {} redirects", n)
+		}
+	}
+}
+
+/// Builds a request for an absolute URL, e.g. `http://127.0.0.1:7878/login`, ready for headers and a body.
+/// The URL can be an IRI, e.g. `http://bücher.example/straße`, which is sent as the URI it stands for.
+pub fn request(method: AllowedMethod, url: &str) -> Result<HttpRequest, ClientError> {
+	let uri = parse_url(url)?;
+	Target::from_uri(&uri)?;
+
+	Ok(HttpRequest {
+		start_line: StartLine {
+			method,
+			uri,
+			major_version: 1,
+			minor_version: 1
+		},
+		headers: HeaderCollection::init_empty(),
+		body: Vec::new()
+	})
+}
+
+/// Sends requests, keeping connections open between them and following redirects.
+pub struct Client {
+	//idle connections by the host:port they're open to
+	pool: Mutex<HashMap<String, Vec<TcpStream>>>,
+	max_redirects: usize,
+	timeout: Duration,
+	limits: Limits
+}
+
+impl Client {
+	pub fn new() -> Client {
+		Client {
+			pool: Mutex::new(HashMap::new()),
+			max_redirects: 10,
+			timeout: Duration::from_secs(30),
+			limits: Limits::default()
+		}
+	}
+
+	/// How many redirects are followed before giving up; 0 hands redirects back as they are.
+	pub fn with_max_redirects(mut self, max_redirects: usize) -> Client {
+		self.max_redirects = max_redirects;
+		self
+	}
+
+	pub fn with_timeout(mut self, timeout: Duration) -> Client {
+		self.timeout = timeout;
+		self
+	}
+
+	/// Limits on the responses read, e.g. how large a body is accepted.
+	pub fn with_limits(mut self, limits: Limits) -> Client {
+		self.limits = limits;
+		self
+	}
+
+	pub fn get(&self, url: &str) -> Result<HttpResponse, ClientError> {
+		self.send(request(AllowedMethod::GET, url)?)
+	}
+
+	pub fn post<B: Into<Vec<u8>>>(&self, url: &str, content_type: &str, body: B) -> Result<HttpResponse, ClientError> {
+		let mut request = request(AllowedMethod::POST, url)?;
+		request.headers.get_or_add("Content-Type").add(content_type.to_string());
+		request.body = body.into();
+		self.send(request)
+	}
+
+	/// Sends a request whose URI is absolute, following any redirects. Host and Content-Length are filled in.
+	pub fn send(&self, mut request: HttpRequest) -> Result<HttpResponse, ClientError> {
+		let mut redirects = 0;
+
+		loop {
+			let target = Target::from_uri(&request.start_line.uri)?;
+			let response = self.exchange(&target, &request)?;
+
+			let code = response.status_line.status_code().get_code();
+			let location = match response.headers.first("Location") {
+				Some(location) if is_redirect(code) && self.max_redirects > 0 => target.resolve(location),
+				_ => return Ok(response)
+			};
+
+			redirects += 1;
+			if redirects > self.max_redirects {
+				return Err(ClientError::TooManyRedirects(self.max_redirects));
+			}
+
+			let uri = parse_url(&location)?;
+			let next = Target::from_uri(&uri)?;
+
+			//303 always means GET the new location; so do 301 and 302 after a POST, as browsers have always done
+			if code == 303 || ((code == 301 || code == 302) && request.start_line.method == AllowedMethod::POST) {
+				request.start_line.method = AllowedMethod::GET;
+				request.body.clear();
+				request.headers.remove("Content-Type");
+				request.headers.remove("Content-Length");
+			}
+			//credentials are only for the server they were meant for
+			if next.authority() != target.authority() {
+				request.headers.remove("Authorization");
+				request.headers.remove("Cookie");
+			}
+			request.headers.remove("Host");
+			request.start_line.uri = uri;
+		}
+	}
+
+	/// How many connections are open and waiting to be reused.
+	pub fn idle_connections(&self) -> usize {
+		self.pool.lock().unwrap().values().map(|c| c.len()).sum()
+	}
+
+	// One request and its response, on a pooled connection if there is one. A pooled connection can have been
+	// closed by the server while it sat idle, so failing before any response arrives is retried on a new one.
+	fn exchange(&self, target: &Target, request: &HttpRequest) -> Result<HttpResponse, ClientError> {
+		if let Some(stream) = self.checkout(&target.authority()) {
+			match self.exchange_on(stream, target, request) {
+				Ok(response) => return Ok(response),
+				Err(Exchange::Stale(_)) => {},
+				Err(Exchange::Failed(e)) => return Err(e)
+			}
+		}
+
+		let stream = self.connect(target)?;
+		match self.exchange_on(stream, target, request) {
+			Ok(response) => Ok(response),
+			Err(Exchange::Stale(e)) | Err(Exchange::Failed(e)) => Err(e)
+		}
+	}
+
+	fn exchange_on(&self, mut stream: TcpStream, target: &Target, request: &HttpRequest) -> Result<HttpResponse, Exchange> {
+		if let Err(e) = stream.write_all(&request_bytes(target, request)).and_then(|_| stream.flush()) {
+			return Err(Exchange::Stale(io_error(e)));
+		}
+
+		let mut parser = HttpRequestParser::new().with_limits(self.limits.clone());
+		let mut first = true;
+		let (status_line, mut headers) = loop {
+			let status_line = match parser.get_status_line(&mut stream) {
+				Ok(status_line) => status_line,
+				Err(status) => return Err(match read_error(status) {
+					ClientError::Timeout => Exchange::Failed(ClientError::Timeout),
+					e if first => Exchange::Stale(e),
+					e => Exchange::Failed(e)
+				})
+			};
+			first = false;
+			let headers = parser.get_request_headers(&mut stream).map_err(|s| Exchange::Failed(read_error(s)))?;
+
+			//interim responses are skipped; nothing was sent that asked for one
+			if status_line.status_code().get_code() >= 200 {
+				break (status_line, headers);
+			}
+		};
+
+		let code = status_line.status_code().get_code();
+		let framed = headers.first("Transfer-Encoding").is_some() || headers.first("Content-Length").is_some();
+		let body = if code == 204 || code == 304 {
+			Vec::new()
+		} else if framed {
+			parser.get_request_body(&mut stream, &headers).map_err(|s| Exchange::Failed(read_error(s)))?
+		} else {
+			self.read_until_closed(&mut parser, &mut stream).map_err(Exchange::Failed)?
+		};
+
+		//the body has been decoded, so it is as if it had been sent with a length
+		if headers.remove("Transfer-Encoding").is_some() {
+			headers.get_or_add("Content-Length").add(body.len().to_string());
+		}
+
+		let closes = headers.get("Connection").map(|h| h.values().iter().any(|v| v.eq_ignore_ascii_case("close"))).unwrap_or(false);
+		let reusable = (framed || code == 204 || code == 304) && !closes && status_line.version() == (1, 1) && parser.take_buffered().is_empty();
+		if reusable {
+			self.checkin(target.authority(), stream);
+		}
+
+		let mut response = HttpResponse::from_status_line(status_line);
+		response.headers = headers;
+		response.body = body;
+		Ok(response)
+	}
+
+	fn read_until_closed(&self, parser: &mut HttpRequestParser, stream: &mut TcpStream) -> Result<Vec<u8>, ClientError> {
+		let mut body = parser.take_buffered();
+		let limit = self.limits.max_body_len.saturating_sub(body.len());
+		stream.take(limit as u64 + 1).read_to_end(&mut body).map_err(io_error)?;
+		if body.len() > self.limits.max_body_len {
+			return Err(ClientError::InvalidResponse(String::from("Body too large")));
+		}
+		Ok(body)
+	}
+
+	fn connect(&self, target: &Target) -> Result<TcpStream, ClientError> {
+		let addrs: Vec<SocketAddr> = target.host.resolve(target.port).map_err(ClientError::Connect)?.collect();
+
+		let mut last_error = io::Error::new(io::ErrorKind::NotFound, "host did not resolve");
+		for addr in addrs {
+			match TcpStream::connect_timeout(&addr, self.timeout) {
+				Ok(stream) => {
+					stream.set_read_timeout(Some(self.timeout)).map_err(ClientError::Connect)?;
+					stream.set_write_timeout(Some(self.timeout)).map_err(ClientError::Connect)?;
+					let _ = stream.set_nodelay(true);
+					return Ok(stream);
+				},
+				Err(e) => last_error = e
+			}
+		}
+		Err(ClientError::Connect(last_error))
+	}
+
+	// An idle connection that is still open, dropping any the server has since closed
+	fn checkout(&self, authority: &str) -> Option<TcpStream> {
+		let mut pool = self.pool.lock().unwrap();
+		let idle = pool.get_mut(authority)?;
+		while let Some(stream) = idle.pop() {
+			if is_open(&stream) {
+				return Some(stream);
+			}
+		}
+		None
+	}
+
+	fn checkin(&self, authority: String, stream: TcpStream) {
+		let mut pool = self.pool.lock().unwrap();
+		let idle = pool.entry(authority).or_default();
+		if idle.len() < MAX_IDLE_PER_HOST {
+			idle.push(stream);
+		}
+	}
+}
+
+impl Default for Client {
+	fn default() -> Client {
+		Client::new()
+	}
+}
+
+// How an exchange on a connection failed
+enum Exchange {
+	//nothing came back, as when the server had already closed the connection
+	Stale(ClientError),
+	Failed(ClientError)
+}
+
+// Where a request goes: the server and the path in origin-form, e.g. `/login?next=%2F`
+#[derive(Debug, PartialEq)]
+struct Target {
+	host: Host,
+	port: u16,
+	path: String
+}
+
+impl Target {
+	fn from_uri(uri: &Uri) -> Result<Target, ClientError> {
+		let invalid = || ClientError::InvalidUrl(uri.to_string());
+		if uri.scheme.as_ref().map(|s| s.eq_ignore_ascii_case("http")) != Some(true) || uri.validate().is_err() {
+			return Err(invalid());
+		}
+
+		let host = uri.path.authority.as_ref().and_then(|a| a.host.clone()).ok_or_else(invalid)?;
+		let port = uri.effective_port().ok().and_then(|p| p).ok_or_else(invalid)?;
+
+		let mut path = format!("/{}", uri.path.path_components.join("/"));
+		if let Some(ref query) = uri.query {
+			path.push('?');
+			path.push_str(query);
+		}
+
+		Ok(Target {
+			host,
+			port,
+			path
+		})
+	}
+
+	// What goes in Host, and what pooled connections are kept under
+	fn authority(&self) -> String {
+		if self.port == 80 {
+			self.host.to_string()
+		} else {
+			format!("{}:{}", self.host, self.port)
+		}
+	}
+
+	// The absolute URL a Location header points to from here
+	fn resolve(&self, location: &str) -> String {
+		match (Uri::parse(&format!("http://{}{}", self.authority(), self.path)), Uri::parse_iri(location)) {
+			(Ok(base), Ok(reference)) => {
+				let mut url = base.join(&reference);
+				url.fragment = None;
+				url.to_string()
+			},
+			//left for parse_url to turn away
+			_ => location.to_string()
+		}
+	}
+}
+
+// Whether it's an http:// URL with a host is up to `Target::from_uri`
+fn parse_url(url: &str) -> Result<Uri, ClientError> {
+	Uri::parse_iri(url).map_err(|_| ClientError::InvalidUrl(url.to_string()))
+}
+
+fn request_bytes(target: &Target, request: &HttpRequest) -> Vec<u8> {
+	let mut head = format!("{} {} HTTP/1.1\r\n", request.start_line.method, target.path);
+	if request.headers.get("Host").is_none() {
+		head.push_str(&format!("Host: {}\r\n", target.authority()));
+	}
+	for header in request.headers.iter() {
+		if *header.header_type() == HttpHeaderType::ContentLength {
+			continue;
+		}
+		for value in header.values() {
+			head.push_str(&format!("{}: {}\r\n", header.name(), value));
+		}
+	}
+	if !request.body.is_empty() || request.start_line.method == AllowedMethod::POST {
+		head.push_str(&format!("Content-Length: {}\r\n", request.body.len()));
+	}
+	head.push_str("\r\n");
+
+	let mut bytes = head.into_bytes();
+	bytes.extend_from_slice(&request.body);
+	bytes
+}
+
+fn is_redirect(code: u32) -> bool {
+	matches!(code, 301 | 302 | 303 | 307 | 308)
+}
+
+// Whether the server has left an idle connection open; one it closed reads as the end of the stream
+fn is_open(stream: &TcpStream) -> bool {
+	if stream.set_nonblocking(true).is_err() {
+		return false;
+	}
+	let open = match stream.peek(&mut [0_u8; 1]) {
+		Err(ref e) => e.kind() == io::ErrorKind::WouldBlock,
+		//an idle connection has nothing to say, so anything read means it can't be trusted
+		Ok(_) => false
+	};
+	stream.set_nonblocking(false).is_ok() && open
+}
+
+fn read_error(status: StatusLine) -> ClientError {
+	if status.status_code() == StatusCode::request_timeout() {
+		ClientError::Timeout
+	} else {
+		ClientError::InvalidResponse(status.reason().to_string())
+	}
+}
+
+fn io_error(e: io::Error) -> ClientError {
+	match e.kind() {
+		io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ClientError::Timeout,
+		_ => ClientError::Io(e)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::{BufRead, BufReader};
+	use std::net::TcpListener;
+	use std::sync::mpsc::{channel, Receiver};
+	use std::thread;
+
+	// A server that answers each connection it takes with the responses listed for it, in order, then closes it.
+	// Sends back the head and body of each request it gets.
+	fn stand_in(connections: Vec<Vec<&'static str>>) -> (String, Receiver<String>) {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let address = format!("http://{}", listener.local_addr().unwrap());
+		let (requests, received) = channel();
+
+		thread::spawn(move || {
+			for responses in connections {
+				let (mut stream, _) = listener.accept().unwrap();
+				let mut reader = BufReader::new(stream.try_clone().unwrap());
+				for response in responses {
+					let mut request = String::new();
+					let mut length = 0;
+					loop {
+						let mut line = String::new();
+						reader.read_line(&mut line).unwrap();
+						if line.to_ascii_lowercase().starts_with("content-length:") {
+							length = line[15..].trim().parse().unwrap();
+						}
+						request.push_str(&line);
+						if line == "\r\n" {
+							break;
+						}
+					}
+					let mut body = vec![0_u8; length];
+					reader.read_exact(&mut body).unwrap();
+					request.push_str(&String::from_utf8(body).unwrap());
+
+					let _ = requests.send(request);
+					stream.write_all(response.as_bytes()).unwrap();
+				}
+			}
+		});
+
+		(address, received)
+	}
+
+	#[test]
+	fn sends_host_and_reads_a_sized_body() {
+		let (address, requests) = stand_in(vec![vec!["HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello"]]);
+		let response = Client::new().get(&format!("{}/a/b?c=d", address)).unwrap();
+
+		assert_eq!(response.status_line.status_code(), StatusCode::ok());
+		assert_eq!(response.body, b"hello");
+		let request = requests.recv().unwrap();
+		assert!(request.starts_with("GET /a/b?c=d HTTP/1.1\r\n"));
+		assert!(request.contains(&format!("Host: {}\r\n", &address[7..])));
+	}
+
+	#[test]
+	fn decodes_chunked_bodies() {
+		let (address, _) = stand_in(vec![vec!["HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n"]]);
+		let response = Client::new().get(&address).unwrap();
+
+		assert_eq!(response.body, b"hello world");
+		assert!(response.headers.get("Transfer-Encoding").is_none());
+		assert_eq!(response.headers.first("Content-Length"), Some("11"));
+	}
+
+	#[test]
+	fn reads_unframed_bodies_to_the_end_and_does_not_pool_them() {
+		let (address, _) = stand_in(vec![vec!["HTTP/1.0 200 OK\r\n\r\nall of it"]]);
+		let client = Client::new();
+		let response = client.get(&address).unwrap();
+
+		assert_eq!(response.status_line.version(), (1, 0));
+		assert_eq!(response.body, b"all of it");
+		assert_eq!(client.idle_connections(), 0);
+	}
+
+	#[test]
+	fn reuses_kept_alive_connections() {
+		//one connection serving both requests; a second connect would never be accepted
+		let (address, requests) = stand_in(vec![vec![
+			"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\none",
+			"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\ntwo"
+		]]);
+		let client = Client::new().with_timeout(Duration::from_secs(5));
+
+		assert_eq!(client.get(&address).unwrap().body, b"one");
+		assert_eq!(client.idle_connections(), 1);
+		assert_eq!(client.post(&address, "text/plain", "ping").unwrap().body, b"two");
+
+		requests.recv().unwrap();
+		let post = requests.recv().unwrap();
+		assert!(post.starts_with("POST / HTTP/1.1\r\n"));
+		assert!(post.contains("Content-Length: 4\r\n"));
+		assert!(post.ends_with("\r\n\r\nping"));
+	}
+
+	#[test]
+	fn reconnects_when_a_pooled_connection_was_closed() {
+		let (address, _) = stand_in(vec![
+			vec!["HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\none"],
+			vec!["HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\ntwo"]
+		]);
+		let client = Client::new();
+
+		assert_eq!(client.get(&address).unwrap().body, b"one");
+		thread::sleep(Duration::from_millis(50));
+		assert_eq!(client.get(&address).unwrap().body, b"two");
+	}
+
+	#[test]
+	fn see_other_after_a_post_becomes_a_get() {
+		let (address, requests) = stand_in(vec![vec![
+			"HTTP/1.1 303 See Other\r\nLocation: /done\r\nContent-Length: 0\r\n\r\n",
+			"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\ndone"
+		]]);
+		let response = Client::new().post(&format!("{}/form", address), "text/plain", "data").unwrap();
+
+		assert_eq!(response.body, b"done");
+		assert!(requests.recv().unwrap().starts_with("POST /form HTTP/1.1\r\n"));
+		let redirected = requests.recv().unwrap();
+		assert!(redirected.starts_with("GET /done HTTP/1.1\r\n"));
+		assert!(!redirected.contains("Content-Type"));
+	}
+
+	#[test]
+	fn redirects_are_limited() {
+		let redirect = "HTTP/1.1 302 Found\r\nLocation: again\r\nContent-Length: 0\r\n\r\n";
+		let (address, _) = stand_in(vec![vec![redirect, redirect, redirect]]);
+
+		match Client::new().with_max_redirects(2).get(&address) {
+			Err(ClientError::TooManyRedirects(2)) => {},
+			other => panic!("expected too many redirects, got {:?}", other.map(|r| r.status_line.to_string()))
+		}
+	}
+
+	#[test]
+	fn redirects_are_returned_when_not_followed() {
+		let (address, _) = stand_in(vec![vec!["HTTP/1.1 302 Found\r\nLocation: /elsewhere\r\nContent-Length: 0\r\n\r\n"]]);
+		let response = Client::new().with_max_redirects(0).get(&address).unwrap();
+
+		assert_eq!(response.status_line.status_code(), StatusCode::found());
+		assert_eq!(response.headers.first("Location"), Some("/elsewhere"));
+	}
+
+	#[test]
+	fn locations_resolve_against_the_current_url() {
+		let target = Target::from_uri(&Uri::new("http://example.com:8080/a/b?q=1")).unwrap();
+
+		assert_eq!(target.resolve("c"), "http://example.com:8080/a/c");
+		assert_eq!(target.resolve("/c#frag"), "http://example.com:8080/c");
+		assert_eq!(target.resolve("//other.com/c"), "http://other.com/c");
+		assert_eq!(target.resolve("http://other.com:81/"), "http://other.com:81/");
+		assert_eq!(target.resolve("../c/./d?x"), "http://example.com:8080/c/d?x");
+		assert_eq!(target.resolve("/straße"), "http://example.com:8080/stra%C3%9Fe");
+
+		let literal = Target::from_uri(&Uri::new("http://[::1]:8080/a")).unwrap();
+		assert_eq!(literal.authority(), "[::1]:8080");
+		assert_eq!(literal.resolve("/b"), "http://[::1]:8080/b");
+	}
+
+	#[test]
+	fn iris_are_sent_as_uris() {
+		let request = request(AllowedMethod::GET, "http://Bücher.example/straße?q=ü").unwrap();
+		let target = Target::from_uri(&request.start_line.uri).unwrap();
+
+		assert_eq!(target.authority(), "xn--bcher-kva.example");
+		assert_eq!(target.path, "/stra%C3%9Fe?q=%C3%BC");
+	}
+
+	#[test]
+	fn only_http_urls_are_accepted() {
+		for url in &["https://example.com/", "example.com", "http://", "http://host:port/", "ftp://example.com", "http://a\u{fffd}b.com/"] {
+			match request(AllowedMethod::GET, url) {
+				Err(ClientError::InvalidUrl(_)) => {},
+				_ => panic!("{} should be rejected", url)
+			}
+		}
+	}
+}
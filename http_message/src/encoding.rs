@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use uri::percent;
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+pub fn to_hex(bytes: &[u8]) -> String {
+	let mut hex = String::with_capacity(bytes.len() * 2);
+	for b in bytes {
+		hex.push(HEX_DIGITS[(b >> 4) as usize] as char);
+		hex.push(HEX_DIGITS[(b & 0x0f) as usize] as char);
+	}
+	hex
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+	if !hex.len().is_multiple_of(2) {
+		return None;
+	}
+
+	let mut bytes = Vec::with_capacity(hex.len() / 2);
+	for pair in hex.as_bytes().chunks(2) {
+		let high = hex_value(pair[0])?;
+		let low = hex_value(pair[1])?;
+		bytes.push(high << 4 | low);
+	}
+	Some(bytes)
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+	match c {
+		b'0' ..= b'9' => Some(c - b'0'),
+		b'a' ..= b'f' => Some(c - b'a' + 10),
+		b'A' ..= b'F' => Some(c - b'A' + 10),
+		_ => None
+	}
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn to_base64(bytes: &[u8]) -> String {
+	let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+	for chunk in bytes.chunks(3) {
+		let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+		let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+		for i in 0..4 {
+			if i <= chunk.len() {
+				out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+			} else {
+				out.push('=');
+			}
+		}
+	}
+
+	out
+}
+
+pub fn from_base64(encoded: &str) -> Option<Vec<u8>> {
+	let encoded = encoded.trim_end_matches('=');
+	let mut bytes = Vec::with_capacity(encoded.len() * 3 / 4);
+
+	let mut acc: u32 = 0;
+	let mut bits = 0;
+	for c in encoded.bytes() {
+		let value = BASE64_ALPHABET.iter().position(|a| *a == c)? as u32;
+		acc = acc << 6 | value;
+		bits += 6;
+		if bits >= 8 {
+			bits -= 8;
+			bytes.push((acc >> bits & 0xff) as u8);
+		}
+	}
+
+	//a single leftover character can't encode a whole byte
+	if bits >= 6 {
+		return None;
+	}
+	Some(bytes)
+}
+
+/// Escapes a string for use inside a JSON string literal; the quotes aren't added.
+pub fn json_escape(raw: &str) -> String {
+	let mut escaped = String::with_capacity(raw.len());
+	for c in raw.chars() {
+		match c {
+			'"' => escaped.push_str("\\\""),
+			'\\' => escaped.push_str("\\\\"),
+			'\n' => escaped.push_str("\\n"),
+			'\r' => escaped.push_str("\\r"),
+			'\t' => escaped.push_str("\\t"),
+			c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+			c => escaped.push(c)
+		}
+	}
+	escaped
+}
+
+/// Parses an application/x-www-form-urlencoded body or query string.
+pub fn parse_form(raw: &str) -> HashMap<String, String> {
+	let mut fields = HashMap::new();
+
+	for pair in raw.split('&').filter(|p| !p.is_empty()) {
+		let (name, value) = match pair.find('=') {
+			Some(ix) => (&pair[..ix], &pair[ix + 1..]),
+			None => (pair, "")
+		};
+		fields.insert(percent::decode(&name.replace('+', " ")), percent::decode(&value.replace('+', " ")));
+	}
+
+	fields
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn base64_round_trips() {
+		for &(raw, encoded) in &[("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foobar", "Zm9vYmFy"), ("user:pass", "dXNlcjpwYXNz")] {
+			assert_eq!(to_base64(raw.as_bytes()), encoded);
+			assert_eq!(from_base64(encoded), Some(raw.as_bytes().to_vec()));
+		}
+		assert_eq!(from_base64("Zm9v!"), None);
+	}
+
+	#[test]
+	fn form_fields_decoded() {
+		let form = parse_form("username=luke&password=a+b%26c%3D&next=%2Fproperties&bad=%zz");
+		assert_eq!(form.get("username").map(|s| s.as_str()), Some("luke"));
+		assert_eq!(form.get("password").map(|s| s.as_str()), Some("a b&c="));
+		assert_eq!(form.get("next").map(|s| s.as_str()), Some("/properties"));
+		assert_eq!(form.get("bad").map(|s| s.as_str()), Some("%zz"));
+	}
+}
@@ -0,0 +1,10 @@
+//! HTTP/1.1 messages: the request and response model, the parser that reads them off a stream, and the encodings they're built from.
+//! Shared by the server and the client so both ends agree on the wire format.
+
+extern crate uri;
+extern crate ring;
+
+pub mod model;
+pub mod parser;
+pub mod limits;
+pub mod encoding;
@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+/// Caps on what a client may send, so one client can't tie up the server's memory or workers.
+#[derive(Clone, Debug)]
+pub struct Limits {
+	/// Longer request lines get a 414.
+	pub max_start_line_len: usize,
+	/// Longer header field lines get a 431.
+	pub max_header_line_len: usize,
+	/// More header field lines than this get a 431.
+	pub max_header_count: usize,
+	/// All header field lines together; more gets a 431.
+	pub max_header_bytes: usize,
+	/// Longer bodies get a 413, whether the length is declared up front or the body is chunked.
+	pub max_body_len: usize,
+	/// How long a client gets to send a whole request, however it trickles it in. Slower clients get a 408.
+	pub request_timeout: Duration,
+	/// How long a kept-alive connection may sit without starting another request.
+	pub idle_timeout: Duration,
+	/// Connections from one address past this are turned away with a 429.
+	pub max_connections_per_ip: usize
+}
+
+impl Default for Limits {
+	fn default() -> Limits {
+		Limits {
+			max_start_line_len: 8192,
+			max_header_line_len: 8000,
+			max_header_count: 100,
+			max_header_bytes: 64 * 1024,
+			max_body_len: 10 * 1024 * 1024,
+			request_timeout: Duration::from_secs(30),
+			idle_timeout: Duration::from_secs(60),
+			max_connections_per_ip: 32
+		}
+	}
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use encoding::{to_hex, from_hex};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None
+}
+
+impl Display for SameSite {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            SameSite::Strict => write!(f, "Strict"),
+            SameSite::Lax => write!(f, "Lax"),
+            SameSite::None => write!(f, "None")
+        }
+    }
+}
+
+/// A cookie to send in a Set-Cookie header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub path: Option<String>,
+    pub domain: Option<String>,
+    pub max_age: Option<i64>,
+    pub expires: Option<String>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: Option<SameSite>
+}
+
+impl Cookie {
+    pub fn new(name: &str, value: &str) -> Cookie {
+        Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None
+        }
+    }
+
+    /// A cookie which tells the browser to delete any cookie with this name and path.
+    pub fn removal(name: &str, path: Option<&str>) -> Cookie {
+        let mut cookie = Cookie::new(name, "");
+        cookie.path = path.map(String::from);
+        cookie.max_age = Some(0);
+        cookie.expires = Some(String::from("Thu, 01 Jan 1970 00:00:00 GMT"));
+        cookie
+    }
+
+    /// Parses the value of a Set-Cookie header. Unknown attributes are ignored, as RFC 6265 requires.
+    pub fn parse_set_cookie(raw: &str) -> Result<Cookie, String> {
+        let mut parts = raw.split(';');
+
+        let (name, value) = match parts.next().and_then(split_pair) {
+            Some(p) => p,
+            None => return Err(String::from("Set-Cookie header is missing the cookie name"))
+        };
+        if name.is_empty() {
+            return Err(String::from("Set-Cookie header is missing the cookie name"));
+        }
+
+        let mut cookie = Cookie::new(name, value);
+        for attribute in parts {
+            let (attr_name, attr_value) = match split_pair(attribute) {
+                Some(p) => p,
+                None => (attribute.trim(), "")
+            };
+
+            match attr_name.to_lowercase().as_ref() {
+                "path" => cookie.path = Some(attr_value.to_string()),
+                "domain" => cookie.domain = Some(attr_value.trim_start_matches('.').to_string()),
+                "max-age" => cookie.max_age = attr_value.parse::<i64>().ok(),
+                "expires" => cookie.expires = Some(attr_value.to_string()),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "samesite" => cookie.same_site = match attr_value.to_lowercase().as_ref() {
+                    "strict" => Some(SameSite::Strict),
+                    "lax" => Some(SameSite::Lax),
+                    "none" => Some(SameSite::None),
+                    _ => None
+                },
+                _ => ()
+            }
+        }
+
+        Ok(cookie)
+    }
+}
+
+// Formats the cookie as the value of a Set-Cookie header
+impl Display for Cookie {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}={}", self.name, self.value)?;
+
+        if let Some(ref domain) = self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(ref path) = self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(ref expires) = self.expires {
+            write!(f, "; Expires={}", expires)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age)?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn split_pair(pair: &str) -> Option<(&str, &str)> {
+    let ix = pair.find('=')?;
+    let value = pair[ix + 1..].trim();
+    let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    };
+    Some((pair[..ix].trim(), value))
+}
+
+
+/// The cookies a client sent in its Cookie header(s).
+pub struct CookieJar {
+    cookies: HashMap<String, String>
+}
+
+impl CookieJar {
+    pub fn parse(header_values: &[String]) -> CookieJar {
+        let mut cookies = HashMap::new();
+
+        for header in header_values {
+            for pair in header.split(';') {
+                if let Some((name, value)) = split_pair(pair) {
+                    if !name.is_empty() {
+                        //the first cookie is the one with the most specific path, so it wins
+                        cookies.entry(name.to_string()).or_insert_with(|| value.to_string());
+                    }
+                }
+            }
+        }
+
+        CookieJar { cookies }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.cookies.get(name).map(|v| v.as_str())
+    }
+
+    /// The value of a cookie signed with `CookieKey::sign`, if the signature is valid.
+    pub fn get_signed(&self, name: &str, key: &CookieKey) -> Option<String> {
+        self.get(name).and_then(|v| key.verify(name, v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+}
+
+
+/// Signs cookie values with HMAC-SHA256 so that clients cannot tamper with them.
+pub struct CookieKey {
+    key: hmac::Key
+}
+
+const SIGNATURE_DELIM: char = '.';
+
+impl CookieKey {
+    pub fn new(secret: &[u8]) -> CookieKey {
+        CookieKey {
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret)
+        }
+    }
+
+    /// A random key; cookies signed with it will not verify after the server restarts.
+    pub fn generate() -> Result<CookieKey, String> {
+        let mut secret = [0_u8; 32];
+        match SystemRandom::new().fill(&mut secret) {
+            Ok(()) => Ok(CookieKey::new(&secret)),
+            Err(_) => Err(String::from("Unable to generate a cookie signing key"))
+        }
+    }
+
+    /// Appends a signature over the cookie's name and value to its value.
+    pub fn sign(&self, cookie: &mut Cookie) {
+        let tag = hmac::sign(&self.key, signed_content(&cookie.name, &cookie.value).as_bytes());
+        cookie.value = format!("{}{}{}", cookie.value, SIGNATURE_DELIM, to_hex(tag.as_ref()));
+    }
+
+    pub fn verify(&self, name: &str, signed_value: &str) -> Option<String> {
+        let ix = signed_value.rfind(SIGNATURE_DELIM)?;
+        let (value, signature) = (&signed_value[..ix], &signed_value[ix + 1..]);
+        let signature = from_hex(signature)?;
+
+        match hmac::verify(&self.key, signed_content(name, value).as_bytes(), &signature) {
+            Ok(()) => Some(value.to_string()),
+            Err(_) => None
+        }
+    }
+}
+
+fn signed_content(name: &str, value: &str) -> String {
+    format!("{}={}", name, value)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_cookie_round_trips() {
+        let mut cookie = Cookie::new("session", "abc123");
+        cookie.path = Some(String::from("/"));
+        cookie.max_age = Some(3600);
+        cookie.http_only = true;
+        cookie.secure = true;
+        cookie.same_site = Some(SameSite::Lax);
+
+        let raw = cookie.to_string();
+        assert_eq!(raw, "session=abc123; Path=/; Max-Age=3600; Secure; HttpOnly; SameSite=Lax");
+        assert_eq!(Cookie::parse_set_cookie(&raw).unwrap(), cookie);
+    }
+
+    #[test]
+    fn cookie_header_parsed() {
+        let jar = CookieJar::parse(&[String::from("a=1; b=\"two\";c=3"), String::from("a=4")]);
+        assert_eq!(jar.get("a"), Some("1"));
+        assert_eq!(jar.get("b"), Some("two"));
+        assert_eq!(jar.get("c"), Some("3"));
+        assert_eq!(jar.len(), 3);
+    }
+
+    #[test]
+    fn signed_cookie_rejects_tampering() {
+        let key = CookieKey::new(b"secret");
+        let mut cookie = Cookie::new("user", "42");
+        key.sign(&mut cookie);
+
+        let jar = CookieJar::parse(&[format!("user={}", cookie.value)]);
+        assert_eq!(jar.get_signed("user", &key), Some(String::from("42")));
+
+        let tampered = cookie.value.replacen("42", "43", 1);
+        assert_eq!(key.verify("user", &tampered), None);
+        assert_eq!(key.verify("other", &cookie.value), None);
+        assert_eq!(CookieKey::new(b"other").verify("user", &cookie.value), None);
+    }
+}
@@ -0,0 +1,119 @@
+pub use self::request::*;
+pub use self::response::*;
+pub use self::cookie::*;
+
+pub(crate) mod request;
+pub(crate) mod response;
+pub(crate) mod cookie;
+
+//use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::collections::hash_map::Values;
+
+#[derive(Clone)]
+pub struct HeaderCollection {
+    headers: HashMap<HttpHeaderType, HttpHeader>,
+}
+
+impl HeaderCollection {
+    pub fn init_empty() -> HeaderCollection {
+        HeaderCollection {
+            headers: HashMap::new(),
+        }
+    }
+
+    pub fn get_or_add(&mut self, field_name: &str) -> &mut HttpHeader {
+        let header_type = HttpHeaderType::from_raw_name(field_name);
+
+        self.headers.entry(header_type).or_insert(HttpHeader::init(field_name))
+    }
+
+    pub fn get(&self, field_name: &str) -> Option<&HttpHeader> {
+        self.headers.get(&HttpHeaderType::from_raw_name(field_name))
+    }
+
+    /// The first value received for the header, if any.
+    pub fn first(&self, field_name: &str) -> Option<&str> {
+        self.get(field_name).and_then(|h| h.values().first()).map(|v| v.as_str())
+    }
+
+    pub fn remove(&mut self, field_name: &str) -> Option<HttpHeader> {
+        self.headers.remove(&HttpHeaderType::from_raw_name(field_name))
+    }
+
+    pub fn iter(&self) -> Values<'_, HttpHeaderType,HttpHeader> {
+        self.headers.values()
+    }
+}
+
+
+#[derive(Clone)]
+pub struct HttpHeader {
+    header_type: HttpHeaderType,
+    name: String,
+    values: Vec<String>
+}
+
+impl HttpHeader {
+    pub fn init(field_name: &str) -> HttpHeader {
+        HttpHeader {
+            header_type: HttpHeaderType::from_raw_name(field_name),
+            name: field_name.to_string(),
+            values: Vec::new()
+        }
+    }
+
+    pub fn header_type(&self) -> &HttpHeaderType {
+        &self.header_type
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// One entry per field line received, in order.
+    pub fn values(&self) -> &Vec<String> {
+        &self.values
+    }
+
+    pub fn add(&mut self, value: String) {
+        self.values.push(value);
+    }
+}
+//todo: create case insensitive string wrapper?
+
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum HttpHeaderType {
+    Host,
+    Connection,
+    Upgrade,
+    Http2Settings,
+    ContentLength,
+    ContentType,
+    TransferEncoding,
+    Cookie,
+    SetCookie,
+    //field names are case-insensitive, so extensions are keyed by their lowercase name
+    Extension(String)
+}
+
+
+impl HttpHeaderType {
+    pub fn from_raw_name(raw_name: &str) -> HttpHeaderType {
+        use self::HttpHeaderType::*;
+
+        match raw_name.to_uppercase().as_ref() {
+            "HOST" => Host,
+            "CONNECTION" => Connection,
+            "UPGRADE" => Upgrade,
+            "HTTP2-SETTINGS" => Http2Settings,
+            "CONTENT-LENGTH" => ContentLength,
+            "CONTENT-TYPE" => ContentType,
+            "TRANSFER-ENCODING" => TransferEncoding,
+            "COOKIE" => Cookie,
+            "SET-COOKIE" => SetCookie,
+            _ => Extension(raw_name.to_lowercase())
+        }
+    }
+}
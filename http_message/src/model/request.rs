@@ -0,0 +1,78 @@
+extern crate uri;
+
+use std;
+use uri::*;
+use model::StatusCode;
+use model::{HeaderCollection, CookieJar};
+
+//TODO: provide accessors and a constructor 
+#[derive(PartialEq, Debug, Clone)]
+pub struct StartLine {
+	pub method: AllowedMethod,
+	pub uri: Uri,
+	pub major_version: usize,
+	pub minor_version: usize
+}
+
+#[derive(Clone)]
+pub struct HttpRequest {
+	pub start_line: StartLine,
+	pub headers: HeaderCollection,
+	pub body: Vec<u8>
+}
+
+impl HttpRequest {
+	pub fn cookies(&self) -> CookieJar {
+		match self.headers.get("Cookie") {
+			Some(h) => CookieJar::parse(h.values()),
+			None => CookieJar::parse(&[])
+		}
+	}
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum AllowedMethod {
+	GET,
+	POST,
+	PUT,
+	PATCH,
+	DELETE,
+	OPTIONS
+}
+
+impl std::fmt::Display for AllowedMethod {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match *self {
+			AllowedMethod::GET => write!(f, "GET"),
+			AllowedMethod::POST => write!(f, "POST"),
+			AllowedMethod::PUT => write!(f, "PUT"),
+			AllowedMethod::PATCH => write!(f, "PATCH"),
+			AllowedMethod::DELETE => write!(f, "DELETE"),
+			AllowedMethod::OPTIONS => write!(f, "OPTIONS")
+		}
+	}
+}
+
+impl AllowedMethod {
+	pub fn from_utf8(raw_method: String) -> Result<AllowedMethod, (StatusCode, String)> {
+
+		if raw_method.len() > 7 {
+			return Err((StatusCode::not_implemented(), String::from("Method name too long")));
+		}
+
+		match raw_method.as_ref() {
+			"GET" =>  Ok(AllowedMethod::GET),
+			"POST" => Ok(AllowedMethod::POST),
+			"PUT" => Ok(AllowedMethod::PUT),
+			"PATCH" => Ok(AllowedMethod::PATCH),
+			"DELETE" => Ok(AllowedMethod::DELETE),
+			"OPTIONS" => Ok(AllowedMethod::OPTIONS),
+			_ => Err((StatusCode::internal_server_error(), String::from("Method not supported")))
+		}
+	}
+
+	/// Whether the method only reads, so a request with it changes nothing on the server.
+	pub fn is_safe(&self) -> bool {
+		matches!(*self, AllowedMethod::GET | AllowedMethod::OPTIONS)
+	}
+}
\ No newline at end of file
@@ -0,0 +1,153 @@
+pub(crate) mod status_code;
+
+pub use self::status_code::*;
+
+use std::fmt::{Display, Formatter, Error};
+use model::{HeaderCollection, HttpHeaderType, Cookie};
+
+#[derive(Debug, Clone)]
+pub struct StatusLine {
+    major_version: usize,
+    minor_version: usize,
+    status_code: StatusCode,
+    reason: String
+}
+
+impl StatusLine {
+    pub fn init(status_code: StatusCode, reason: String) -> StatusLine {
+        StatusLine {
+            major_version: 1,
+            minor_version: 1,
+            status_code,
+            reason
+        }
+    }
+
+    /// The same status line for another HTTP version, e.g. one read off a response.
+    pub fn with_version(mut self, major_version: usize, minor_version: usize) -> StatusLine {
+        self.major_version = major_version;
+        self.minor_version = minor_version;
+        self
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        self.status_code
+    }
+
+    pub fn version(&self) -> (usize, usize) {
+        (self.major_version, self.minor_version)
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl Display for StatusLine {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "HTTP/{}.{} {} {}", self.major_version, self.minor_version, self.status_code.get_code(), self.reason)
+    }
+}
+
+
+#[derive(Clone)]
+pub struct HttpResponse {
+    pub status_line: StatusLine,
+    pub headers: HeaderCollection,
+    pub body: Vec<u8>
+}
+
+impl HttpResponse {
+    pub fn new(status_code: StatusCode) -> HttpResponse {
+        HttpResponse {
+            status_line: StatusLine::init(status_code, String::from(status_code.reason_phrase())),
+            headers: HeaderCollection::init_empty(),
+            body: Vec::new()
+        }
+    }
+
+    pub fn from_status_line(status_line: StatusLine) -> HttpResponse {
+        HttpResponse {
+            status_line,
+            headers: HeaderCollection::init_empty(),
+            body: Vec::new()
+        }
+    }
+
+    /// CR and LF are dropped from the name and value, so a value taken from a request can't add lines of its own.
+    pub fn add_header(&mut self, field_name: &str, value: &str) {
+        let single_line = |s: &str| s.chars().filter(|&c| c != '\r' && c != '\n').collect::<String>();
+        self.headers.get_or_add(&single_line(field_name)).add(single_line(value));
+    }
+
+    pub fn with_header(mut self, field_name: &str, value: &str) -> HttpResponse {
+        self.add_header(field_name, value);
+        self
+    }
+
+    pub fn set_body<B: Into<Vec<u8>>>(&mut self, content_type: &str, body: B) {
+        self.headers.remove("Content-Type");
+        self.add_header("Content-Type", content_type);
+        self.body = body.into();
+    }
+
+    pub fn with_body<B: Into<Vec<u8>>>(mut self, content_type: &str, body: B) -> HttpResponse {
+        self.set_body(content_type, body);
+        self
+    }
+
+    pub fn set_cookie(&mut self, cookie: &Cookie) {
+        self.add_header("Set-Cookie", &cookie.to_string());
+    }
+
+    /// The status line and header fields as they are, for when the body is sent separately.
+    pub fn head_bytes(&self) -> Vec<u8> {
+        let mut head = format!("{}\r\n", self.status_line);
+        for header in self.headers.iter() {
+            for value in header.values() {
+                head.push_str(&format!("{}: {}\r\n", header.name(), value));
+            }
+        }
+        head.push_str("\r\n");
+        head.into_bytes()
+    }
+
+    /// The response as sent on the wire; Content-Length is set from the body, except on 1xx, 204 and 304 responses which don't have one.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut head = format!("{}\r\n", self.status_line);
+        for header in self.headers.iter() {
+            if *header.header_type() == HttpHeaderType::ContentLength {
+                continue;
+            }
+            for value in header.values() {
+                head.push_str(&format!("{}: {}\r\n", header.name(), value));
+            }
+        }
+        let code = self.status_line.status_code().get_code();
+        if code >= 200 && code != 204 && code != 304 {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("\r\n");
+
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_fields_stay_on_one_line() {
+        let response = HttpResponse::new(StatusCode::see_other())
+            .with_header("Location", "/\r\nSet-Cookie: session=stolen")
+            .with_header("X-Split\r\nSet-Cookie", "a");
+
+        assert_eq!(response.headers.first("Location"), Some("/Set-Cookie: session=stolen"));
+        assert!(response.headers.first("Set-Cookie").is_none());
+        let head = String::from_utf8(response.head_bytes()).unwrap();
+        assert_eq!(head.matches("\r\n").count(), 4);
+    }
+}
@@ -1,5 +1,6 @@
 use std::fmt::{Display, Formatter, Error};
 
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct StatusCode {
     code_class: StatusCodeClass, 
     flavor: u32
@@ -20,30 +21,88 @@ impl StatusCode {
     declare_code!(continue_code,100);
     declare_code!(switching_protocols,101);
 
+    declare_code!(ok, 200);
+    declare_code!(no_content, 204);
+
+    declare_code!(found, 302);
+    declare_code!(see_other, 303);
+    declare_code!(not_modified, 304);
+
     declare_code!(bad_request, 400);
+    declare_code!(unauthorized, 401);
+    declare_code!(forbidden, 403);
+    declare_code!(not_found, 404);
+    declare_code!(request_timeout, 408);
+    declare_code!(content_too_large, 413);
     declare_code!(uri_too_long, 414);
+    declare_code!(upgrade_required, 426);
+    declare_code!(too_many_requests, 429);
+    declare_code!(request_header_fields_too_large, 431);
 
     declare_code!(internal_server_error, 500);
     declare_code!(not_implemented, 501);
+    declare_code!(bad_gateway, 502);
+    declare_code!(service_unavailable, 503);
+    declare_code!(gateway_timeout, 504);
     declare_code!(http_version_not_supported, 505);
 
+    /// Any code from 100 to 599, e.g. one received from another server.
+    pub fn from_code(code: u32) -> Option<StatusCode> {
+        if !(100..=599).contains(&code) {
+            return None;
+        }
+
+        Some(StatusCode {
+            code_class: StatusCodeClass::from_code(code),
+            flavor: code % 100
+        })
+    }
+
     pub fn get_code(&self) -> u32 {
         let base_code = self.code_class.get_base_code();
         base_code + self.flavor
     }
 
-    pub fn is_cacheable(&self) -> bool {
+    pub fn reason_phrase(&self) -> &'static str {
         match self.get_code() {
+            100 => "Continue",
+            101 => "Switching Protocols",
+            200 => "OK",
+            204 => "No Content",
+            302 => "Found",
+            303 => "See Other",
+            304 => "Not Modified",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            408 => "Request Timeout",
+            413 => "Content Too Large",
+            414 => "URI Too Long",
+            426 => "Upgrade Required",
+            429 => "Too Many Requests",
+            431 => "Request Header Fields Too Large",
+            500 => "Internal Server Error",
+            501 => "Not Implemented",
+            502 => "Bad Gateway",
+            503 => "Service Unavailable",
+            504 => "Gateway Timeout",
+            505 => "HTTP Version Not Supported",
+            _ => ""
+        }
+    }
+
+    pub fn is_cacheable(&self) -> bool {
+        matches!(self.get_code(),
             200 | 203 | 204 | 206 |
             300 | 301 |
             404 | 405 | 410 | 414 |
-            501 => true,
-            _ => false
-        }
+            501)
     }
 }
 
 
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum StatusCodeClass {
     Informational,
     Successful,
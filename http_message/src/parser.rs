@@ -0,0 +1,840 @@
+extern crate uri;
+
+use std;
+use std::io;
+use std::io::{Read, Write};
+use uri::*;
+use model::*;
+use limits::Limits;
+
+/// Why copying a body failed: reading it in, or passing it on.
+pub enum CopyError {
+	Read(StatusLine),
+	Write(io::Error)
+}
+
+pub struct HttpRequestParser {
+	//bytes read from the stream but not yet parsed
+	buffer: Vec<u8>,
+	limits: Limits
+}
+
+impl Default for HttpRequestParser {
+	fn default() -> HttpRequestParser {
+		HttpRequestParser::new()
+	}
+}
+
+impl HttpRequestParser {
+	pub fn new() -> HttpRequestParser{
+		HttpRequestParser {
+			buffer: Vec::new(),
+			limits: Limits::default()
+		}
+	}
+
+	/// A parser that starts with bytes already read from the stream.
+	pub fn with_buffered(buffer: Vec<u8>) -> HttpRequestParser {
+		HttpRequestParser {
+			buffer,
+			limits: Limits::default()
+		}
+	}
+
+	pub fn with_limits(mut self, limits: Limits) -> HttpRequestParser {
+		self.limits = limits;
+		self
+	}
+
+	/// Bytes read from the stream but not yet parsed, e.g. a pipelined request.
+	pub fn buffered(&self) -> &[u8] {
+		&self.buffer
+	}
+
+	/// Hands over bytes read past the last request, e.g. frames a client sent right after a websocket handshake.
+	pub fn take_buffered(&mut self) -> Vec<u8> {
+		::std::mem::take(&mut self.buffer)
+	}
+
+	/// Whether the stream starts with `prefix`. Only reads as much as it takes to tell; nothing is consumed.
+	pub fn starts_with<R: Read>(&mut self, stream: &mut R, prefix: &[u8]) -> bool {
+		loop {
+			let len = self.buffer.len().min(prefix.len());
+			if self.buffer[..len] != prefix[..len] {
+				return false;
+			}
+			if len == prefix.len() {
+				return true;
+			}
+			if self.fill(stream).is_err() {
+				return false;
+			}
+		}
+	}
+
+	pub fn get_start_line<R: Read>(&mut self, stream: &mut R) -> std::result::Result<StartLine, StatusLine> {
+		let mut line = Vec::new();
+
+		//servers should ignore empty lines received before the request line
+		while line.is_empty() {
+			let max_len = self.limits.max_start_line_len;
+			line = self.read_line(stream, max_len, || {
+				StatusLine::init(StatusCode::uri_too_long(), String::from("Request target longer than max allowed length"))
+			})?;
+		}
+
+		let mut method_term_ix = line.len();
+		for ix in 0..line.len() {
+			if b' ' == *(line.get(ix).unwrap()) {
+				method_term_ix = ix;
+				break;
+			}
+		}
+
+		let mut vers_start_ix = method_term_ix;
+		for ix in (method_term_ix..line.len() - 1).rev() {
+			if b' ' == *(line.get(ix).unwrap()) {
+				vers_start_ix = ix;
+				break;
+			}
+		}
+
+		if vers_start_ix <= method_term_ix {
+			return Err(StatusLine::init(StatusCode::bad_request(), String::from("Malformed request line")))
+		}
+
+		let version = String::from_utf8_lossy(&line[vers_start_ix+1..]).into_owned();
+		let version_digits = (version.get(5..6).and_then(|v| v.parse::<usize>().ok()), version.get(7..8).and_then(|v| v.parse::<usize>().ok()));
+		let (major_version, minor_version) = match version_digits {
+			(Some(major), Some(minor)) if version.len() == 8 && version.starts_with("HTTP/") => (major, minor),
+			_ => return Err(StatusLine::init(StatusCode::internal_server_error(), String::from("Invalid HTTP Version value"))) //Check on what IIS does
+		};
+
+		let method = String::from_utf8_lossy(&line[0..method_term_ix]).into_owned();
+		let method = match AllowedMethod::from_utf8(method) {
+			Ok(a) => a,
+			Err((c,s)) => return Err(StatusLine::init(c,s))
+		};
+
+		let uri = match std::str::from_utf8(&line[method_term_ix+1..vers_start_ix]) {
+			Ok(u) => u,
+			Err(_) => return Err(StatusLine::init(StatusCode::bad_request(), String::from("Request target is not valid UTF-8")))
+		};
+		//`OPTIONS *` asks about the server as a whole, which nothing here answers
+		if uri == "*" {
+			return Err(StatusLine::init(StatusCode::bad_request(), String::from("Asterisk-form request target not supported")))
+		}
+		//checked where it lies in the line, and only copied once it's known to be good
+		let uri = match UriRef::parse(uri) {
+			Ok(u) => u,
+			Err(e) => return Err(StatusLine::init(StatusCode::bad_request(), format!("Invalid request target: {}", e)))
+		};
+		//origin-form, e.g. `/where?q`, or absolute-form, e.g. `http://example.com/where?q`, as sent to proxies
+		if uri.scheme().is_none() && !uri.path().starts_with('/') {
+			return Err(StatusLine::init(StatusCode::bad_request(), String::from("Request target must be an absolute path or URI")))
+		}
+		let uri = uri.to_uri();
+
+		Ok(StartLine {
+			method,
+			uri,
+			major_version,
+			minor_version
+		})
+		//501 returned if the method is longer than any supported
+		//414 returned if request-target is longer than max allowed uri length
+		//  recommended that recipients support request-lines of 8000 octects
+		//		- 4000 chars in UTF-16 and max 8000 in utf-8 and 2000 in utf-32
+	}
+
+	pub fn get_request_headers<R: Read>(&mut self, stream: &mut R) -> Result<HeaderCollection,StatusLine> {
+		let mut headers = HeaderCollection::init_empty();
+		let mut count = 0;
+		let mut total_len = 0;
+
+		loop {
+			let max_len = self.limits.max_header_line_len;
+			let line = self.read_line(stream, max_len, header_field_too_long)?;
+
+			if line.is_empty() {
+				break;
+			}
+
+			count += 1;
+			total_len += line.len();
+			if count > self.limits.max_header_count || total_len > self.limits.max_header_bytes {
+				return Err(StatusLine::init(StatusCode::request_header_fields_too_large(), String::from("Too many header fields")));
+			}
+
+			let name_end = match line.iter().position(|c| b':' == *c) {
+				Some(ix) => ix,
+				None => return Err(StatusLine::init(StatusCode::bad_request(), String::from("header field is missing ':'")))
+			};
+
+			let (header_name, header_val) = line.split_at(name_end);
+
+			let field_name = match String::from_utf8(header_name.to_vec()) {
+				Ok(ref n) if !n.is_empty() && !n.ends_with(|c: char| c.is_whitespace()) => n.clone(),
+				_ => return Err(StatusLine::init(StatusCode::bad_request(), String::from("invalid header field name")))
+			};
+
+			let val = match String::from_utf8(header_val[1..].to_vec()) {
+				Ok(v) => v,
+				Err(_) => return Err(StatusLine::init(StatusCode::bad_request(), String::from("invalid header field value")))
+			};
+
+			headers.get_or_add(&field_name).add(val.trim_matches(|c| c == ' ' || c == '\t').to_string());
+		}
+
+		Ok(headers)
+	}
+
+	pub fn get_request_body<R: Read>(&mut self, stream: &mut R, headers: &HeaderCollection) -> Result<Vec<u8>, StatusLine> {
+		if is_chunked(headers)? {
+			return self.get_chunked_body(stream);
+		}
+
+		let length = self.content_length(headers)?;
+		self.read_exact(stream, length)
+	}
+
+	fn get_chunked_body<R: Read>(&mut self, stream: &mut R) -> Result<Vec<u8>, StatusLine> {
+		let mut body = Vec::new();
+		self.read_chunked(stream, |piece| {
+			body.extend_from_slice(piece);
+			Ok(())
+		})?;
+		Ok(body)
+	}
+
+	/// Parses a response's status line, e.g. `HTTP/1.1 200 OK`, for when this end is the client.
+	pub fn get_status_line<R: Read>(&mut self, stream: &mut R) -> Result<StatusLine, StatusLine> {
+		let max_len = self.limits.max_start_line_len;
+		let line = self.read_line(stream, max_len, || {
+			StatusLine::init(StatusCode::bad_request(), String::from("Status line too long"))
+		})?;
+		let line = String::from_utf8_lossy(&line);
+
+		let mut parts = line.splitn(3, ' ');
+		let minor_version = match parts.next() {
+			Some("HTTP/1.0") => 0,
+			Some("HTTP/1.1") => 1,
+			_ => return Err(StatusLine::init(StatusCode::bad_request(), String::from("Malformed status line")))
+		};
+		let code = parts.next().and_then(|c| c.parse::<u32>().ok()).and_then(StatusCode::from_code);
+		match code {
+			Some(code) => Ok(StatusLine::init(code, parts.next().unwrap_or("").to_string()).with_version(1, minor_version)),
+			None => Err(StatusLine::init(StatusCode::bad_request(), String::from("Malformed status line")))
+		}
+	}
+
+	/// Copies a message body to `out` as it arrives instead of collecting it; a chunked body stays chunked. Returns the body's length.
+	pub fn copy_body<R: Read, W: Write>(&mut self, stream: &mut R, headers: &HeaderCollection, out: &mut W) -> Result<usize, CopyError> {
+		let mut write_error = None;
+		let result = {
+			let mut write = |bytes: &[u8]| out.write_all(bytes).map_err(|e| {
+				write_error = Some(e);
+				StatusLine::init(StatusCode::bad_gateway(), String::from("Failed to pass the body on"))
+			});
+
+			//framed the same way as bodies read in full, so other codings get the same 501
+			match is_chunked(headers) {
+				Ok(true) => self.read_chunked(stream, |piece| write(format!("{:x}\r\n", piece.len()).as_bytes())
+						.and_then(|_| write(piece))
+						.and_then(|_| write(b"\r\n")))
+					.and_then(|len| write(b"0\r\n\r\n").map(|_| len)),
+				Ok(false) => match self.content_length(headers) {
+					Ok(length) => self.read_sized(stream, length, &mut write).map(|_| length),
+					Err(status) => Err(status)
+				},
+				Err(status) => Err(status)
+			}
+		};
+
+		match (result, write_error) {
+			(_, Some(e)) => Err(CopyError::Write(e)),
+			(Ok(len), None) => Ok(len),
+			(Err(status), None) => Err(CopyError::Read(status))
+		}
+	}
+
+	/// Copies everything up to the end of the stream, for responses that end by closing the connection.
+	/// Stops with a 413 once more than the body limit has come.
+	pub fn copy_until_closed<R: Read, W: Write>(&mut self, stream: &mut R, out: &mut W) -> Result<usize, CopyError> {
+		let buffered = self.take_buffered();
+		if buffered.len() > self.limits.max_body_len {
+			return Err(CopyError::Read(body_too_large()));
+		}
+		out.write_all(&buffered).map_err(CopyError::Write)?;
+
+		let mut len = buffered.len();
+		let mut buf = [0_u8; 8192];
+		loop {
+			match stream.read(&mut buf) {
+				Ok(0) => return Ok(len),
+				Ok(n) => {
+					if n > self.limits.max_body_len - len {
+						return Err(CopyError::Read(body_too_large()));
+					}
+					out.write_all(&buf[..n]).map_err(CopyError::Write)?;
+					len += n;
+				},
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+				Err(_) => return Err(CopyError::Read(StatusLine::init(StatusCode::bad_request(), String::from("Failed to read the body"))))
+			}
+		}
+	}
+
+	fn content_length(&self, headers: &HeaderCollection) -> Result<usize, StatusLine> {
+		let length = match headers.first("Content-Length") {
+			None => return Ok(0),
+			Some(l) => match l.parse::<usize>() {
+				Ok(l) => l,
+				Err(_) => return Err(StatusLine::init(StatusCode::bad_request(), String::from("invalid Content-Length")))
+			}
+		};
+		if length > self.limits.max_body_len {
+			return Err(body_too_large());
+		}
+		Ok(length)
+	}
+
+	// Hands `length` bytes of body to `sink` in whatever pieces they arrive in
+	fn read_sized<R, F>(&mut self, stream: &mut R, length: usize, mut sink: F) -> Result<(), StatusLine>
+		where R: Read, F: FnMut(&[u8]) -> Result<(), StatusLine> {
+		let mut remaining = length;
+		while remaining > 0 {
+			if self.buffer.is_empty() {
+				self.fill(stream)?;
+			}
+			let n = remaining.min(self.buffer.len());
+			sink(&self.buffer[..n])?;
+			self.buffer.drain(..n);
+			remaining -= n;
+		}
+		Ok(())
+	}
+
+	// Decodes a chunked body, handing the data to `sink` as it arrives. Returns the decoded length.
+	fn read_chunked<R, F>(&mut self, stream: &mut R, mut sink: F) -> Result<usize, StatusLine>
+		where R: Read, F: FnMut(&[u8]) -> Result<(), StatusLine> {
+		let mut total = 0;
+
+		loop {
+			let max_len = self.limits.max_header_line_len;
+			let size_line = self.read_line(stream, max_len, chunk_size_line_too_long)?;
+
+			let size = chunk_size(&size_line)?;
+			if size > self.limits.max_body_len.saturating_sub(total) {
+				return Err(body_too_large());
+			}
+
+			if size == 0 {
+				//trailer fields are read and discarded
+				self.get_request_headers(stream)?;
+				return Ok(total);
+			}
+
+			self.read_sized(stream, size, &mut sink)?;
+			total += size;
+			let terminator = self.read_line(stream, 0, chunk_data_too_long)?;
+			if !terminator.is_empty() {
+				return Err(chunk_data_too_long());
+			}
+		}
+	}
+
+	// Returns the next line without its line terminator, reading from the stream as needed
+	fn read_line<R: Read, F: Fn() -> StatusLine>(&mut self, stream: &mut R, max_len: usize, too_long: F) -> Result<Vec<u8>, StatusLine> {
+		let mut searched = 0;
+
+		loop {
+			if let Some(ix) = self.buffer[searched..].iter().position(|c| b'\n' == *c) {
+				let end = searched + ix;
+				if end > max_len + 1 {
+					return Err(too_long());
+				}
+				let mut line: Vec<u8> = self.buffer.drain(..end + 1).collect();
+				line.pop();
+				if line.last() == Some(&b'\r') {
+					line.pop();
+				}
+				return Ok(line);
+			}
+
+			searched = self.buffer.len();
+			if searched > max_len + 1 {
+				return Err(too_long());
+			}
+
+			self.fill(stream)?;
+		}
+	}
+
+	fn read_exact<R: Read>(&mut self, stream: &mut R, length: usize) -> Result<Vec<u8>, StatusLine> {
+		while self.buffer.len() < length {
+			self.fill(stream)?;
+		}
+
+		Ok(self.buffer.drain(..length).collect())
+	}
+
+	fn fill<R: Read>(&mut self, stream: &mut R) -> Result<(), StatusLine> {
+		let local_buf: &mut [u8] = &mut [0_u8; 512];
+
+		match stream.read(local_buf) {
+			Ok(0) => Err(StatusLine::init(StatusCode::bad_request(), String::from("Connection closed before the request was complete"))),
+			Ok(n) => {
+				self.buffer.extend_from_slice(&local_buf[..n]);
+				Ok(())
+			},
+			Err(ref e) if e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock => {
+				Err(StatusLine::init(StatusCode::request_timeout(), String::from("Request Timeout")))
+			},
+			Err(_) => Err(StatusLine::init(StatusCode::bad_request(), String::from("Failed to read the request")))
+		}
+	}
+}
+
+fn body_too_large() -> StatusLine {
+	StatusLine::init(StatusCode::content_too_large(), String::from("Content Too Large"))
+}
+
+// Whether the body is chunked; no other transfer coding is supported
+fn is_chunked(headers: &HeaderCollection) -> Result<bool, StatusLine> {
+	match headers.first("Transfer-Encoding") {
+		Some(encoding) if encoding.eq_ignore_ascii_case("chunked") => Ok(true),
+		Some(_) => Err(StatusLine::init(StatusCode::not_implemented(), String::from("Transfer-Encoding not supported"))),
+		None => Ok(false)
+	}
+}
+
+// The size on a chunk size line; chunk extensions follow a ';' and are ignored
+fn chunk_size(line: &[u8]) -> Result<usize, StatusLine> {
+	let size = String::from_utf8_lossy(line);
+	let size = size.split(';').next().unwrap_or("").trim();
+	usize::from_str_radix(size, 16).map_err(|_| StatusLine::init(StatusCode::bad_request(), String::from("invalid chunk size")))
+}
+
+fn chunk_size_line_too_long() -> StatusLine {
+	StatusLine::init(StatusCode::bad_request(), String::from("chunk size line too long"))
+}
+
+fn chunk_data_too_long() -> StatusLine {
+	StatusLine::init(StatusCode::bad_request(), String::from("chunk data longer than chunk size"))
+}
+
+fn header_field_too_long() -> StatusLine {
+	StatusLine::init(StatusCode::request_header_fields_too_large(), String::from("Header field too long"))
+}
+
+// The most a start line and header section within the limits can take, line breaks included. Once more than this
+// is buffered, parsing them fails whether or not their end has arrived.
+fn max_head_len(limits: &Limits) -> usize {
+	limits.max_start_line_len + limits.max_header_bytes + limits.max_header_line_len + 2 * (limits.max_header_count + 3)
+}
+
+
+/// How far a request fed to an `IncrementalParser` has got.
+pub enum Progress {
+	//boxed, since a request is much larger than the other variants
+	Complete(Box<HttpRequest>),
+	/// More bytes are needed.
+	Incomplete,
+	Invalid(StatusLine)
+}
+
+// Stands in for a non-blocking socket with nothing left to read, noting whether the parser asked for more
+struct Starved {
+	hit: bool
+}
+
+impl Read for Starved {
+	fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+		self.hit = true;
+		Err(io::Error::new(io::ErrorKind::WouldBlock, "no more buffered data"))
+	}
+}
+
+/// Parses requests from bytes as they arrive, for sockets that can't block until the rest of a request shows up.
+/// The start line and header section are only parsed once they're all in, a body of known length once it's all in,
+/// and a chunked body is decoded as it comes, so however the request trickles in, each byte is only gone over about once.
+pub struct IncrementalParser {
+	parser: HttpRequestParser,
+	start_line: Option<StartLine>,
+	headers: Option<HeaderCollection>,
+	body: Option<BodyProgress>,
+	//how much of the buffer is known not to finish the line or header section in progress
+	searched: usize
+}
+
+// How far the body of the request in progress has got
+enum BodyProgress {
+	/// This many bytes, taken in one go once they're all buffered.
+	Sized(usize),
+	Chunked(Vec<u8>, ChunkState)
+}
+
+enum ChunkState {
+	Size,
+	/// This much of the chunk's data is still to come.
+	Data(usize),
+	/// The line break after a chunk's data.
+	DataEnd,
+	/// Trailer fields, which are dropped, with how many have been read.
+	Trailers(usize)
+}
+
+impl IncrementalParser {
+	pub fn new(limits: Limits) -> IncrementalParser {
+		IncrementalParser {
+			parser: HttpRequestParser::new().with_limits(limits),
+			start_line: None,
+			headers: None,
+			body: None,
+			searched: 0
+		}
+	}
+
+	pub fn feed(&mut self, data: &[u8]) {
+		self.parser.buffer.extend_from_slice(data);
+	}
+
+	/// Bytes fed but not yet part of a complete request.
+	pub fn buffered(&self) -> &[u8] {
+		&self.parser.buffer
+	}
+
+	/// Hands over the unparsed bytes, e.g. when the connection switches protocols.
+	pub fn take_buffered(&mut self) -> Vec<u8> {
+		self.parser.take_buffered()
+	}
+
+	/// Whether the request in progress has got past its start line.
+	pub fn has_start_line(&self) -> bool {
+		self.start_line.is_some()
+	}
+
+	/// Whether enough is buffered to get further with the request in progress: to finish it, to find it's over the limits,
+	/// or, for a chunked body, to decode a good part of it. Reading more before then only piles it up here.
+	pub fn has_enough(&self) -> bool {
+		let limits = &self.parser.limits;
+		let enough = match self.body {
+			None => max_head_len(limits) + 1,
+			Some(BodyProgress::Sized(length)) => length,
+			Some(BodyProgress::Chunked(ref decoded, _)) => limits.max_body_len.saturating_sub(decoded.len()) + max_head_len(limits)
+		};
+		self.parser.buffer.len() >= enough
+	}
+
+	/// The next complete request from the bytes fed so far. Bytes after it stay buffered for the next call.
+	pub fn next_request(&mut self) -> Progress {
+		if self.start_line.is_none() {
+			if !self.line_ended() && self.parser.buffer.len() <= self.parser.limits.max_start_line_len + 1 {
+				return Progress::Incomplete;
+			}
+			match self.attempt(|parser, stream| parser.get_start_line(stream)) {
+				Ok(Some(start_line)) => self.start_line = Some(start_line),
+				Ok(None) => return Progress::Incomplete,
+				Err(status) => return Progress::Invalid(status)
+			}
+			self.searched = 0;
+		}
+
+		if self.headers.is_none() {
+			if !self.head_ended() && self.parser.buffer.len() <= max_head_len(&self.parser.limits) {
+				return Progress::Incomplete;
+			}
+			let headers = match self.attempt(|parser, stream| parser.get_request_headers(stream)) {
+				Ok(Some(headers)) => headers,
+				Ok(None) => return Progress::Incomplete,
+				Err(status) => return Progress::Invalid(status)
+			};
+			self.searched = 0;
+
+			let body = match is_chunked(&headers) {
+				Ok(true) => Ok(BodyProgress::Chunked(Vec::new(), ChunkState::Size)),
+				Ok(false) => self.parser.content_length(&headers).map(BodyProgress::Sized),
+				Err(status) => Err(status)
+			};
+			match body {
+				Ok(body) => self.body = Some(body),
+				Err(status) => return Progress::Invalid(status)
+			}
+			self.headers = Some(headers);
+		}
+
+		let body = match self.read_body() {
+			Ok(Some(body)) => body,
+			Ok(None) => return Progress::Incomplete,
+			Err(status) => return Progress::Invalid(status)
+		};
+
+		self.body = None;
+		Progress::Complete(Box::new(HttpRequest {
+			start_line: self.start_line.take().unwrap(),
+			headers: self.headers.take().unwrap(),
+			body
+		}))
+	}
+
+	// Whether a line break has arrived, looking only at what hasn't been searched
+	fn line_ended(&mut self) -> bool {
+		let buffer = &self.parser.buffer;
+		if buffer[self.searched..].contains(&b'\n') {
+			return true;
+		}
+		self.searched = buffer.len();
+		false
+	}
+
+	// Whether the empty line that ends a header section has arrived, looking only at what hasn't been searched
+	fn head_ended(&mut self) -> bool {
+		let buffer = &self.parser.buffer;
+		//always at the start of a line
+		let mut line = self.searched;
+		while let Some(ix) = buffer[line..].iter().position(|c| b'\n' == *c) {
+			if ix == 0 || (ix == 1 && buffer[line] == b'\r') {
+				return true;
+			}
+			line += ix + 1;
+		}
+		self.searched = line;
+		false
+	}
+
+	// Takes in as much of the body as has arrived, returning it once it's complete
+	fn read_body(&mut self) -> Result<Option<Vec<u8>>, StatusLine> {
+		let buffer = &mut self.parser.buffer;
+		match self.body {
+			Some(BodyProgress::Sized(length)) if buffer.len() < length => Ok(None),
+			Some(BodyProgress::Sized(length)) => Ok(Some(buffer.drain(..length).collect())),
+			Some(BodyProgress::Chunked(ref mut decoded, ref mut state)) => {
+				//what's decoded is only taken off the buffer at the end, so it's shifted down once however many chunks there are
+				let mut pos = 0;
+				let result = decode_chunks(buffer, &mut pos, &self.parser.limits, decoded, state);
+				buffer.drain(..pos);
+				result
+			},
+			None => Ok(None)
+		}
+	}
+
+	// Runs one step of the parser over the buffered bytes, putting them back if it ran out before finishing
+	fn attempt<T, F>(&mut self, step: F) -> Result<Option<T>, StatusLine>
+		where F: FnOnce(&mut HttpRequestParser, &mut Starved) -> Result<T, StatusLine> {
+		let mut stream = Starved { hit: false };
+		let snapshot = self.parser.buffer.clone();
+
+		match step(&mut self.parser, &mut stream) {
+			Ok(value) => Ok(Some(value)),
+			Err(_) if stream.hit => {
+				self.parser.buffer = snapshot;
+				//what's there has all been looked at; it's waiting on a line break
+				self.searched = self.parser.buffer.len();
+				Ok(None)
+			},
+			Err(status) => {
+				self.searched = 0;
+				Err(status)
+			}
+		}
+	}
+}
+
+// Decodes chunks from `buffer`, starting at `pos` and moving it past what's done with. The body once the last chunk is in.
+fn decode_chunks(buffer: &[u8], pos: &mut usize, limits: &Limits, decoded: &mut Vec<u8>, state: &mut ChunkState) -> Result<Option<Vec<u8>>, StatusLine> {
+	let max_len = limits.max_header_line_len;
+	loop {
+		match *state {
+			ChunkState::Size => {
+				let size = match line_at(buffer, pos, max_len, chunk_size_line_too_long)? {
+					Some(line) => chunk_size(line)?,
+					None => return Ok(None)
+				};
+				if size > limits.max_body_len.saturating_sub(decoded.len()) {
+					return Err(body_too_large());
+				}
+				*state = if size == 0 { ChunkState::Trailers(0) } else { ChunkState::Data(size) };
+			},
+			ChunkState::Data(remaining) => {
+				let n = remaining.min(buffer.len() - *pos);
+				if n == 0 {
+					return Ok(None);
+				}
+				decoded.extend_from_slice(&buffer[*pos..*pos + n]);
+				*pos += n;
+				*state = if n == remaining { ChunkState::DataEnd } else { ChunkState::Data(remaining - n) };
+			},
+			ChunkState::DataEnd => match line_at(buffer, pos, 0, chunk_data_too_long)? {
+				Some([]) => *state = ChunkState::Size,
+				Some(_) => return Err(chunk_data_too_long()),
+				None => return Ok(None)
+			},
+			//trailer fields are read and dropped
+			ChunkState::Trailers(count) => match line_at(buffer, pos, max_len, header_field_too_long)? {
+				Some([]) => return Ok(Some(::std::mem::take(decoded))),
+				Some(_) if count >= limits.max_header_count => {
+					return Err(StatusLine::init(StatusCode::request_header_fields_too_large(), String::from("Too many header fields")));
+				},
+				Some(_) => *state = ChunkState::Trailers(count + 1),
+				None => return Ok(None)
+			}
+		}
+	}
+}
+
+// The line at `pos` without its line terminator, moving `pos` past it, as `read_line` would. None if it hasn't all arrived.
+fn line_at<'a>(buffer: &'a [u8], pos: &mut usize, max_len: usize, too_long: fn() -> StatusLine) -> Result<Option<&'a [u8]>, StatusLine> {
+	let rest = &buffer[*pos..];
+	let end = match rest.iter().position(|c| b'\n' == *c) {
+		Some(end) if end > max_len + 1 => return Err(too_long()),
+		Some(end) => end,
+		None if rest.len() > max_len + 1 => return Err(too_long()),
+		None => return Ok(None)
+	};
+	*pos += end + 1;
+
+	let line = &rest[..end];
+	Ok(Some(line.strip_suffix(b"\r").unwrap_or(line)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+
+	fn parse(raw: &str) -> Result<(StartLine, HeaderCollection, Vec<u8>), StatusLine> {
+		parse_with(raw, Limits::default())
+	}
+
+	fn parse_with(raw: &str, limits: Limits) -> Result<(StartLine, HeaderCollection, Vec<u8>), StatusLine> {
+		let mut stream = Cursor::new(raw.as_bytes().to_vec());
+		let mut parser = HttpRequestParser::new().with_limits(limits);
+		let start_line = parser.get_start_line(&mut stream)?;
+		let headers = parser.get_request_headers(&mut stream)?;
+		let body = parser.get_request_body(&mut stream, &headers)?;
+		Ok((start_line, headers, body))
+	}
+
+	#[test]
+	fn request_with_headers_and_body() {
+		let (start_line, headers, body) = parse("POST /test/post HTTP/1.1\r\nHost: localhost\r\nCookie: a=1\r\ncookie: b=2\r\nContent-Length: 4\r\n\r\nbody").unwrap();
+		assert_eq!(start_line.method, AllowedMethod::POST);
+		assert_eq!(start_line.minor_version, 1);
+		assert_eq!(headers.first("host"), Some("localhost"));
+		assert_eq!(headers.get("COOKIE").unwrap().values(), &vec!(String::from("a=1"), String::from("b=2")));
+		assert_eq!(body, b"body".to_vec());
+	}
+
+	#[test]
+	fn chunked_body_is_decoded() {
+		let (_, _, body) = parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWiki\r\n5;ext=1\r\npedia\r\n0\r\n\r\n").unwrap();
+		assert_eq!(body, b"Wikipedia".to_vec());
+	}
+
+	#[test]
+	fn copied_bodies_are_framed_like_read_ones() {
+		let mut headers = HeaderCollection::init_empty();
+		headers.get_or_add("Transfer-Encoding").add(String::from("gzip"));
+		let mut out = Vec::new();
+		match HttpRequestParser::new().copy_body(&mut Cursor::new(b"data".to_vec()), &headers, &mut out) {
+			Err(CopyError::Read(status)) => assert_eq!(status.status_code(), StatusCode::not_implemented()),
+			_ => panic!("expected a 501")
+		}
+		assert!(out.is_empty());
+	}
+
+	#[test]
+	fn bodies_up_to_close_are_held_to_the_limit() {
+		let mut parser = HttpRequestParser::new().with_limits(Limits { max_body_len: 4, ..Limits::default() });
+		let mut out = Vec::new();
+		assert_eq!(parser.copy_until_closed(&mut Cursor::new(b"Wiki".to_vec()), &mut out).ok(), Some(4));
+		assert_eq!(out, b"Wiki".to_vec());
+
+		let too_long = parser.copy_until_closed(&mut Cursor::new(b"Wikipedia".to_vec()), &mut Vec::new());
+		assert!(matches!(too_long, Err(CopyError::Read(ref s)) if s.status_code() == StatusCode::content_too_large()));
+	}
+
+	#[test]
+	fn incremental_parse_resumes_across_feeds() {
+		let raw = b"POST /test/post HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\nGET / HTTP/1.1\r\n\r\n";
+		let mut parser = IncrementalParser::new(Limits::default());
+
+		let mut requests = Vec::new();
+		for b in raw.iter() {
+			parser.feed(&[*b]);
+			while let Progress::Complete(request) = parser.next_request() {
+				requests.push(*request);
+			}
+		}
+
+		assert_eq!(requests.len(), 2);
+		assert_eq!(requests[0].headers.first("host"), Some("localhost"));
+		assert_eq!(requests[0].body, b"Wikipedia".to_vec());
+		assert_eq!(requests[1].start_line.method, AllowedMethod::GET);
+		assert!(parser.buffered().is_empty() && !parser.has_start_line());
+
+		parser.feed(b"BREW / HTTP/1.1\r\n");
+		assert!(matches!(parser.next_request(), Progress::Invalid(_)));
+	}
+
+	#[test]
+	fn incremental_parse_says_when_it_has_enough() {
+		let limits = Limits { max_header_count: 2, max_body_len: 8, ..Limits::default() };
+		let mut parser = IncrementalParser::new(limits.clone());
+
+		parser.feed(b"POST / HTTP/1.1\r\nContent-Length: 6\r\n\r\nabc");
+		assert!(matches!(parser.next_request(), Progress::Incomplete));
+		assert!(!parser.has_enough());
+		parser.feed(b"def");
+		assert!(parser.has_enough());
+		assert!(matches!(parser.next_request(), Progress::Complete(ref r) if r.body == b"abcdef".to_vec()));
+
+		parser.feed(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\n123");
+		assert!(matches!(parser.next_request(), Progress::Incomplete));
+		parser.feed(b"45\r\n4\r\n");
+		assert!(matches!(parser.next_request(), Progress::Invalid(ref s) if s.status_code() == StatusCode::content_too_large()));
+
+		//a header section that can't end within the limits is turned away before its end arrives
+		let mut parser = IncrementalParser::new(limits);
+		parser.feed(b"GET / HTTP/1.1\r\n");
+		while !parser.has_enough() {
+			assert!(matches!(parser.next_request(), Progress::Incomplete));
+			parser.feed(b"X-Field: value\r\n");
+		}
+		assert!(matches!(parser.next_request(), Progress::Invalid(ref s) if s.status_code() == StatusCode::request_header_fields_too_large()));
+	}
+
+	#[test]
+	fn oversized_requests_get_matching_status() {
+		let limits = Limits { max_start_line_len: 20, max_header_count: 2, max_header_line_len: 30, max_body_len: 8, ..Limits::default() };
+		let status = |raw: &str| parse_with(raw, limits.clone()).err().map(|e| e.status_code().get_code());
+
+		assert_eq!(status("GET /a-very-long-path-indeed HTTP/1.1\r\n\r\n"), Some(414));
+		assert_eq!(status("GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n"), Some(431));
+		assert_eq!(status("GET / HTTP/1.1\r\nX-Long: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\r\n\r\n"), Some(431));
+		assert_eq!(status("POST / HTTP/1.1\r\nContent-Length: 9\r\n\r\n123456789"), Some(413));
+		assert_eq!(status("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\n12345\r\n4\r\n6789\r\n0\r\n\r\n"), Some(413));
+		assert_eq!(status("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1\r\n1\r\nffffffffffffffff\r\n0\r\n\r\n"), Some(413));
+		assert_eq!(status("POST / HTTP/1.1\r\nContent-Length: 8\r\n\r\n12345678"), None);
+	}
+
+	#[test]
+	fn truncated_request_is_bad_request() {
+		let err = parse("GET / HTTP/1.1\r\nHost: local").err().unwrap();
+		assert_eq!(err.status_code(), StatusCode::bad_request());
+	}
+
+	#[test]
+	fn invalid_request_targets_are_bad_requests() {
+		let status = |raw: &str| parse(raw).err().map(|e| e.status_code().get_code());
+
+		assert_eq!(status("GET /a%zz HTTP/1.1\r\n\r\n"), Some(400));
+		assert_eq!(status("GET /a|b HTTP/1.1\r\n\r\n"), Some(400));
+		assert_eq!(status("GET http://[::1 HTTP/1.1\r\n\r\n"), Some(400));
+		assert_eq!(status("GET where HTTP/1.1\r\n\r\n"), Some(400));
+		assert_eq!(status("GET /where?a=1#top HTTP/1.1\r\n\r\n"), None);
+		assert_eq!(status("GET http://example.com:8080/where HTTP/1.1\r\n\r\n"), None);
+	}
+}
@@ -1,18 +1,60 @@
 use std::thread;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 pub struct ThreadPool {
 	_num: usize,
 	_threads: Vec<Worker>,
-	sender: mpsc::Sender<Job>
+	sender: mpsc::Sender<Job>,
+	stats: PoolStats
+}
+
+/// What the pool's threads are up to, readable from anywhere while they work.
+#[derive(Clone)]
+pub struct PoolStats {
+	size: usize,
+	busy: Arc<AtomicUsize>,
+	queued: Arc<AtomicUsize>
+}
+
+impl PoolStats {
+	/// How many threads the pool has.
+	pub fn size(&self) -> usize {
+		self.size
+	}
+
+	/// How many threads are running a job.
+	pub fn busy(&self) -> usize {
+		self.busy.load(Ordering::Relaxed)
+	}
+
+	/// How many jobs are waiting for a thread.
+	pub fn queued(&self) -> usize {
+		self.queued.load(Ordering::Relaxed)
+	}
+}
+
+// Counts a job as running until dropped, even if the job panics
+struct Running<'a>(&'a AtomicUsize);
+
+impl<'a> Drop for Running<'a> {
+	fn drop(&mut self) {
+		self.0.fetch_sub(1, Ordering::Relaxed);
+	}
 }
 
 pub struct PoolCreationError<'a> {
 	pub message: &'a str
 }
 
+/// Returned by `execute` when there is no thread left to run the job.
+pub struct ExecuteError {
+	pub message: &'static str
+}
+
 type JobReceiver<T> = Arc<Mutex<mpsc::Receiver<T>>>;
 
 struct Worker {
@@ -34,16 +76,18 @@ type Job = Box<dyn FnBox + Send + 'static>;
 
 
 impl Worker {
-	fn new(id: usize, receiver: JobReceiver<Job>) -> Worker {
+	fn new(id: usize, receiver: JobReceiver<Job>, stats: PoolStats) -> Worker {
 		Worker {
 			_id: id,
 			_handle: thread::spawn(move || {
 				loop {
 					let job = receiver.lock().unwrap().recv().unwrap();
+					stats.queued.fetch_sub(1, Ordering::Relaxed);
+					stats.busy.fetch_add(1, Ordering::Relaxed);
+					let _running = Running(&stats.busy);
 
-					println!("Worker {} got a job; executing.", id);
-
-					job.call_box();
+					//a job that panics has already reported it; the thread carries on with the next one
+					let _ = panic::catch_unwind(AssertUnwindSafe(|| job.call_box()));
 				}
 			}),
 		}
@@ -70,22 +114,36 @@ impl ThreadPool {
 
 		let (sender, receiver) = mpsc::channel();
 		let receiver = Arc::new(Mutex::new(receiver));
-
+		let stats = PoolStats {
+			size: count,
+			busy: Arc::new(AtomicUsize::new(0)),
+			queued: Arc::new(AtomicUsize::new(0))
+		};
 
 		for id in 0..count {
-			threads.push(Worker::new(id, Arc::clone(&receiver)));
+			threads.push(Worker::new(id, Arc::clone(&receiver), stats.clone()));
 		}
 
 		Ok(ThreadPool{
 			_num:  count,
 			_threads: threads,
-			sender
+			sender,
+			stats
 		})
 	}
 
-	pub fn execute<F>(&self, f: F) 
+	/// Queues the job for the next free thread.
+	pub fn execute<F>(&self, f: F) -> Result<(), ExecuteError>
 		where F: FnOnce() + Send + 'static {
 			let job = Box::new(f);
-			self.sender.send(job).unwrap();
+			self.stats.queued.fetch_add(1, Ordering::Relaxed);
+			self.sender.send(job).map_err(|_| {
+				self.stats.queued.fetch_sub(1, Ordering::Relaxed);
+				ExecuteError { message: "The pool's threads have stopped" }
+			})
+	}
+
+	pub fn stats(&self) -> PoolStats {
+		self.stats.clone()
 	}
-}
\ No newline at end of file
+}
\ No newline at end of file
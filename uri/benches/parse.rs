@@ -0,0 +1,38 @@
+#[macro_use]
+extern crate criterion;
+extern crate uri;
+
+use criterion::{black_box, Criterion};
+use uri::{Uri, UriRef};
+
+// What request lines carry: mostly origin-form paths, sometimes a query, now and then an absolute URL for a proxy
+const TARGETS: [&str; 4] = [
+	"/",
+	"/scripts/test.js",
+	"/api/properties/42/photos?page=2&size=20&sort=taken%20desc",
+	"http://welling.local:7878/login?next=%2Fproperties%3Fview%3Dmap"
+];
+
+fn request_targets(c: &mut Criterion) {
+	let mut group = c.benchmark_group("request targets");
+	group.bench_function("Uri::parse", |b| b.iter(|| {
+		for target in TARGETS.iter() {
+			black_box(Uri::parse(black_box(target)).unwrap());
+		}
+	}));
+	group.bench_function("UriRef::parse", |b| b.iter(|| {
+		for target in TARGETS.iter() {
+			black_box(UriRef::parse(black_box(target)).unwrap());
+		}
+	}));
+	//what routing needs: the segments, without keeping the URI
+	group.bench_function("UriRef::parse then segments", |b| b.iter(|| {
+		for target in TARGETS.iter() {
+			black_box(UriRef::parse(black_box(target)).unwrap().segments().count());
+		}
+	}));
+	group.finish();
+}
+
+criterion_group!(benches, request_targets);
+criterion_main!(benches);
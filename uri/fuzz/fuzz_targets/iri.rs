@@ -0,0 +1,14 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate uri;
+
+use uri::Uri;
+
+// Any text at all, Unicode included, either converts to a URI that round-trips or is turned away
+fuzz_target!(|raw: &str| {
+	if let Ok(uri) = Uri::parse_iri(raw) {
+		assert_eq!(Uri::parse(&uri.to_string()), Ok(uri.clone()));
+		let _ = Uri::parse_iri(&uri.to_iri());
+	}
+});
@@ -0,0 +1,23 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate uri;
+
+use uri::{Uri, UriRef};
+
+// Whatever parses formats to something that parses back to it, and formats the same way again
+fuzz_target!(|raw: &str| {
+	let uri = match Uri::parse(raw) {
+		Ok(uri) => uri,
+		Err(_) => return
+	};
+	assert_eq!(UriRef::parse(raw).map(|r| r.to_uri()), Ok(uri.clone()));
+
+	let formatted = uri.to_string();
+	let reparsed = Uri::parse(&formatted).expect("a formatted URI parses");
+	assert_eq!(reparsed, uri);
+	assert_eq!(reparsed.to_string(), formatted);
+
+	let normalized = uri.normalize();
+	assert_eq!(normalized.normalize(), normalized);
+});
@@ -0,0 +1,25 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate uri;
+
+use std::collections::HashMap;
+
+use uri::{TemplateValue, Uri, UriTemplate};
+
+// Templates parse or are turned away, and expand to ASCII; an expansion that's a URI reference, which literals
+// such as `[` can keep it from being, can be matched against the template
+fuzz_target!(|input: (&str, &str)| {
+	let (raw, value) = input;
+	let template = match UriTemplate::parse(raw) {
+		Ok(template) => template,
+		Err(_) => return
+	};
+
+	let variables: HashMap<String, TemplateValue> = template.variables().iter().map(|name| (name.to_string(), TemplateValue::from(value))).collect();
+	let expanded = template.expand(&variables);
+	assert!(expanded.is_ascii());
+	if let Ok(uri) = Uri::parse(&expanded) {
+		let _ = template.matches(&uri);
+	}
+});
@@ -0,0 +1,104 @@
+use std::error::Error;
+use std::fmt;
+
+/// The part of a URI an error was found in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Component {
+	Scheme,
+	Userinfo,
+	Host,
+	Port,
+	Path,
+	Query,
+	Fragment
+}
+
+impl fmt::Display for Component {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match *self {
+			Component::Scheme => "scheme",
+			Component::Userinfo => "userinfo",
+			Component::Host => "host",
+			Component::Port => "port",
+			Component::Path => "path",
+			Component::Query => "query",
+			Component::Fragment => "fragment"
+		};
+		f.write_str(name)
+	}
+}
+
+/// Why a string isn't a URI reference. Positions are byte offsets into the string that was parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UriError {
+	/// A character that can't appear in that part of a URI, e.g. a space in the path.
+	InvalidCharacter { position: usize, component: Component, character: char },
+	/// A `%` that isn't followed by two hex digits.
+	InvalidPercentEncoding { position: usize, component: Component },
+	/// A `:` with nothing before it, e.g. `:8080`.
+	EmptyScheme,
+	/// A port too large for TCP or UDP.
+	PortOutOfRange { position: usize },
+	/// Something between `[` and `]` that is neither an IPv6 address nor an `IPvFuture` one, or a `[` with no `]`.
+	InvalidIpLiteral { position: usize },
+	/// An internationalized host name that UTS #46 processing turns away, e.g. one with a label over 63 characters.
+	InvalidDomain { position: usize },
+	/// A path that would be read back as something else, e.g. `a` after a host, which would become part of it.
+	InvalidPath { position: usize, reason: &'static str },
+	/// A URI RFC 3986 allows but its scheme doesn't, e.g. `http:g`, which has no host.
+	SchemeViolation { scheme: &'static str, reason: &'static str }
+}
+
+impl UriError {
+	pub fn position(&self) -> usize {
+		match *self {
+			UriError::InvalidCharacter { position, .. }
+			| UriError::InvalidPercentEncoding { position, .. }
+			| UriError::PortOutOfRange { position }
+			| UriError::InvalidIpLiteral { position }
+			| UriError::InvalidDomain { position }
+			| UriError::InvalidPath { position, .. } => position,
+			UriError::EmptyScheme | UriError::SchemeViolation { .. } => 0
+		}
+	}
+
+	pub fn component(&self) -> Component {
+		match *self {
+			UriError::InvalidCharacter { component, .. } | UriError::InvalidPercentEncoding { component, .. } => component,
+			UriError::EmptyScheme | UriError::SchemeViolation { .. } => Component::Scheme,
+			UriError::PortOutOfRange { .. } => Component::Port,
+			UriError::InvalidIpLiteral { .. } | UriError::InvalidDomain { .. } => Component::Host,
+			UriError::InvalidPath { .. } => Component::Path
+		}
+	}
+
+	// The same error, at the position `at` maps its own to, e.g. in the IRI a URI was converted from
+	pub(crate) fn moved<F: Fn(usize) -> usize>(self, at: F) -> UriError {
+		match self {
+			UriError::InvalidCharacter { position, component, character } => UriError::InvalidCharacter { position: at(position), component, character },
+			UriError::InvalidPercentEncoding { position, component } => UriError::InvalidPercentEncoding { position: at(position), component },
+			UriError::PortOutOfRange { position } => UriError::PortOutOfRange { position: at(position) },
+			UriError::InvalidIpLiteral { position } => UriError::InvalidIpLiteral { position: at(position) },
+			UriError::InvalidDomain { position } => UriError::InvalidDomain { position: at(position) },
+			UriError::InvalidPath { position, reason } => UriError::InvalidPath { position: at(position), reason },
+			other => other
+		}
+	}
+}
+
+impl fmt::Display for UriError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			UriError::InvalidCharacter { position, component, character } => write!(f, "invalid character {:?} in {} at {}", character, component, position),
+			UriError::InvalidPercentEncoding { position, component } => write!(f, "invalid percent-encoding in {} at {}", component, position),
+			UriError::EmptyScheme => write!(f, "empty scheme"),
+			UriError::PortOutOfRange { position } => write!(f, "port out of range at {}", position),
+			UriError::InvalidIpLiteral { position } => write!(f, "invalid IP literal at {}", position),
+			UriError::InvalidDomain { position } => write!(f, "invalid internationalized domain name at {}", position),
+			UriError::InvalidPath { position, reason } => write!(f, "invalid path at {}: {}", position, reason),
+			UriError::SchemeViolation { scheme, reason } => write!(f, "invalid {} URI: {}", scheme, reason)
+		}
+	}
+}
+
+impl Error for UriError {}
@@ -0,0 +1,141 @@
+use std::fmt::{Display, Formatter, Result};
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, ToSocketAddrs};
+use std::result;
+use std::vec::IntoIter;
+
+use {check, is_reg_name_char, is_sub_delim, is_unreserved, percent};
+use error::{Component, UriError};
+
+/// The host of an authority, told apart by how it's written, as RFC 3986 section 3.2.2 does.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Host {
+	/// A registered name, e.g. `example.com`, for DNS to resolve. Kept as written, escapes and all.
+	Domain(String),
+	/// A dotted-decimal address, e.g. `127.0.0.1`.
+	Ipv4(Ipv4Addr),
+	/// A bracketed address, e.g. `[::1]`, with the zone it's scoped to when written like `[fe80::1%25eth0]` (RFC 6874).
+	Ipv6(Ipv6Addr, Option<String>),
+	/// An address in a format RFC 3986 leaves for later versions, e.g. `[v7.fe80::1]`, without its brackets.
+	IpvFuture(String)
+}
+
+impl Host {
+	// `None` for an empty host, as in `file:///etc/hosts`
+	pub(crate) fn parse(host: &str, offset: usize) -> result::Result<Option<Host>, UriError> {
+		if host.is_empty() {
+			return Ok(None);
+		}
+		if host.starts_with('[') && host.ends_with(']') {
+			return parse_ip_literal(&host[1..host.len() - 1]).map(Some).ok_or(UriError::InvalidIpLiteral { position: offset });
+		}
+
+		check(host, offset, Component::Host, is_reg_name_char)?;
+		//anything that isn't exactly four decimal octets, e.g. `1.2.3` or `010.0.0.1`, is a name
+		match host.parse::<Ipv4Addr>() {
+			Ok(address) => Ok(Some(Host::Ipv4(address))),
+			Err(_) => Ok(Some(Host::Domain(host.to_string())))
+		}
+	}
+
+	/// The addresses this host has on `port`: a name is looked up, an address is used as it is.
+	pub fn resolve(&self, port: u16) -> io::Result<IntoIter<SocketAddr>> {
+		match *self {
+			Host::Domain(ref name) => (percent::decode(name).as_str(), port).to_socket_addrs(),
+			Host::Ipv4(address) => Ok(vec!(SocketAddr::from((address, port))).into_iter()),
+			Host::Ipv6(address, None) => Ok(vec!(SocketAddr::from((address, port))).into_iter()),
+			Host::Ipv6(address, Some(ref zone)) => match zone.parse::<u32>() {
+				Ok(scope_id) => Ok(vec!(SocketAddr::V6(SocketAddrV6::new(address, port, 0, scope_id))).into_iter()),
+				//the system resolver knows interfaces by name
+				Err(_) => (format!("{}%{}", address, zone).as_str(), port).to_socket_addrs()
+			},
+			Host::IpvFuture(_) => Err(io::Error::new(io::ErrorKind::InvalidInput, "IPvFuture addresses can't be connected to"))
+		}
+	}
+}
+
+impl Display for Host {
+	fn fmt(&self, f: &mut Formatter) -> Result {
+		match *self {
+			Host::Domain(ref name) => write!(f, "{}", name),
+			Host::Ipv4(address) => write!(f, "{}", address),
+			Host::Ipv6(address, None) => write!(f, "[{}]", address),
+			Host::Ipv6(address, Some(ref zone)) => {
+				//a zone is unreserved characters only, and its `%` delimiter is itself encoded
+				let zone: String = zone.bytes().map(|b| if is_unreserved(b) { (b as char).to_string() } else { format!("%{:02X}", b) }).collect();
+				write!(f, "[{}%25{}]", address, zone)
+			},
+			Host::IpvFuture(ref address) => write!(f, "[{}]", address)
+		}
+	}
+}
+
+// IPv6address, IPv6addrz or IPvFuture, without the brackets
+fn parse_ip_literal(literal: &str) -> Option<Host> {
+	if literal.starts_with('v') || literal.starts_with('V') {
+		//"v" 1*HEXDIG "." 1*( unreserved / sub-delims / ":" )
+		let mut parts = literal[1..].splitn(2, '.');
+		let version = parts.next().unwrap_or("");
+		let address = parts.next().unwrap_or("");
+		let valid = !version.is_empty() && version.bytes().all(|b| b.is_ascii_hexdigit())
+			&& !address.is_empty() && address.bytes().all(|b| is_unreserved(b) || is_sub_delim(b) || b == b':');
+		return if valid { Some(Host::IpvFuture(literal.to_string())) } else { None };
+	}
+
+	match literal.find("%25") {
+		Some(ix) => {
+			let address = literal[..ix].parse::<Ipv6Addr>().ok()?;
+			let zone = &literal[ix + 3..];
+			if zone.is_empty() || check(zone, 0, Component::Host, is_unreserved).is_err() {
+				return None;
+			}
+			//kept decoded, so it has to decode to text to be written the same way again
+			String::from_utf8(percent::decode_bytes(zone)).ok().map(|zone| Host::Ipv6(address, Some(zone)))
+		},
+		None => literal.parse::<Ipv6Addr>().ok().map(|address| Host::Ipv6(address, None))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn host(raw: &str) -> Host {
+		Host::parse(raw, 0).unwrap().unwrap()
+	}
+
+	#[test]
+	fn hosts_are_typed_by_syntax() {
+		assert_eq!(host("example.com"), Host::Domain(String::from("example.com")));
+		assert_eq!(host("127.0.0.1"), Host::Ipv4(Ipv4Addr::new(127, 0, 0, 1)));
+		assert_eq!(host("1.2.3"), Host::Domain(String::from("1.2.3")));
+		assert_eq!(host("010.0.0.1"), Host::Domain(String::from("010.0.0.1")));
+		assert_eq!(host("[::1]"), Host::Ipv6(Ipv6Addr::LOCALHOST, None));
+		assert_eq!(host("[fe80::1%25en%30]"), Host::Ipv6("fe80::1".parse().unwrap(), Some(String::from("en0"))));
+		assert_eq!(host("[v7.fe80::1]"), Host::IpvFuture(String::from("v7.fe80::1")));
+		assert_eq!(Host::parse("", 0), Ok(None));
+
+		for invalid in &["[::1%eth0]", "[::1%25]", "[::1%25eth 0]", "[::1%25%FF]", "[1.2.3.4]", "[v.x]", "[vz.x]"] {
+			assert_eq!(Host::parse(invalid, 3), Err(UriError::InvalidIpLiteral { position: 3 }), "{}", invalid);
+		}
+	}
+
+	#[test]
+	fn hosts_display_as_written_in_uris() {
+		for raw in &["example.com", "10.0.0.1", "[::1]", "[fe80::1%25eth0]", "[v7.fe80::1]"] {
+			assert_eq!(host(raw).to_string(), *raw);
+		}
+		assert_eq!(Host::Ipv6(Ipv6Addr::LOCALHOST, Some(String::from("a b"))).to_string(), "[::1%25a%20b]");
+	}
+
+	#[test]
+	fn addresses_resolve_without_a_lookup() {
+		let addrs: Vec<SocketAddr> = host("[::1]").resolve(8080).unwrap().collect();
+		assert_eq!(addrs, vec!("[::1]:8080".parse().unwrap()));
+
+		let scoped: Vec<SocketAddr> = host("[fe80::1%253]").resolve(80).unwrap().collect();
+		assert_eq!(scoped, vec!(SocketAddr::V6(SocketAddrV6::new("fe80::1".parse().unwrap(), 80, 0, 3))));
+
+		assert!(host("[v7.fe80::1]").resolve(80).is_err());
+	}
+}
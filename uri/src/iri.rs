@@ -0,0 +1,168 @@
+use std::iter;
+use std::result;
+
+use idna;
+
+use error::UriError;
+use {percent, Host, Uri};
+
+impl Uri {
+	/// Parses an IRI (RFC 3987), which is a URI with Unicode in it, e.g. `http://bücher.example/straße`, into the URI it stands for.
+	/// The host goes through IDNA (UTS #46) processing into Punycode, and any other non-ASCII characters are percent-encoded
+	/// as UTF-8. An IRI that is all ASCII is just a URI, and parses as one. Error positions are in the IRI.
+	pub fn parse_iri(iri: &str) -> result::Result<Uri, UriError> {
+		let mut converted = String::with_capacity(iri.len());
+		//for each byte of the URI, where in the IRI it came from
+		let mut origins = Vec::with_capacity(iri.len());
+
+		let mut ix = 0;
+		if let Some((start, end)) = host_span(iri) {
+			encode_non_ascii(&iri[..start], 0, &mut converted, &mut origins);
+			let name = &iri[start..end];
+			if name.is_ascii() {
+				converted.push_str(name);
+				origins.extend(start..end);
+			} else {
+				let ascii = idna::domain_to_ascii(name).map_err(|_| UriError::InvalidDomain { position: start })?;
+				converted.push_str(&ascii);
+				origins.extend(iter::repeat_n(start, ascii.len()));
+			}
+			ix = end;
+		}
+		encode_non_ascii(&iri[ix..], ix, &mut converted, &mut origins);
+
+		Uri::parse(&converted).map_err(|e| e.moved(|position| origins.get(position).cloned().unwrap_or(iri.len())))
+	}
+
+	/// This URI as people read it (RFC 3987 section 3.2), e.g. `http://xn--bcher-kva.example/stra%C3%9Fe` as
+	/// `http://bücher.example/straße`. Escapes of ASCII characters, such as `%2F`, are kept, since they mean something.
+	/// It's for showing, not for sending: what looks alike in Unicode needn't be alike.
+	pub fn to_iri(&self) -> String {
+		let mut iri = String::new();
+		if let Some(ref scheme) = self.scheme {
+			iri.push_str(scheme);
+			iri.push(':');
+		}
+
+		if let Some(ref authority) = self.path.authority {
+			iri.push_str("//");
+			if let Some(ref userinfo) = authority.userinfo {
+				iri.push_str(&percent::decode_non_ascii(userinfo));
+				iri.push('@');
+			}
+			if let Some(ref host) = authority.host {
+				iri.push_str(&host.to_unicode());
+			}
+			if let Some(port) = authority.port {
+				iri.push_str(&format!(":{}", port));
+			}
+		}
+
+		if self.path.absolute_path {
+			iri.push('/');
+		}
+		iri.push_str(&percent::decode_non_ascii(&self.path.path_components.join("/")));
+
+		if let Some(ref query) = self.query {
+			iri.push('?');
+			iri.push_str(&percent::decode_non_ascii(query));
+		}
+		if let Some(ref fragment) = self.fragment {
+			iri.push('#');
+			iri.push_str(&percent::decode_non_ascii(fragment));
+		}
+		iri
+	}
+}
+
+impl Host {
+	/// The host as people read it: a domain's Punycode labels, e.g. `xn--bcher-kva`, back in Unicode, e.g. `bücher`.
+	/// A name that isn't valid IDNA is left as it is.
+	pub fn to_unicode(&self) -> String {
+		match *self {
+			Host::Domain(ref name) if name.split('.').any(is_punycode) => match idna::domain_to_unicode(name) {
+				(unicode, Ok(())) => unicode,
+				_ => name.clone()
+			},
+			ref other => other.to_string()
+		}
+	}
+}
+
+fn is_punycode(label: &str) -> bool {
+	label.get(..4).map(|prefix| prefix.eq_ignore_ascii_case("xn--")).unwrap_or(false)
+}
+
+// Where the host is, found as `UriRef::parse` finds it, but with nothing checked
+fn host_span(iri: &str) -> Option<(usize, usize)> {
+	let rest = &iri[..iri.find(['?', '#']).unwrap_or(iri.len())];
+	let mut start = 0;
+	if let Some(colon) = rest.find(':') {
+		if !rest[..colon].contains('/') {
+			start = colon + 1;
+		}
+	}
+	if !rest[start..].starts_with("//") {
+		return None;
+	}
+
+	let start = start + 2;
+	let end = rest[start..].find('/').map(|ix| start + ix).unwrap_or(rest.len());
+	let host_start = rest[start..end].find('@').map(|ix| start + ix + 1).unwrap_or(start);
+	let host = &rest[host_start..end];
+	let host_end = if host.starts_with('[') {
+		host.find(']').map(|ix| host_start + ix + 1).unwrap_or(end)
+	} else {
+		host.find(':').map(|ix| host_start + ix).unwrap_or(end)
+	};
+	Some((host_start, host_end))
+}
+
+fn encode_non_ascii(text: &str, offset: usize, out: &mut String, origins: &mut Vec<usize>) {
+	for (ix, c) in text.char_indices() {
+		if c.is_ascii() {
+			out.push(c);
+			origins.push(offset + ix);
+		} else {
+			let mut utf8 = [0; 4];
+			for b in c.encode_utf8(&mut utf8).bytes() {
+				out.push_str(&format!("%{:02X}", b));
+				origins.extend_from_slice(&[offset + ix; 3]);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use Component;
+
+	#[test]
+	fn iris_become_ascii_uris() {
+		let uri = Uri::parse_iri("http://Bücher.example:8080/straße/日本?q=ü#Überblick").unwrap();
+		assert_eq!(uri.to_string(), "http://xn--bcher-kva.example:8080/stra%C3%9Fe/%E6%97%A5%E6%9C%AC?q=%C3%BC#%C3%9Cberblick");
+		assert_eq!(uri.path.decoded_segments(), vec!(String::from("straße"), String::from("日本")));
+
+		assert_eq!(Uri::parse_iri("/a/b?c").unwrap(), Uri::new("/a/b?c"));
+		assert_eq!(Uri::parse_iri("http://user@[::1]:80/ä").unwrap().to_string(), "http://user@[::1]:80/%C3%A4");
+	}
+
+	#[test]
+	fn errors_point_into_the_iri() {
+		assert_eq!(Uri::parse_iri("http://ü.example/ä b"), Err(UriError::InvalidCharacter { position: 20, component: Component::Path, character: ' ' }));
+		assert_eq!(Uri::parse_iri("http://a\u{200d}b\u{fffd}.example/").unwrap_err(), UriError::InvalidDomain { position: 7 });
+		assert_eq!(Uri::parse_iri("http://ab%zz.de/").unwrap_err().position(), 9);
+	}
+
+	#[test]
+	fn uris_are_shown_in_unicode() {
+		let uri = Uri::new("http://xn--bcher-kva.example/stra%C3%9Fe%2F%20?q=%E6%97%A5#%FF");
+		assert_eq!(uri.to_iri(), "http://bücher.example/straße%2F%20?q=日#%FF");
+		assert_eq!(Uri::parse_iri(&uri.to_iri()).unwrap(), uri);
+
+		assert_eq!(Host::Domain(String::from("xn--bcher-kva.example")).to_unicode(), "bücher.example");
+		assert_eq!(Host::Domain(String::from("xn--invalid-.example")).to_unicode(), "xn--invalid-.example");
+		assert_eq!(Host::Domain(String::from("example.com")).to_unicode(), "example.com");
+	}
+}
@@ -0,0 +1,205 @@
+use {is_unreserved, is_sub_delim};
+
+/// Which part of a URI a value is going into, and so which characters it can keep as they are.
+/// Unreserved characters are always kept; everything outside the set is percent-encoded as UTF-8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncodeSet {
+	/// `user:password`, which can't contain `@`.
+	Userinfo,
+	/// A single path segment, so `/` is encoded too.
+	PathSegment,
+	/// A whole query, e.g. `a=1&b=2`.
+	Query,
+	/// A name or a value in a query of `name=value` pairs, so `&`, `=` and `+` are encoded too.
+	QueryPair,
+	Fragment
+}
+
+impl EncodeSet {
+	fn allows(self, b: u8) -> bool {
+		if is_unreserved(b) {
+			return true;
+		}
+		match self {
+			EncodeSet::Userinfo => is_sub_delim(b) || b == b':',
+			EncodeSet::PathSegment => is_sub_delim(b) || b == b':' || b == b'@',
+			EncodeSet::Query | EncodeSet::Fragment => is_sub_delim(b) || matches!(b, b':' | b'@' | b'/' | b'?'),
+			EncodeSet::QueryPair => (is_sub_delim(b) && !matches!(b, b'&' | b'=' | b'+')) || matches!(b, b':' | b'@' | b'/' | b'?')
+		}
+	}
+}
+
+/// Encodes a decoded value, e.g. `encode("a b/c", EncodeSet::PathSegment)` is `a%20b%2Fc`. A `%` is always encoded.
+pub fn encode(decoded: &str, set: EncodeSet) -> String {
+	let mut encoded = String::with_capacity(decoded.len());
+	for b in decoded.bytes() {
+		push_byte(&mut encoded, b, set);
+	}
+	encoded
+}
+
+/// Encodes whatever can't appear in an already-encoded value, leaving its `%XX` escapes alone.
+/// What was parsed comes back unchanged; a space put in by hand becomes `%20`.
+pub fn encode_invalid(raw: &str, set: EncodeSet) -> String {
+	let bytes = raw.as_bytes();
+	let mut encoded = String::with_capacity(raw.len());
+	let mut ix = 0;
+	while ix < bytes.len() {
+		if escape_at(bytes, ix).is_some() {
+			encoded.push_str(&raw[ix..ix + 3]);
+			ix += 3;
+		} else {
+			push_byte(&mut encoded, bytes[ix], set);
+			ix += 1;
+		}
+	}
+	encoded
+}
+
+/// Decodes `%XX` escapes to the octets they stand for. A `%` that isn't followed by two hex digits is kept as it is.
+pub fn decode_bytes(raw: &str) -> Vec<u8> {
+	let bytes = raw.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut ix = 0;
+	while ix < bytes.len() {
+		match escape_at(bytes, ix) {
+			Some(b) => {
+				decoded.push(b);
+				ix += 3;
+			},
+			None => {
+				decoded.push(bytes[ix]);
+				ix += 1;
+			}
+		}
+	}
+	decoded
+}
+
+/// Decodes `%XX` escapes as UTF-8, replacing any octets that aren't with U+FFFD.
+/// `+` is left alone: it only means a space in form data.
+pub fn decode(raw: &str) -> String {
+	String::from_utf8_lossy(&decode_bytes(raw)).into_owned()
+}
+
+/// Puts escapes in the form RFC 3986 section 6.2.2.2 compares them in: unreserved characters decoded, e.g. `%7E` to `~`,
+/// and the hex digits of the rest in upper case, e.g. `%2f` to `%2F`.
+pub fn normalize(raw: &str) -> String {
+	let bytes = raw.as_bytes();
+	let mut normalized = Vec::with_capacity(bytes.len());
+	let mut ix = 0;
+	while ix < bytes.len() {
+		match escape_at(bytes, ix) {
+			Some(b) if is_unreserved(b) => normalized.push(b),
+			Some(b) => normalized.extend_from_slice(format!("%{:02X}", b).as_bytes()),
+			None => {
+				normalized.push(bytes[ix]);
+				ix += 1;
+				continue;
+			}
+		}
+		ix += 3;
+	}
+	//only ASCII escapes were replaced, with ASCII, so it's still UTF-8
+	String::from_utf8(normalized).unwrap_or_else(|_| raw.to_string())
+}
+
+/// Decodes only the escapes of non-ASCII characters, e.g. `stra%C3%9Fe%2F` to `straße%2F`, for showing a URI as an IRI.
+/// Escapes of ASCII, of octets that aren't UTF-8, and of characters that could disguise the text, such as bidi controls, are kept.
+pub fn decode_non_ascii(raw: &str) -> String {
+	let bytes = raw.as_bytes();
+	let mut decoded = String::with_capacity(raw.len());
+	let mut ix = 0;
+	while ix < bytes.len() {
+		if let Some(c) = utf8_escapes_at(bytes, ix) {
+			decoded.push(c);
+			ix += c.len_utf8() * 3;
+		} else {
+			//escapes are ASCII, so this is always on a character boundary
+			let c = raw[ix..].chars().next().unwrap_or('\u{fffd}');
+			decoded.push(c);
+			ix += c.len_utf8();
+		}
+	}
+	decoded
+}
+
+// The non-ASCII character a run of escapes at `ix` stands for, e.g. `%C3%9F` for `ß`, if it can be shown as it is
+fn utf8_escapes_at(bytes: &[u8], ix: usize) -> Option<char> {
+	let len = match escape_at(bytes, ix)? {
+		0xC2..=0xDF => 2,
+		0xE0..=0xEF => 3,
+		0xF0..=0xF4 => 4,
+		_ => return None
+	};
+	let mut octets = Vec::with_capacity(len);
+	for n in 0..len {
+		let at = ix + n * 3;
+		octets.push(if at < bytes.len() { escape_at(bytes, at)? } else { return None });
+	}
+	let c = ::std::str::from_utf8(&octets).ok()?.chars().next()?;
+	let disguising = c.is_control() || c.is_whitespace() || matches!(c, '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' | '\u{feff}');
+	if disguising { None } else { Some(c) }
+}
+
+fn push_byte(out: &mut String, b: u8, set: EncodeSet) {
+	if b != b'%' && set.allows(b) {
+		out.push(b as char);
+	} else {
+		out.push_str(&format!("%{:02X}", b));
+	}
+}
+
+// The octet a `%XX` at `ix` stands for
+fn escape_at(bytes: &[u8], ix: usize) -> Option<u8> {
+	if bytes[ix] != b'%' || ix + 2 >= bytes.len() {
+		return None;
+	}
+	let hex = |b: u8| (b as char).to_digit(16);
+	Some((hex(bytes[ix + 1])? << 4 | hex(bytes[ix + 2])?) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn each_set_keeps_what_its_component_allows() {
+		let value = "a b/c?d&e=f+g@h:i#j%k";
+		assert_eq!(encode(value, EncodeSet::Userinfo), "a%20b%2Fc%3Fd&e=f+g%40h:i%23j%25k");
+		assert_eq!(encode(value, EncodeSet::PathSegment), "a%20b%2Fc%3Fd&e=f+g@h:i%23j%25k");
+		assert_eq!(encode(value, EncodeSet::Query), "a%20b/c?d&e=f+g@h:i%23j%25k");
+		assert_eq!(encode(value, EncodeSet::QueryPair), "a%20b/c?d%26e%3Df%2Bg@h:i%23j%25k");
+		assert_eq!(encode("caf\u{e9}", EncodeSet::Fragment), "caf%C3%A9");
+	}
+
+	#[test]
+	fn decoding_undoes_encoding() {
+		for set in &[EncodeSet::Userinfo, EncodeSet::PathSegment, EncodeSet::Query, EncodeSet::QueryPair, EncodeSet::Fragment] {
+			let value = "caf\u{e9} 100% /?&=+#";
+			assert_eq!(decode(&encode(value, *set)), value);
+		}
+		assert_eq!(decode("a%2fb%zz%4"), "a/b%zz%4");
+		assert_eq!(decode("%FF"), "\u{fffd}");
+		assert_eq!(decode_bytes("%FF%00"), vec!(0xff, 0));
+	}
+
+	#[test]
+	fn normalized_escapes_are_only_the_ones_needed() {
+		assert_eq!(normalize("%7euser/%2fa%2Fb%41"), "~user/%2Fa%2FbA");
+		assert_eq!(normalize("100%"), "100%");
+	}
+
+	#[test]
+	fn valid_escapes_are_not_encoded_twice() {
+		assert_eq!(encode_invalid("a%20b c%zz", EncodeSet::PathSegment), "a%20b%20c%25zz");
+		assert_eq!(encode_invalid("100%", EncodeSet::Query), "100%25");
+	}
+
+	#[test]
+	fn only_non_ascii_escapes_are_decoded_for_showing() {
+		assert_eq!(decode_non_ascii("stra%C3%9Fe%2F%20%E6%97%A5"), "straße%2F%20日");
+		assert_eq!(decode_non_ascii("%FF%C3%E2%80%AEa%C3%9"), "%FF%C3%E2%80%AEa%C3%9");
+		assert_eq!(decode_non_ascii("ü%e2%82%ac"), "ü€");
+	}
+}
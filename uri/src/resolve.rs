@@ -0,0 +1,232 @@
+use {percent, split_path, Authority, HierarchicalPart, Host, Scheme, Uri};
+
+impl Uri {
+	/// Resolves a reference found in this URI's resource against it, as RFC 3986 section 5.2 does,
+	/// e.g. `../scripts/test.js` against `http://example.com/views/index.html` is `http://example.com/scripts/test.js`.
+	/// The base should be an absolute URI; its fragment, if it has one, plays no part.
+	pub fn join(&self, reference: &Uri) -> Uri {
+		let (scheme, authority, path, query) = if reference.scheme.is_some() {
+			(reference.scheme.clone(), reference.path.authority.clone(), remove_dot_segments(&path_of(&reference.path)), reference.query.clone())
+		} else if reference.path.authority.is_some() {
+			(self.scheme.clone(), reference.path.authority.clone(), remove_dot_segments(&path_of(&reference.path)), reference.query.clone())
+		} else {
+			let reference_path = path_of(&reference.path);
+			let (path, query) = if reference_path.is_empty() {
+				(path_of(&self.path), reference.query.clone().or_else(|| self.query.clone()))
+			} else if reference_path.starts_with('/') {
+				(remove_dot_segments(&reference_path), reference.query.clone())
+			} else {
+				(remove_dot_segments(&merge(&self.path, &reference_path)), reference.query.clone())
+			};
+			(self.scheme.clone(), self.path.authority.clone(), path, query)
+		};
+
+		Uri {
+			scheme,
+			path: hierarchical_part(authority, &path),
+			query,
+			fragment: reference.fragment.clone()
+		}
+	}
+
+	/// The form of this URI that equivalent ones share, per RFC 3986 section 6.2.2 and 6.2.3: scheme and host in lower case,
+	/// escapes only where needed and in upper case, no `.` or `..` segments, no default port, and `/` for an empty path to a server, e.g. `http://example.com`.
+	/// Relative references keep their dot segments, which only mean something once they're resolved.
+	pub fn normalize(&self) -> Uri {
+		let scheme = self.scheme.as_ref().map(|s| s.to_ascii_lowercase());
+		let authority = self.path.authority.as_ref().map(|a| Authority {
+			userinfo: a.userinfo.as_ref().map(|u| percent::normalize(u)),
+			host: a.host.as_ref().map(|h| match *h {
+				Host::Domain(ref name) => Host::Domain(lowercase_name(name)),
+				ref other => other.clone()
+			}),
+			port: match (a.port, scheme.as_ref().and_then(|s| Scheme::find(s))) {
+				(Some(port), Some(known)) if port == known.default_port as usize => None,
+				(port, _) => port
+			}
+		});
+
+		let mut path = self.path.path_components.iter().map(|s| percent::normalize(s)).collect::<Vec<String>>().join("/");
+		if self.path.absolute_path {
+			path.insert(0, '/');
+		}
+		if scheme.is_some() {
+			path = remove_dot_segments(&path);
+		}
+		if path.is_empty() && authority.is_some() && self.known_scheme().is_some() {
+			path.push('/');
+		}
+
+		Uri {
+			scheme,
+			path: hierarchical_part(authority, &path),
+			query: self.query.as_ref().map(|q| percent::normalize(q)),
+			fragment: self.fragment.as_ref().map(|f| percent::normalize(f))
+		}
+	}
+}
+
+// A name normalized and then in lower case, but for the hex digits of its escapes, e.g. `%41%2f` to `a%2F`
+fn lowercase_name(name: &str) -> String {
+	let mut lowered = String::with_capacity(name.len());
+	let mut hex_digits = 0;
+	for c in percent::normalize(name).chars() {
+		if c == '%' {
+			hex_digits = 2;
+			lowered.push(c);
+		} else if hex_digits > 0 {
+			hex_digits -= 1;
+			lowered.push(c);
+		} else {
+			lowered.push(c.to_ascii_lowercase());
+		}
+	}
+	lowered
+}
+
+// The path as it's written, e.g. `/a/b`, `a/b` or nothing at all
+fn path_of(part: &HierarchicalPart) -> String {
+	let path = part.path_components.join("/");
+	if part.absolute_path { format!("/{}", path) } else { path }
+}
+
+fn hierarchical_part(authority: Option<Authority>, path: &str) -> HierarchicalPart {
+	HierarchicalPart {
+		authority,
+		path_components: split_path(path),
+		absolute_path: path.starts_with('/')
+	}
+}
+
+// RFC 3986 section 5.2.3: the reference's path in place of the base's last segment
+fn merge(base: &HierarchicalPart, reference_path: &str) -> String {
+	let base_path = path_of(base);
+	if base.authority.is_some() && base_path.is_empty() {
+		format!("/{}", reference_path)
+	} else {
+		match base_path.rfind('/') {
+			Some(ix) => format!("{}{}", &base_path[..ix + 1], reference_path),
+			None => reference_path.to_string()
+		}
+	}
+}
+
+// RFC 3986 section 5.2.4, a step at a time as it's written there
+fn remove_dot_segments(path: &str) -> String {
+	let mut input = path;
+	let mut output = String::with_capacity(path.len());
+	while !input.is_empty() {
+		if input.starts_with("../") {
+			input = &input[3..];
+		} else if input.starts_with("./") || input.starts_with("/./") {
+			input = &input[2..];
+		} else if input == "/." {
+			input = "/";
+		} else if input.starts_with("/../") {
+			input = &input[3..];
+			pop_segment(&mut output);
+		} else if input == "/.." {
+			input = "/";
+			pop_segment(&mut output);
+		} else if input == "." || input == ".." {
+			input = "";
+		} else {
+			let end = input[1..].find('/').map(|ix| ix + 1).unwrap_or(input.len());
+			output.push_str(&input[..end]);
+			input = &input[end..];
+		}
+	}
+	output
+}
+
+fn pop_segment(output: &mut String) {
+	let ix = output.rfind('/').unwrap_or(0);
+	output.truncate(ix);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn resolve(reference: &str) -> String {
+		Uri::new("http://a/b/c/d;p?q").join(&Uri::new(reference)).to_string()
+	}
+
+	#[test]
+	fn rfc_3986_normal_examples() {
+		for &(reference, target) in &[
+			("g:h", "g:h"),
+			("g", "http://a/b/c/g"),
+			("./g", "http://a/b/c/g"),
+			("g/", "http://a/b/c/g/"),
+			("/g", "http://a/g"),
+			("//g", "http://g"),
+			("?y", "http://a/b/c/d;p?y"),
+			("g?y", "http://a/b/c/g?y"),
+			("#s", "http://a/b/c/d;p?q#s"),
+			("g#s", "http://a/b/c/g#s"),
+			("g?y#s", "http://a/b/c/g?y#s"),
+			(";x", "http://a/b/c/;x"),
+			("g;x", "http://a/b/c/g;x"),
+			("g;x?y#s", "http://a/b/c/g;x?y#s"),
+			("", "http://a/b/c/d;p?q"),
+			(".", "http://a/b/c/"),
+			("./", "http://a/b/c/"),
+			("..", "http://a/b/"),
+			("../", "http://a/b/"),
+			("../g", "http://a/b/g"),
+			("../..", "http://a/"),
+			("../../", "http://a/"),
+			("../../g", "http://a/g")
+		] {
+			assert_eq!(resolve(reference), target, "{}", reference);
+		}
+	}
+
+	#[test]
+	fn rfc_3986_abnormal_examples() {
+		for &(reference, target) in &[
+			("../../../g", "http://a/g"),
+			("../../../../g", "http://a/g"),
+			("/./g", "http://a/g"),
+			("/../g", "http://a/g"),
+			("g.", "http://a/b/c/g."),
+			(".g", "http://a/b/c/.g"),
+			("g..", "http://a/b/c/g.."),
+			("..g", "http://a/b/c/..g"),
+			("./../g", "http://a/b/g"),
+			("./g/.", "http://a/b/c/g/"),
+			("g/./h", "http://a/b/c/g/h"),
+			("g/../h", "http://a/b/c/h"),
+			("g;x=1/./y", "http://a/b/c/g;x=1/y"),
+			("g;x=1/../y", "http://a/b/c/y"),
+			("g?y/./x", "http://a/b/c/g?y/./x"),
+			("g?y/../x", "http://a/b/c/g?y/../x"),
+			("g#s/./x", "http://a/b/c/g#s/./x"),
+			("g#s/../x", "http://a/b/c/g#s/../x"),
+			("http:g", "http:g")
+		] {
+			assert_eq!(resolve(reference), target, "{}", reference);
+		}
+	}
+
+	#[test]
+	fn links_resolve_against_the_page() {
+		let page = Uri::new("http://example.com/views/index.html");
+		assert_eq!(page.join(&Uri::new("../scripts/test.js")).to_string(), "http://example.com/scripts/test.js");
+		assert_eq!(Uri::new("http://example.com").join(&Uri::new("a")).to_string(), "http://example.com/a");
+	}
+
+	#[test]
+	fn equivalent_uris_normalize_alike() {
+		let normalized = Uri::new("HTTP://User%3a@Example.COM:80/%7euser/./a/../b%2f?q=%41%3d#%7E").normalize();
+		assert_eq!(normalized.to_string(), "http://User%3A@example.com/~user/b%2F?q=A%3D#~");
+
+		assert_eq!(Uri::new("http://example.com").normalize().to_string(), "http://example.com/");
+		assert_eq!(Uri::new("https://example.com:443").normalize(), Uri::new("https://example.com/"));
+		assert_eq!(Uri::new("https://example.com:80/").normalize().to_string(), "https://example.com:80/");
+		assert_eq!(Uri::new("mailto:Someone@Example.com").normalize().to_string(), "mailto:Someone@Example.com");
+		assert_eq!(Uri::new("../a/./b").normalize().to_string(), "../a/./b");
+		assert_eq!(Uri::new("http://%41%2f.Example/").normalize().to_string(), "http://a%2F.example/");
+	}
+}
@@ -0,0 +1,75 @@
+use std::result;
+
+use error::UriError;
+use Uri;
+
+/// What's known about a scheme whose URIs name a server: the port it listens on unless told otherwise,
+/// and what else its URIs must or mustn't have.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Scheme {
+	pub name: &'static str,
+	pub default_port: u16,
+	/// Whether its URIs can have a fragment; WebSocket ones can't (RFC 6455 section 3).
+	pub fragments: bool,
+	/// How many path segments its URIs can have, if there's a limit, e.g. one, the database, for `mysql`.
+	pub max_segments: Option<usize>
+}
+
+static KNOWN: [Scheme; 5] = [
+	Scheme { name: "http", default_port: 80, fragments: true, max_segments: None },
+	Scheme { name: "https", default_port: 443, fragments: true, max_segments: None },
+	Scheme { name: "ws", default_port: 80, fragments: false, max_segments: None },
+	Scheme { name: "wss", default_port: 443, fragments: false, max_segments: None },
+	Scheme { name: "mysql", default_port: 3306, fragments: false, max_segments: Some(1) }
+];
+
+impl Scheme {
+	/// The registered scheme of that name, which like all scheme names is case-insensitive.
+	pub fn find(name: &str) -> Option<&'static Scheme> {
+		KNOWN.iter().find(|s| s.name.eq_ignore_ascii_case(name))
+	}
+
+	/// Checks a URI of this scheme has what the scheme needs beyond what RFC 3986 does, e.g. that an `http` one has a host.
+	pub fn check(&self, uri: &Uri) -> result::Result<(), UriError> {
+		let violation = |reason| Err(UriError::SchemeViolation { scheme: self.name, reason });
+
+		if uri.path.authority.as_ref().and_then(|a| a.host.as_ref()).is_none() {
+			return violation("a host is required");
+		}
+		if !self.fragments && uri.fragment.is_some() {
+			return violation("fragments aren't allowed");
+		}
+		if let Some(max) = self.max_segments {
+			let segments = uri.path.path_components.iter().filter(|s| !s.is_empty()).count();
+			if segments > max {
+				return violation("the path has too many segments");
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn schemes_are_found_whatever_their_case() {
+		assert_eq!(Scheme::find("HTTPS").map(|s| s.default_port), Some(443));
+		assert_eq!(Scheme::find("mysql").map(|s| s.default_port), Some(3306));
+		assert!(Scheme::find("gopher").is_none());
+	}
+
+	#[test]
+	fn each_scheme_has_its_own_rules() {
+		let check = |raw: &str| Uri::new(raw).validate();
+
+		assert_eq!(check("http://example.com/a#b"), Ok(()));
+		assert_eq!(check("http:g"), Err(UriError::SchemeViolation { scheme: "http", reason: "a host is required" }));
+		assert_eq!(check("https:///a"), Err(UriError::SchemeViolation { scheme: "https", reason: "a host is required" }));
+		assert_eq!(check("wss://example.com/chat#top"), Err(UriError::SchemeViolation { scheme: "wss", reason: "fragments aren't allowed" }));
+		assert_eq!(check("mysql://app@db/welling"), Ok(()));
+		assert_eq!(check("mysql://app@db/welling/users"), Err(UriError::SchemeViolation { scheme: "mysql", reason: "the path has too many segments" }));
+		assert_eq!(check("urn:isbn:0451450523"), Ok(()));
+	}
+}
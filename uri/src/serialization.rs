@@ -0,0 +1,66 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use error::UriError;
+use {Authority, HierarchicalPart, Uri};
+
+// Each is stored as the string it displays as, and checked as it's read back
+macro_rules! as_string {
+	($t:ty, $expecting:expr) => {
+		impl Serialize for $t {
+			fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+				serializer.collect_str(self)
+			}
+		}
+
+		impl<'de> Deserialize<'de> for $t {
+			fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$t, D::Error> {
+				deserializer.deserialize_str(Parsed(PhantomData, $expecting))
+			}
+		}
+	}
+}
+
+as_string!(Uri, "a URI reference");
+as_string!(Authority, "a URI authority, e.g. user@example.com:8080");
+as_string!(HierarchicalPart, "the authority and path of a URI, e.g. //example.com/a");
+
+// Reads a string with `FromStr`, without copying it first
+struct Parsed<T>(PhantomData<T>, &'static str);
+
+impl<'de, T: FromStr<Err = UriError>> Visitor<'de> for Parsed<T> {
+	type Value = T;
+
+	fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.1)
+	}
+
+	fn visit_str<E: de::Error>(self, value: &str) -> Result<T, E> {
+		value.parse().map_err(E::custom)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_test::{assert_de_tokens_error, assert_tokens, Token};
+	use super::*;
+
+	#[test]
+	fn uris_are_stored_as_strings() {
+		let uri = Uri::new("http://user@example.com:8080/a%20b?q=1#top");
+		assert_tokens(&uri, &[Token::Str("http://user@example.com:8080/a%20b?q=1#top")]);
+		assert_tokens(uri.path.authority.as_ref().unwrap(), &[Token::Str("user@example.com:8080")]);
+		assert_tokens(&uri.path, &[Token::Str("//user@example.com:8080/a%20b")]);
+	}
+
+	#[test]
+	fn invalid_strings_are_turned_away() {
+		assert_de_tokens_error::<Uri>(&[Token::Str("http://exa mple.com/")], "invalid character ' ' in host at 10");
+		assert_de_tokens_error::<Authority>(&[Token::Str("host:99999")], "port out of range at 5");
+		assert_de_tokens_error::<Uri>(&[Token::I32(1)], "invalid type: integer `1`, expected a URI reference");
+	}
+}
@@ -0,0 +1,650 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::result;
+
+use {is_sub_delim, is_unreserved, percent, Uri};
+
+/// A URI template as RFC 6570 defines it, at all four levels, e.g. `/properties/{id}{?view,page}`.
+/// It expands into a URI given values for its variables, and a URI can be matched against it to get them back,
+/// so routes and the links to them can share one definition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UriTemplate {
+	raw: String,
+	parts: Vec<Part>
+}
+
+/// What a template variable stands for: a string, a list of them, or `name=value` pairs, kept in the order given.
+/// An empty list or map counts as undefined, and expands to nothing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TemplateValue {
+	String(String),
+	List(Vec<String>),
+	Map(Vec<(String, String)>)
+}
+
+/// Why a string isn't a URI template, at a byte offset into it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TemplateError {
+	pub position: usize,
+	pub reason: &'static str
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+	//already percent-encoded wherever it needed to be
+	Literal(String),
+	Expression(Operator, Vec<VarSpec>)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct VarSpec {
+	name: String,
+	//the first so many characters of a string
+	prefix: Option<usize>,
+	explode: bool
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operator {
+	Simple,
+	Reserved,
+	Fragment,
+	Label,
+	Path,
+	PathParameter,
+	Query,
+	QueryContinuation
+}
+
+// The table in RFC 6570 appendix A
+impl Operator {
+	fn from_char(c: char) -> Option<Operator> {
+		match c {
+			'+' => Some(Operator::Reserved),
+			'#' => Some(Operator::Fragment),
+			'.' => Some(Operator::Label),
+			'/' => Some(Operator::Path),
+			';' => Some(Operator::PathParameter),
+			'?' => Some(Operator::Query),
+			'&' => Some(Operator::QueryContinuation),
+			_ => None
+		}
+	}
+
+	fn first(self) -> &'static str {
+		match self {
+			Operator::Simple | Operator::Reserved => "",
+			Operator::Fragment => "#",
+			Operator::Label => ".",
+			Operator::Path => "/",
+			Operator::PathParameter => ";",
+			Operator::Query => "?",
+			Operator::QueryContinuation => "&"
+		}
+	}
+
+	fn separator(self) -> &'static str {
+		match self {
+			Operator::Simple | Operator::Reserved | Operator::Fragment => ",",
+			Operator::Label => ".",
+			Operator::Path => "/",
+			Operator::PathParameter => ";",
+			Operator::Query | Operator::QueryContinuation => "&"
+		}
+	}
+
+	fn named(self) -> bool {
+		matches!(self, Operator::PathParameter | Operator::Query | Operator::QueryContinuation)
+	}
+
+	// What a named variable with an empty value is followed by
+	fn if_empty(self) -> &'static str {
+		if matches!(self, Operator::Query | Operator::QueryContinuation) { "=" } else { "" }
+	}
+
+	fn allows_reserved(self) -> bool {
+		matches!(self, Operator::Reserved | Operator::Fragment)
+	}
+
+	// Whether an expansion with this operator could have written the octet
+	fn writes(self, b: u8) -> bool {
+		is_unreserved(b) || b == b'%' || b == b',' || b == b'=' || self.first().as_bytes().contains(&b) || self.separator().as_bytes().contains(&b)
+			|| (self.allows_reserved() && (is_reserved(b) || b == b'%'))
+	}
+}
+
+impl UriTemplate {
+	pub fn parse(raw: &str) -> result::Result<UriTemplate, TemplateError> {
+		let mut parts = Vec::new();
+		let mut literal = String::new();
+		let mut ix = 0;
+		while ix < raw.len() {
+			let c = raw[ix..].chars().next().unwrap_or('\u{fffd}');
+			match c {
+				'{' => {
+					let end = raw[ix..].find('}').map(|e| ix + e).ok_or(TemplateError { position: ix, reason: "unclosed expression" })?;
+					if !literal.is_empty() {
+						parts.push(Part::Literal(encode(&literal, true)));
+						literal.clear();
+					}
+					parts.push(parse_expression(&raw[ix + 1..end], ix + 1)?);
+					ix = end + 1;
+					continue;
+				},
+				'}' => return Err(TemplateError { position: ix, reason: "'}' outside an expression" }),
+				'%' if !raw.as_bytes().get(ix + 1..ix + 3).map(|h| h.iter().all(|b| b.is_ascii_hexdigit())).unwrap_or(false) => {
+					return Err(TemplateError { position: ix, reason: "'%' not followed by two hex digits" });
+				},
+				c if c.is_control() || matches!(c, ' ' | '"' | '\'' | '<' | '>' | '\\' | '^' | '`' | '|') => {
+					return Err(TemplateError { position: ix, reason: "character not allowed in a literal" });
+				},
+				c => literal.push(c)
+			}
+			ix += c.len_utf8();
+		}
+		if !literal.is_empty() {
+			parts.push(Part::Literal(encode(&literal, true)));
+		}
+
+		Ok(UriTemplate { raw: raw.to_string(), parts })
+	}
+
+	/// Parses a template known to be valid, such as a route in the code.
+	///
+	/// # Panics
+	///
+	/// Panics if it isn't one.
+	pub fn new(raw: &str) -> UriTemplate {
+		UriTemplate::parse(raw).unwrap_or_else(|e| panic!("Invalid URI template {:?}: {}", raw, e))
+	}
+
+	/// The template as it was written.
+	pub fn as_str(&self) -> &str {
+		&self.raw
+	}
+
+	/// The names of the variables, in the order they appear.
+	pub fn variables(&self) -> Vec<&str> {
+		let mut names = Vec::new();
+		for part in &self.parts {
+			if let Part::Expression(_, ref specs) = *part {
+				names.extend(specs.iter().map(|s| s.name.as_str()));
+			}
+		}
+		names
+	}
+
+	/// The URI reference the template stands for with these values, e.g. `/properties/{id}{?view}` with `id` 42 and no `view`
+	/// is `/properties/42`. Variables without a value are left out, along with whatever would have introduced them.
+	pub fn expand(&self, variables: &HashMap<String, TemplateValue>) -> String {
+		let mut expanded = String::new();
+		for part in &self.parts {
+			match *part {
+				Part::Literal(ref literal) => expanded.push_str(literal),
+				Part::Expression(operator, ref specs) => expand_expression(operator, specs, variables, &mut expanded)
+			}
+		}
+		expanded
+	}
+
+	/// The values a URI was expanded from, if it could have been expanded from this template, e.g. `id` 42 from
+	/// `/properties/42` against `/properties/{id}`. Values are percent-decoded. A query or fragment the template has
+	/// no place for is ignored, so routes match whatever query they're sent.
+	///
+	/// Expansion loses information, so this can only guess where it's ambiguous: each variable gets one item, except
+	/// the last in an expression, which takes the rest, as a string unless it's exploded, e.g. `b,c` for `{a,b}` matching `a,b,c`.
+	pub fn matches(&self, uri: &Uri) -> Option<HashMap<String, TemplateValue>> {
+		let mut uri = uri.clone();
+		if !self.mentions("?") && !self.mentions("&") {
+			uri.query = None;
+		}
+		if !self.mentions("#") {
+			uri.fragment = None;
+		}
+
+		let mut variables = HashMap::new();
+		if match_parts(&self.parts, &uri.to_string(), &mut variables) {
+			Some(variables)
+		} else {
+			None
+		}
+	}
+
+	// Whether the template has somewhere to put `delimiter`, either in a literal or as an operator
+	fn mentions(&self, delimiter: &str) -> bool {
+		self.parts.iter().any(|p| match *p {
+			Part::Literal(ref literal) => literal.contains(delimiter),
+			Part::Expression(operator, _) => operator.first() == delimiter
+		})
+	}
+}
+
+impl fmt::Display for UriTemplate {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.raw)
+	}
+}
+
+impl TemplateValue {
+	fn is_defined(&self) -> bool {
+		match *self {
+			TemplateValue::String(_) => true,
+			TemplateValue::List(ref items) => !items.is_empty(),
+			TemplateValue::Map(ref pairs) => !pairs.is_empty()
+		}
+	}
+}
+
+impl<'a> From<&'a str> for TemplateValue {
+	fn from(value: &'a str) -> TemplateValue {
+		TemplateValue::String(value.to_string())
+	}
+}
+
+impl From<String> for TemplateValue {
+	fn from(value: String) -> TemplateValue {
+		TemplateValue::String(value)
+	}
+}
+
+impl fmt::Display for TemplateError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "invalid URI template at {}: {}", self.position, self.reason)
+	}
+}
+
+impl Error for TemplateError {}
+
+// What's between the braces, e.g. `?x,list*,name:3`
+fn parse_expression(expression: &str, offset: usize) -> result::Result<Part, TemplateError> {
+	let operator = expression.chars().next().and_then(Operator::from_char);
+	let mut start = if operator.is_some() { 1 } else { 0 };
+	if expression[start..].starts_with(['=', ',', '!', '@', '|']) {
+		return Err(TemplateError { position: offset, reason: "operator reserved for future extensions" });
+	}
+
+	let mut specs = Vec::new();
+	for spec in expression[start..].split(',') {
+		specs.push(parse_varspec(spec, offset + start)?);
+		start += spec.len() + 1;
+	}
+	Ok(Part::Expression(operator.unwrap_or(Operator::Simple), specs))
+}
+
+// `name`, `name*` or `name:3`
+fn parse_varspec(spec: &str, offset: usize) -> result::Result<VarSpec, TemplateError> {
+	let (name, modifier) = match spec.find([':', '*']) {
+		Some(ix) => (&spec[..ix], &spec[ix..]),
+		None => (spec, "")
+	};
+
+	let bytes = name.as_bytes();
+	let mut ix = 0;
+	while ix < bytes.len() {
+		let b = bytes[ix];
+		if b == b'%' && bytes.get(ix + 1..ix + 3).map(|h| h.iter().all(|b| b.is_ascii_hexdigit())).unwrap_or(false) {
+			ix += 3;
+			continue;
+		}
+		//dots only between other characters
+		let dot = b == b'.' && ix > 0 && ix + 1 < bytes.len() && bytes[ix + 1] != b'.';
+		if !(b.is_ascii_alphanumeric() || b == b'_' || dot) {
+			return Err(TemplateError { position: offset + ix, reason: "invalid character in a variable name" });
+		}
+		ix += 1;
+	}
+	if name.is_empty() {
+		return Err(TemplateError { position: offset, reason: "empty variable name" });
+	}
+
+	let position = offset + name.len();
+	let (prefix, explode) = match modifier {
+		"" => (None, false),
+		"*" => (None, true),
+		_ => {
+			let length = &modifier[1..];
+			match length.parse::<usize>() {
+				Ok(n) if (1..10000).contains(&n) && !length.starts_with('0') && length.bytes().all(|b| b.is_ascii_digit()) => (Some(n), false),
+				_ => return Err(TemplateError { position, reason: "a prefix must be from 1 to 9999 characters" })
+			}
+		}
+	};
+	Ok(VarSpec { name: name.to_string(), prefix, explode })
+}
+
+// RFC 6570 appendix A, a variable at a time
+fn expand_expression(operator: Operator, specs: &[VarSpec], variables: &HashMap<String, TemplateValue>, out: &mut String) {
+	let reserved = operator.allows_reserved();
+	let mut first = true;
+	for spec in specs {
+		let value = match variables.get(&spec.name) {
+			Some(value) if value.is_defined() => value,
+			_ => continue
+		};
+		out.push_str(if first { operator.first() } else { operator.separator() });
+		first = false;
+
+		match *value {
+			TemplateValue::String(ref s) => {
+				if operator.named() {
+					out.push_str(&spec.name);
+					if s.is_empty() {
+						out.push_str(operator.if_empty());
+						continue;
+					}
+					out.push('=');
+				}
+				match spec.prefix {
+					Some(n) => out.push_str(&encode(&s.chars().take(n).collect::<String>(), reserved)),
+					None => out.push_str(&encode(s, reserved))
+				}
+			},
+			TemplateValue::List(ref items) if spec.explode => {
+				for (ix, item) in items.iter().enumerate() {
+					if ix > 0 {
+						out.push_str(operator.separator());
+					}
+					push_named(operator, &spec.name, item, out);
+				}
+			},
+			TemplateValue::List(ref items) => {
+				if operator.named() {
+					out.push_str(&spec.name);
+					out.push('=');
+				}
+				out.push_str(&items.iter().map(|i| encode(i, reserved)).collect::<Vec<String>>().join(","));
+			},
+			TemplateValue::Map(ref pairs) if spec.explode => {
+				for (ix, (key, value)) in pairs.iter().enumerate() {
+					if ix > 0 {
+						out.push_str(operator.separator());
+					}
+					if operator.named() {
+						push_named(operator, &encode(key, reserved), value, out);
+					} else {
+						out.push_str(&format!("{}={}", encode(key, reserved), encode(value, reserved)));
+					}
+				}
+			},
+			TemplateValue::Map(ref pairs) => {
+				if operator.named() {
+					out.push_str(&spec.name);
+					out.push('=');
+				}
+				let pairs = pairs.iter().map(|(key, value)| format!("{},{}", encode(key, reserved), encode(value, reserved)));
+				out.push_str(&pairs.collect::<Vec<String>>().join(","));
+			}
+		}
+	}
+}
+
+// `name=value` for named operators, just the value for the rest
+fn push_named(operator: Operator, name: &str, value: &str, out: &mut String) {
+	if operator.named() {
+		out.push_str(name);
+		if value.is_empty() {
+			out.push_str(operator.if_empty());
+			return;
+		}
+		out.push('=');
+	}
+	out.push_str(&encode(value, operator.allows_reserved()));
+}
+
+// Only unreserved characters are kept, unless reserved ones are allowed too, in which case so are escapes already made
+fn encode(value: &str, reserved: bool) -> String {
+	let bytes = value.as_bytes();
+	let mut encoded = String::with_capacity(value.len());
+	let mut ix = 0;
+	while ix < bytes.len() {
+		let b = bytes[ix];
+		let escape = b == b'%' && bytes.get(ix + 1..ix + 3).map(|h| h.iter().all(|b| b.is_ascii_hexdigit())).unwrap_or(false);
+		if reserved && escape {
+			encoded.push_str(&value[ix..ix + 3]);
+			ix += 3;
+			continue;
+		}
+		if is_unreserved(b) || (reserved && is_reserved(b)) {
+			encoded.push(b as char);
+		} else {
+			encoded.push_str(&format!("%{:02X}", b));
+		}
+		ix += 1;
+	}
+	encoded
+}
+
+fn is_reserved(b: u8) -> bool {
+	is_sub_delim(b) || matches!(b, b':' | b'/' | b'?' | b'#' | b'[' | b']' | b'@')
+}
+
+// Whether the rest of the URI matches the rest of the template, trying the longest span an expression could have written first
+fn match_parts(parts: &[Part], text: &str, variables: &mut HashMap<String, TemplateValue>) -> bool {
+	let (part, rest) = match parts.split_first() {
+		Some(split) => split,
+		None => return text.is_empty()
+	};
+
+	match *part {
+		Part::Literal(ref literal) => text.starts_with(literal.as_str()) && match_parts(rest, &text[literal.len()..], variables),
+		Part::Expression(operator, ref specs) => {
+			let longest = text.bytes().take_while(|&b| operator.writes(b)).count();
+			for end in (0..=longest).rev() {
+				let mut captured = variables.clone();
+				if capture(operator, specs, &text[..end], &mut captured) && match_parts(rest, &text[end..], &mut captured) {
+					*variables = captured;
+					return true;
+				}
+			}
+			false
+		}
+	}
+}
+
+// Reads the values back out of what one expression expanded to
+fn capture(operator: Operator, specs: &[VarSpec], expanded: &str, variables: &mut HashMap<String, TemplateValue>) -> bool {
+	//every variable undefined
+	if expanded.is_empty() {
+		return true;
+	}
+	let body = match expanded.strip_prefix(operator.first()) {
+		Some(body) => body,
+		None => return false
+	};
+	let items: Vec<&str> = body.split(operator.separator()).collect();
+
+	let mut ix = 0;
+	for (n, spec) in specs.iter().enumerate() {
+		if ix == items.len() {
+			break;
+		}
+		let last = n + 1 == specs.len();
+
+		if operator.named() {
+			let named = |item: &str| item == spec.name || item.starts_with(&format!("{}=", spec.name));
+			if spec.explode && last {
+				let rest = &items[ix..];
+				let value = if rest.iter().all(|i| named(i)) {
+					TemplateValue::List(rest.iter().map(|i| value_of(i)).collect())
+				} else {
+					TemplateValue::Map(rest.iter().map(|i| pair_of(i)).collect())
+				};
+				variables.insert(spec.name.clone(), value);
+				ix = items.len();
+			} else if spec.explode {
+				let count = items[ix..].iter().take_while(|i| named(i)).count();
+				if count > 0 {
+					variables.insert(spec.name.clone(), TemplateValue::List(items[ix..ix + count].iter().map(|i| value_of(i)).collect()));
+				}
+				ix += count;
+			} else if named(items[ix]) {
+				variables.insert(spec.name.clone(), TemplateValue::String(value_of(items[ix])));
+				ix += 1;
+			}
+		} else if last {
+			let rest = &items[ix..];
+			let value = if !spec.explode {
+				TemplateValue::String(percent::decode(&rest.join(operator.separator())))
+			} else if rest.iter().all(|i| i.contains('=')) {
+				TemplateValue::Map(rest.iter().map(|i| pair_of(i)).collect())
+			} else {
+				TemplateValue::List(rest.iter().map(|i| percent::decode(i)).collect())
+			};
+			variables.insert(spec.name.clone(), value);
+			ix = items.len();
+		} else {
+			variables.insert(spec.name.clone(), TemplateValue::String(percent::decode(items[ix])));
+			ix += 1;
+		}
+	}
+	ix == items.len()
+}
+
+// What follows the `=` in `name=value`, or nothing for just `name`
+fn value_of(item: &str) -> String {
+	pair_of(item).1
+}
+
+fn pair_of(item: &str) -> (String, String) {
+	match item.find('=') {
+		Some(ix) => (percent::decode(&item[..ix]), percent::decode(&item[ix + 1..])),
+		None => (percent::decode(item), String::new())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// The variables the examples in RFC 6570 section 3.2 are expanded with
+	fn rfc_variables() -> HashMap<String, TemplateValue> {
+		let mut variables = HashMap::new();
+		for &(name, value) in &[
+			("var", "value"), ("hello", "Hello World!"), ("path", "/foo/bar"), ("empty", ""),
+			("x", "1024"), ("y", "768"), ("half", "50%"), ("who", "fred"), ("base", "http://example.com/home/"), ("v", "6")
+		] {
+			variables.insert(name.to_string(), TemplateValue::from(value));
+		}
+		variables.insert(String::from("list"), TemplateValue::List(vec!(String::from("red"), String::from("green"), String::from("blue"))));
+		variables.insert(String::from("keys"), TemplateValue::Map(vec!(
+			(String::from("semi"), String::from(";")), (String::from("dot"), String::from(".")), (String::from("comma"), String::from(","))
+		)));
+		variables.insert(String::from("empty_keys"), TemplateValue::Map(Vec::new()));
+		variables
+	}
+
+	fn expand(template: &str) -> String {
+		UriTemplate::new(template).expand(&rfc_variables())
+	}
+
+	#[test]
+	fn rfc_6570_levels_one_to_three() {
+		for &(template, expanded) in &[
+			("{var}", "value"),
+			("{hello}", "Hello%20World%21"),
+			("{half}", "50%25"),
+			("{+var}", "value"),
+			("{+hello}", "Hello%20World!"),
+			("{+path}/here", "/foo/bar/here"),
+			("here?ref={+path}", "here?ref=/foo/bar"),
+			("{#var}", "#value"),
+			("{#hello}", "#Hello%20World!"),
+			("map?{x,y}", "map?1024,768"),
+			("{x,hello,y}", "1024,Hello%20World%21,768"),
+			("{+x,hello,y}", "1024,Hello%20World!,768"),
+			("{+path,x}/here", "/foo/bar,1024/here"),
+			("{#x,hello,y}", "#1024,Hello%20World!,768"),
+			("X{.var}", "X.value"),
+			("X{.x,y}", "X.1024.768"),
+			("{/var}", "/value"),
+			("{/var,x}/here", "/value/1024/here"),
+			("{;x,y}", ";x=1024;y=768"),
+			("{;x,y,empty}", ";x=1024;y=768;empty"),
+			("{?x,y}", "?x=1024&y=768"),
+			("{?x,y,empty}", "?x=1024&y=768&empty="),
+			("?fixed=yes{&x}", "?fixed=yes&x=1024"),
+			("{&x,y,empty}", "&x=1024&y=768&empty="),
+			("{undef}{?undef}", "")
+		] {
+			assert_eq!(expand(template), expanded, "{}", template);
+		}
+	}
+
+	#[test]
+	fn rfc_6570_level_four() {
+		for &(template, expanded) in &[
+			("{var:3}", "val"),
+			("{var:30}", "value"),
+			("{list}", "red,green,blue"),
+			("{list*}", "red,green,blue"),
+			("{keys}", "semi,%3B,dot,.,comma,%2C"),
+			("{keys*}", "semi=%3B,dot=.,comma=%2C"),
+			("{+path:6}/here", "/foo/b/here"),
+			("{+keys*}", "semi=;,dot=.,comma=,"),
+			("{#keys}", "#semi,;,dot,.,comma,,"),
+			("X{.list*}", "X.red.green.blue"),
+			("X{.keys}", "X.semi,%3B,dot,.,comma,%2C"),
+			("{/var:1,var}", "/v/value"),
+			("{/list*}", "/red/green/blue"),
+			("{/list*,path:4}", "/red/green/blue/%2Ffoo"),
+			("{/keys*}", "/semi=%3B/dot=./comma=%2C"),
+			("{;hello:5}", ";hello=Hello"),
+			("{;list}", ";list=red,green,blue"),
+			("{;list*}", ";list=red;list=green;list=blue"),
+			("{;keys*}", ";semi=%3B;dot=.;comma=%2C"),
+			("{?var:3}", "?var=val"),
+			("{?list*}", "?list=red&list=green&list=blue"),
+			("{?keys}", "?keys=semi,%3B,dot,.,comma,%2C"),
+			("{?keys*}", "?semi=%3B&dot=.&comma=%2C"),
+			("{&list*}", "&list=red&list=green&list=blue"),
+			("{?empty_keys*}", ""),
+			("{base}index", "http%3A%2F%2Fexample.com%2Fhome%2Findex"),
+			("{+base}index", "http://example.com/home/index")
+		] {
+			assert_eq!(expand(template), expanded, "{}", template);
+		}
+	}
+
+	#[test]
+	fn invalid_templates_say_where() {
+		for &(template, position) in &[("/a/{id", 3), ("/a}", 2), ("{}", 1), ("{=x}", 1), ("{x:0}", 2), ("{x:10000}", 2), ("{a b}", 2), ("a b", 1), ("{.x.}", 3), ("%zz", 0)] {
+			assert_eq!(UriTemplate::parse(template).map_err(|e| e.position), Err(position), "{}", template);
+		}
+		assert_eq!(UriTemplate::new("/café/{id}").expand(&HashMap::new()), "/caf%C3%A9/");
+	}
+
+	#[test]
+	fn uris_match_the_template_they_were_expanded_from() {
+		let template = UriTemplate::new("/properties/{id}/photos{/photo}{?page,size}");
+		assert_eq!(template.variables(), vec!("id", "photo", "page", "size"));
+
+		let variables = template.matches(&Uri::new("/properties/42/photos/a%20b?page=2")).unwrap();
+		assert_eq!(variables.get("id"), Some(&TemplateValue::from("42")));
+		assert_eq!(variables.get("photo"), Some(&TemplateValue::from("a b")));
+		assert_eq!(variables.get("page"), Some(&TemplateValue::from("2")));
+		assert_eq!(variables.get("size"), None);
+		assert_eq!(template.expand(&variables), "/properties/42/photos/a%20b?page=2");
+
+		assert!(template.matches(&Uri::new("/properties/42/photos")).is_some());
+		assert!(template.matches(&Uri::new("/properties/42/videos")).is_none());
+		assert!(template.matches(&Uri::new("/properties/4/2/photos")).is_none());
+		assert!(template.matches(&Uri::new("/properties/42/photos?view=map")).is_none());
+	}
+
+	#[test]
+	fn composite_values_match_as_well_as_they_can() {
+		let variables = UriTemplate::new("/files{/path*}{?tags*}").matches(&Uri::new("/files/a/b?tags=x&tags=y")).unwrap();
+		assert_eq!(variables.get("path"), Some(&TemplateValue::List(vec!(String::from("a"), String::from("b")))));
+		assert_eq!(variables.get("tags"), Some(&TemplateValue::List(vec!(String::from("x"), String::from("y")))));
+
+		let variables = UriTemplate::new("/search{?q,filters*}").matches(&Uri::new("/search?q=x&beds=2&pets=")).unwrap();
+		assert_eq!(variables.get("filters"), Some(&TemplateValue::Map(vec!((String::from("beds"), String::from("2")), (String::from("pets"), String::new())))));
+
+		//a query the template has no place for is ignored, as is a fragment
+		let variables = UriTemplate::new("http://{host}/{+rest}").matches(&Uri::new("http://example.com/a/b,c?q#top")).unwrap();
+		assert_eq!(variables.get("host"), Some(&TemplateValue::from("example.com")));
+		assert_eq!(variables.get("rest"), Some(&TemplateValue::from("a/b,c")));
+		assert_eq!(UriTemplate::new("/{a,b}").matches(&Uri::new("/1,2,3")).unwrap().get("b"), Some(&TemplateValue::from("2,3")));
+	}
+}
@@ -0,0 +1,249 @@
+use std::fmt::{Display, Formatter, Result};
+use std::result;
+use std::str::Split;
+
+use error::{Component, UriError};
+use {check, is_path_char, is_query_char, is_reg_name_char, is_userinfo_char, split_path};
+use {Authority, HierarchicalPart, Host, Uri};
+
+// Where a component is in the string, as byte offsets
+type Span = (usize, usize);
+
+/// A URI reference borrowed from the string it was parsed from: it only notes where each component is,
+/// so parsing one allocates nothing. `to_uri` makes an owned `Uri` of it when one needs keeping.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UriRef<'a> {
+	raw: &'a str,
+	scheme: Option<Span>,
+	userinfo: Option<Span>,
+	//an empty span for an empty host, as in `file:///etc/hosts`; `None` without an authority
+	host: Option<Span>,
+	port: Option<u16>,
+	path: Span,
+	query: Option<Span>,
+	fragment: Option<Span>
+}
+
+impl<'a> UriRef<'a> {
+	/// Parses a URI reference as RFC 3986 defines it, as `Uri::parse` does.
+	pub fn parse(raw: &'a str) -> result::Result<UriRef<'a>, UriError> {
+		let (rest, fragment) = split_off(raw, b'#');
+		if let Some((start, end)) = fragment {
+			check(&raw[start..end], start, Component::Fragment, is_query_char)?;
+		}
+		let (rest, query) = split_off(rest, b'?');
+		if let Some((start, end)) = query {
+			check(&raw[start..end], start, Component::Query, is_query_char)?;
+		}
+
+		//a colon before any slash ends the scheme; later ones are part of the path
+		let mut offset = 0;
+		let mut scheme = None;
+		if let Some(colon) = rest.find(':') {
+			if !rest[..colon].contains('/') {
+				check_scheme(&rest[..colon])?;
+				scheme = Some((0, colon));
+				offset = colon + 1;
+			}
+		}
+
+		let mut uri = UriRef { raw, scheme, userinfo: None, host: None, port: None, path: (0, 0), query, fragment };
+		if rest[offset..].starts_with("//") {
+			let start = offset + 2;
+			let end = rest[start..].find('/').map(|ix| start + ix).unwrap_or(rest.len());
+			uri.parse_authority(start, end)?;
+			offset = end;
+		}
+
+		check(&rest[offset..], offset, Component::Path, is_path_char)?;
+		uri.path = (offset, rest.len());
+		Ok(uri)
+	}
+
+	fn parse_authority(&mut self, start: usize, end: usize) -> result::Result<(), UriError> {
+		let authority = &self.raw[start..end];
+
+		//userinfo can't contain an @, so the first one ends it
+		let host_start = match authority.find('@') {
+			Some(ix) => {
+				check(&authority[..ix], start, Component::Userinfo, is_userinfo_char)?;
+				self.userinfo = Some((start, start + ix));
+				start + ix + 1
+			},
+			None => start
+		};
+
+		//an IP literal has colons of its own, so only one after its closing bracket starts the port
+		let host_and_port = &self.raw[host_start..end];
+		let host_end = if host_and_port.starts_with('[') {
+			let close = host_and_port.find(']').ok_or(UriError::InvalidIpLiteral { position: host_start })?;
+			Host::parse(&host_and_port[..close + 1], host_start)?;
+			host_start + close + 1
+		} else {
+			let host_end = host_start + host_and_port.find(':').unwrap_or(host_and_port.len());
+			check(&self.raw[host_start..host_end], host_start, Component::Host, is_reg_name_char)?;
+			host_end
+		};
+		self.host = Some((host_start, host_end));
+
+		self.port = match self.raw[host_end..end].chars().next() {
+			None => None,
+			Some(':') => parse_port(&self.raw[host_end + 1..end], host_end + 1)?,
+			Some(c) => return Err(UriError::InvalidCharacter { position: host_end, component: Component::Host, character: c })
+		};
+		Ok(())
+	}
+
+	/// The whole reference, as it was written.
+	pub fn as_str(&self) -> &'a str {
+		self.raw
+	}
+
+	pub fn scheme(&self) -> Option<&'a str> {
+		self.slice(self.scheme)
+	}
+
+	pub fn has_authority(&self) -> bool {
+		self.host.is_some()
+	}
+
+	pub fn userinfo(&self) -> Option<&'a str> {
+		self.slice(self.userinfo)
+	}
+
+	/// The host as it was written, e.g. `example.com` or `[::1]`; `Host::parse` tells which kind it is.
+	pub fn host(&self) -> Option<&'a str> {
+		self.slice(self.host).filter(|h| !h.is_empty())
+	}
+
+	/// The port given, if any; an empty one, as in `http://host:/`, is none.
+	pub fn port(&self) -> Option<u16> {
+		self.port
+	}
+
+	/// The path as it was written, e.g. `/a/b%20c`.
+	pub fn path(&self) -> &'a str {
+		&self.raw[self.path.0..self.path.1]
+	}
+
+	/// The path's segments as they were written, after its leading slash if it has one.
+	pub fn segments(&self) -> Split<'a, char> {
+		let path = self.path();
+		path.strip_prefix('/').unwrap_or(path).split('/')
+	}
+
+	pub fn query(&self) -> Option<&'a str> {
+		self.slice(self.query)
+	}
+
+	pub fn fragment(&self) -> Option<&'a str> {
+		self.slice(self.fragment)
+	}
+
+	/// An owned copy, for keeping after the string it was parsed from is gone.
+	pub fn to_uri(&self) -> Uri {
+		let authority = self.host.map(|span| Authority {
+			userinfo: self.userinfo().map(|u| u.to_string()),
+			//already checked when parsed, so it can't fail now
+			host: Host::parse(&self.raw[span.0..span.1], span.0).unwrap_or(None),
+			port: self.port.map(|p| p as usize)
+		});
+
+		Uri {
+			scheme: self.scheme().map(|s| s.to_string()),
+			path: HierarchicalPart {
+				authority,
+				path_components: split_path(self.path()),
+				absolute_path: self.path().starts_with('/')
+			},
+			query: self.query().map(|q| q.to_string()),
+			fragment: self.fragment().map(|f| f.to_string())
+		}
+	}
+
+	fn slice(&self, span: Option<Span>) -> Option<&'a str> {
+		span.map(|(start, end)| &self.raw[start..end])
+	}
+}
+
+impl<'a> From<UriRef<'a>> for Uri {
+	fn from(uri: UriRef<'a>) -> Uri {
+		uri.to_uri()
+	}
+}
+
+impl<'a> Display for UriRef<'a> {
+	fn fmt(&self, f: &mut Formatter) -> Result {
+		f.write_str(self.raw)
+	}
+}
+
+// Splits at the first `delim`, giving where what follows it is
+fn split_off(s: &str, delim: u8) -> (&str, Option<Span>) {
+	match s.bytes().position(|b| b == delim) {
+		Some(ix) => (&s[..ix], Some((ix + 1, s.len()))),
+		None => (s, None)
+	}
+}
+
+pub(crate) fn check_scheme(scheme: &str) -> result::Result<(), UriError> {
+	for (ix, c) in scheme.char_indices() {
+		let valid = if ix == 0 { c.is_ascii_alphabetic() } else { c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.' };
+		if !valid {
+			return Err(UriError::InvalidCharacter { position: ix, component: Component::Scheme, character: c });
+		}
+	}
+
+	if scheme.is_empty() {
+		Err(UriError::EmptyScheme)
+	} else {
+		Ok(())
+	}
+}
+
+// An empty port is allowed, and means the scheme's default
+fn parse_port(port: &str, offset: usize) -> result::Result<Option<u16>, UriError> {
+	if let Some((ix, c)) = port.char_indices().find(|&(_, c)| !c.is_ascii_digit()) {
+		return Err(UriError::InvalidCharacter { position: offset + ix, component: Component::Port, character: c });
+	}
+	if port.is_empty() {
+		return Ok(None);
+	}
+
+	match port.parse::<u16>() {
+		Ok(port) => Ok(Some(port)),
+		Err(_) => Err(UriError::PortOutOfRange { position: offset })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn components_are_borrowed_from_the_input() {
+		let raw = String::from("http://user@[::1]:8080/a/b%20c?q=1#top");
+		let uri = UriRef::parse(&raw).unwrap();
+		assert_eq!(uri.scheme(), Some("http"));
+		assert_eq!(uri.userinfo(), Some("user"));
+		assert_eq!(uri.host(), Some("[::1]"));
+		assert_eq!(uri.port(), Some(8080));
+		assert_eq!(uri.path(), "/a/b%20c");
+		assert_eq!(uri.segments().collect::<Vec<&str>>(), vec!("a", "b%20c"));
+		assert_eq!(uri.query(), Some("q=1"));
+		assert_eq!(uri.fragment(), Some("top"));
+		assert_eq!(uri.to_string(), raw);
+
+		let origin = UriRef::parse("/a?").unwrap();
+		assert_eq!((origin.scheme(), origin.has_authority(), origin.query(), origin.fragment()), (None, false, Some(""), None));
+		assert_eq!(UriRef::parse("file:///etc/hosts").unwrap().host(), None);
+	}
+
+	#[test]
+	fn owned_copies_keep_every_component() {
+		for raw in &["http://user@example.com:8080/a/b?q=1#top", "/a/b?q", "../g;x?y#s", "mailto:someone@example.com", "http://[fe80::1%25eth0]/", ""] {
+			assert_eq!(Uri::from(UriRef::parse(raw).unwrap()).to_string(), *raw);
+		}
+		assert_eq!(UriRef::parse("http://host:65536/"), Err(UriError::PortOutOfRange { position: 12 }));
+	}
+}
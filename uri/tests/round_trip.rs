@@ -0,0 +1,151 @@
+extern crate proptest;
+extern crate uri;
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use proptest::collection::vec;
+use proptest::option;
+use proptest::prelude::*;
+use proptest::string::string_regex;
+use uri::{Authority, HierarchicalPart, Host, Uri};
+
+// A component as it would be written: allowed characters and escapes, plus `extra`, which only some components allow.
+// Escaped delimiters get an alternative of their own, since they'd be rare among all the escapes.
+fn component(extra: &str, max: usize) -> impl Strategy<Value = String> {
+	string_regex(&format!("([a-zA-Z0-9._~!$&'()*+,;={}-]|%[0-9A-F]{{2}}|%(2[1-9A-F]|3[ABDF]|40|5[BD])){{0,{}}}", extra, max)).unwrap()
+}
+
+fn schemes() -> impl Strategy<Value = String> {
+	"[a-zA-Z][a-zA-Z0-9+.-]{0,6}"
+}
+
+fn hosts() -> impl Strategy<Value = Host> {
+	prop_oneof![
+		component("", 12).prop_filter_map("a name", |name| match name.parse::<Ipv4Addr>() {
+			Ok(_) => None,
+			Err(_) if name.is_empty() => None,
+			Err(_) => Some(Host::Domain(name))
+		}),
+		any::<[u8; 4]>().prop_map(|octets| Host::Ipv4(Ipv4Addr::from(octets))),
+		(any::<[u16; 8]>(), option::of("\\PC{1,6}")).prop_map(|(groups, zone)| Host::Ipv6(Ipv6Addr::from(groups), zone)),
+		"[vV][0-9a-fA-F]{1,2}\\.[a-zA-Z0-9:!$&'()*+,;=._~-]{1,8}".prop_map(Host::IpvFuture)
+	]
+}
+
+fn authorities() -> impl Strategy<Value = Authority> {
+	prop_oneof![
+		//`//`, with nothing in it at all
+		Just(Authority { userinfo: None, host: None, port: None }),
+		(option::of(component(":", 8)), option::of(hosts()), option::of(any::<u16>()))
+			.prop_map(|(userinfo, host, port)| Authority { userinfo, host, port: port.map(usize::from) })
+	]
+}
+
+// Put together field by field, kept to what a URI reference can be, which is what `Display` promises to round-trip
+fn uris() -> impl Strategy<Value = Uri> {
+	(
+		option::of(schemes()),
+		option::of(authorities()),
+		any::<bool>(),
+		vec(component(":@", 6), 1..5),
+		option::of(component(":@/?", 10)),
+		option::of(component(":@/?", 10))
+	).prop_filter("not a URI reference", |&(ref scheme, ref authority, absolute_path, ref path_components, _, _)| {
+		let first = path_components[0].as_str();
+		let empty = path_components.len() == 1 && first.is_empty();
+		match *authority {
+			//a path after a host is empty or starts with /
+			Some(_) => absolute_path || empty,
+			//a path that isn't would read as an authority after //, or as the rest of a relative path after /
+			None if absolute_path => !first.is_empty() || path_components.len() == 1,
+			//a colon in the first segment would read as the end of a scheme
+			None => (empty || !first.is_empty()) && (scheme.is_some() || !first.contains(':'))
+		}
+	}).prop_map(|(scheme, authority, absolute_path, path_components, query, fragment)| Uri {
+		scheme,
+		path: HierarchicalPart { authority, path_components, absolute_path },
+		query,
+		fragment
+	})
+}
+
+// Written out as `Display` would write them: IP addresses in their usual form, zones without escapes, since they're
+// kept decoded, and no empty port after a colon
+fn uri_strings() -> impl Strategy<Value = String> {
+	let host = prop_oneof![
+		component("", 12),
+		any::<[u8; 4]>().prop_map(|octets| Ipv4Addr::from(octets).to_string()),
+		(any::<[u16; 8]>(), option::of("[a-zA-Z0-9._~-]{1,4}"))
+			.prop_map(|(groups, zone)| format!("[{}{}]", Ipv6Addr::from(groups), zone.map(|z| format!("%25{}", z)).unwrap_or_default())),
+		"\\[[vV][0-9a-fA-F]{1,2}\\.[a-zA-Z0-9:!$&'()*+,;=._~-]{1,8}\\]"
+	];
+	let authority = (option::of(component(":", 8)), host, option::of(any::<u16>()))
+		.prop_map(|(userinfo, host, port)| {
+			format!("//{}{}{}", userinfo.map(|u| format!("{}@", u)).unwrap_or_default(), host, port.map(|p| format!(":{}", p)).unwrap_or_default())
+		});
+
+	(
+		option::of(schemes()),
+		option::of(authority),
+		option::of(Just("/")),
+		vec(component(":@", 6), 0..4),
+		option::of(component(":@/?", 10)),
+		option::of(component(":@/?", 10))
+	).prop_map(|(scheme, authority, slash, segments, query, fragment)| {
+		let mut uri = String::new();
+		if let Some(scheme) = scheme {
+			uri.push_str(&format!("{}:", scheme));
+		}
+		let path = format!("{}{}", slash.unwrap_or(""), segments.join("/"));
+		//a path after a host has to start with /, or it would read as part of the host
+		let separator = if authority.is_some() && !path.is_empty() && !path.starts_with('/') { "/" } else { "" };
+		uri.push_str(&authority.unwrap_or_default());
+		uri.push_str(separator);
+		uri.push_str(&path);
+		if let Some(query) = query {
+			uri.push_str(&format!("?{}", query));
+		}
+		if let Some(fragment) = fragment {
+			uri.push_str(&format!("#{}", fragment));
+		}
+		uri
+	}).prop_filter("not a URI reference", |uri| Uri::parse(uri).is_ok())
+}
+
+proptest! {
+	#[test]
+	fn uris_format_to_what_parses_back_to_them(uri in uris()) {
+		let formatted = uri.to_string();
+		prop_assert_eq!(Uri::parse(&formatted), Ok(uri.clone()), "{}", formatted);
+		prop_assert_eq!(Uri::new(&formatted), uri);
+	}
+
+	#[test]
+	fn written_uris_parse_to_what_formats_back_to_them(raw in uri_strings()) {
+		prop_assert_eq!(Uri::new(&raw).to_string(), raw);
+	}
+
+	#[test]
+	fn parts_parse_back_from_what_they_display(uri in uris()) {
+		if let Some(ref authority) = uri.path.authority {
+			prop_assert_eq!(authority.to_string().parse::<Authority>(), Ok(authority.clone()));
+		}
+		prop_assert_eq!(uri.path.to_string().parse::<HierarchicalPart>(), Ok(uri.path.clone()));
+	}
+
+	#[test]
+	fn parsed_uris_format_stably(raw in "[a-zA-Z0-9:/?#\\[\\]@!$&'()*+,;=%._~ -]{0,40}") {
+		if let Ok(uri) = Uri::parse(&raw) {
+			let formatted = uri.to_string();
+			let reparsed = Uri::parse(&formatted);
+			prop_assert_eq!(reparsed.as_ref(), Ok(&uri), "{}", formatted);
+			prop_assert_eq!(reparsed.unwrap().to_string(), formatted);
+		}
+	}
+
+	#[test]
+	fn normalizing_twice_changes_nothing(uri in uris()) {
+		let normalized = uri.normalize();
+		prop_assert_eq!(normalized.normalize(), normalized.clone(), "{}", normalized);
+	}
+}
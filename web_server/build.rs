@@ -2,8 +2,15 @@ use std::env;
 use std::fs;
 use std::path::Path;
 use std::io;
+use std::io::Write;
+
+#[path = "src/templates/parser.rs"]
+#[allow(dead_code)]
+mod parser;
 
 fn main() {
+	compile_views();
+
 	// let args: Vec<String> = env::args().collect();
 	// println!("{}", args);
 
@@ -42,4 +49,43 @@ fn copy_dir_contents(out_dir: &Path, src_dir: &Path) -> io::Result<()> {
 	}
 
 	Ok(())
-}
\ No newline at end of file
+}
+
+// Parses every view so template errors fail the build, and embeds their sources for TemplateEngine::embedded
+fn compile_views() {
+	println!("cargo:rerun-if-changed=views");
+	println!("cargo:rerun-if-changed=scripts");
+	println!("cargo:rerun-if-changed=src/templates/parser.rs");
+
+	let mut views = Vec::new();
+	collect_views(Path::new("views"), "", &mut views).unwrap();
+
+	let out_file = Path::new(&env::var("OUT_DIR").unwrap()).join("views.rs");
+	let mut out = fs::File::create(out_file).unwrap();
+
+	writeln!(out, "pub static VIEWS: &'static [(&'static str, &'static str)] = &[").unwrap();
+	for (name, path) in views {
+		let source = fs::read_to_string(&path).unwrap();
+		if let Err(e) = parser::parse(&source) {
+			panic!("template {} failed to compile: {}", name, e);
+		}
+
+		let path = fs::canonicalize(&path).unwrap();
+		writeln!(out, "\t({:?}, include_str!({:?})),", name, path).unwrap();
+	}
+	writeln!(out, "];").unwrap();
+}
+
+fn collect_views(dir: &Path, prefix: &str, views: &mut Vec<(String, std::path::PathBuf)>) -> io::Result<()> {
+	for entry in fs::read_dir(dir)? {
+		let entry = entry?.path();
+		let name = format!("{}{}", prefix, entry.file_name().unwrap().to_str().unwrap());
+		if entry.is_dir() {
+			collect_views(&entry, &format!("{}/", name), views)?;
+		} else {
+			views.push((name, entry));
+		}
+	}
+
+	Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,207 @@
+mod password;
+mod users;
+
+pub use self::password::{hash_password, verify_password};
+pub use self::users::{User, UserStore, MemoryUserStore};
+
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use encoding::{to_hex, from_base64};
+use http::model::HttpRequest;
+use session::{Session, SessionManager};
+
+const SESSION_USER_KEY: &str = "user";
+
+pub enum Credentials {
+	Basic { username: String, password: String },
+	Bearer(String)
+}
+
+impl Credentials {
+	/// Reads Basic or Bearer credentials from the Authorization header.
+	pub fn from_request(request: &HttpRequest) -> Option<Credentials> {
+		let header = request.headers.first("Authorization")?;
+		let (scheme, value) = match header.find(' ') {
+			Some(ix) => (&header[..ix], header[ix + 1..].trim()),
+			None => return None
+		};
+
+		if scheme.eq_ignore_ascii_case("Basic") {
+			let decoded = String::from_utf8(from_base64(value)?).ok()?;
+			let ix = decoded.find(':')?;
+			Some(Credentials::Basic {
+				username: decoded[..ix].to_string(),
+				password: decoded[ix + 1..].to_string()
+			})
+		} else if scheme.eq_ignore_ascii_case("Bearer") {
+			Some(Credentials::Bearer(value.to_string()))
+		} else {
+			None
+		}
+	}
+}
+
+pub struct Authenticator {
+	users: RwLock<Box<dyn UserStore>>,
+	//bearer tokens are kept as sha-256 digests, mapped to the username they were issued to
+	tokens: Mutex<HashMap<String, String>>,
+	//checked against when the username is unknown, so both failures take as long
+	dummy_hash: String,
+	random: SystemRandom
+}
+
+impl Authenticator {
+	pub fn new(users: Box<dyn UserStore>) -> Result<Authenticator, String> {
+		Ok(Authenticator {
+			users: RwLock::new(users),
+			tokens: Mutex::new(HashMap::new()),
+			dummy_hash: hash_password("")?,
+			random: SystemRandom::new()
+		})
+	}
+
+	/// The user making the request: from the Authorization header if one was sent, otherwise from the session.
+	pub fn authenticate(&self, request: &HttpRequest, session: &Session) -> Option<User> {
+		match Credentials::from_request(request) {
+			Some(Credentials::Basic { username, password }) => self.check_password(&username, &password),
+			Some(Credentials::Bearer(token)) => {
+				let username = self.tokens.lock().unwrap().get(&token_digest(&token)).cloned();
+				username.and_then(|u| self.users.read().unwrap().find(&u))
+			},
+			None => session.get(SESSION_USER_KEY).and_then(|u| self.users.read().unwrap().find(u))
+		}
+	}
+
+	pub fn check_password(&self, username: &str, password: &str) -> Option<User> {
+		let user = self.users.read().unwrap().find(username);
+		match user {
+			Some(user) => {
+				if verify_password(password, &user.password_hash) { Some(user) } else { None }
+			},
+			None => {
+				verify_password(password, &self.dummy_hash);
+				None
+			}
+		}
+	}
+
+	/// Checks the password and ties the user to the session, under a new session id.
+	pub fn login(&self, sessions: &SessionManager, session: &mut Session, username: &str, password: &str) -> Option<User> {
+		let user = self.check_password(username, password)?;
+		sessions.regenerate(session);
+		session.set(SESSION_USER_KEY, &user.username);
+		Some(user)
+	}
+
+	/// Swaps in another set of users, e.g. once the users file has been edited. Sessions and tokens are kept,
+	/// but only let in users the new store still has.
+	pub fn replace_users(&self, users: Box<dyn UserStore>) {
+		*self.users.write().unwrap() = users;
+	}
+
+	pub fn logout(&self, session: &mut Session) {
+		session.destroy();
+	}
+
+	/// Creates a bearer token for API clients. Only its digest is kept, so it can't be shown again.
+	pub fn issue_token(&self, user: &User) -> Result<String, String> {
+		let mut token = [0_u8; 32];
+		if self.random.fill(&mut token).is_err() {
+			return Err(String::from("Unable to generate a token"));
+		}
+
+		let token = to_hex(&token);
+		self.tokens.lock().unwrap().insert(token_digest(&token), user.username.clone());
+		Ok(token)
+	}
+
+	pub fn revoke_token(&self, token: &str) -> bool {
+		self.tokens.lock().unwrap().remove(&token_digest(token)).is_some()
+	}
+}
+
+fn token_digest(token: &str) -> String {
+	to_hex(digest::digest(&digest::SHA256, token.as_bytes()).as_ref())
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use http::model::*;
+	use session::MemorySessionStore;
+	use routing::Routable;
+
+	fn authenticator() -> Authenticator {
+		let users = MemoryUserStore::new();
+		users.save(User {
+			username: String::from("luke"),
+			password_hash: hash_password("hunter2").unwrap(),
+			roles: vec!(String::from("admin"))
+		});
+		Authenticator::new(Box::new(users)).unwrap()
+	}
+
+	fn request(authorization: Option<&str>) -> HttpRequest {
+		let mut headers = HeaderCollection::init_empty();
+		if let Some(a) = authorization {
+			headers.get_or_add("Authorization").add(a.to_string());
+		}
+		HttpRequest { start_line: Routable::get(&"/"), headers, body: Vec::new() }
+	}
+
+	#[test]
+	fn basic_and_bearer_credentials() {
+		let auth = authenticator();
+		let sessions = SessionManager::new(Box::new(MemorySessionStore::new()), ::http::model::CookieKey::new(b"k"));
+		let session = sessions.load(&request(None));
+
+		//luke:hunter2
+		let user = auth.authenticate(&request(Some("Basic bHVrZTpodW50ZXIy")), &session).unwrap();
+		assert!(user.has_role("admin"));
+		assert!(auth.authenticate(&request(Some("Basic bHVrZTpodW50ZXIz")), &session).is_none());
+
+		let token = auth.issue_token(&user).unwrap();
+		assert!(auth.authenticate(&request(Some(&format!("Bearer {}", token))), &session).is_some());
+		assert!(auth.revoke_token(&token));
+		assert!(auth.authenticate(&request(Some(&format!("Bearer {}", token))), &session).is_none());
+	}
+
+	// The session cookie the response sets, the way the client sends it back
+	fn session_cookie(sessions: &SessionManager, session: &Session) -> String {
+		let mut response = HttpResponse::new(StatusCode::ok());
+		sessions.commit(session, &mut response);
+		let set_cookie = response.headers.first("Set-Cookie").unwrap();
+		set_cookie.split(';').next().unwrap().to_string()
+	}
+
+	fn with_cookie(cookie: &str) -> HttpRequest {
+		let mut request = request(None);
+		request.headers.get_or_add("Cookie").add(cookie.to_string());
+		request
+	}
+
+	#[test]
+	fn login_changes_session_id() {
+		let auth = authenticator();
+		let sessions = SessionManager::new(Box::new(MemorySessionStore::new()), ::http::model::CookieKey::new(b"k"));
+		let mut session = sessions.load(&request(None));
+		session.set("theme", "dark");
+		let anonymous = session_cookie(&sessions, &session);
+
+		assert!(auth.login(&sessions, &mut session, "luke", "wrong").is_none());
+		assert!(auth.login(&sessions, &mut session, "luke", "hunter2").is_some());
+		let signed_in = session_cookie(&sessions, &session);
+		assert_ne!(signed_in, anonymous);
+		assert_eq!(auth.authenticate(&request(None), &session).map(|u| u.username), Some(String::from("luke")));
+
+		//the cookie from before login no longer leads to the session
+		assert!(sessions.load(&with_cookie(&anonymous)).get("theme").is_none());
+		let reloaded = sessions.load(&with_cookie(&signed_in));
+		assert_eq!(reloaded.get("theme"), Some("dark"));
+		assert_eq!(auth.authenticate(&request(None), &reloaded).map(|u| u.username), Some(String::from("luke")));
+	}
+}
@@ -0,0 +1,66 @@
+use std::num::NonZeroU32;
+use ring::{digest, pbkdf2};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use encoding::{to_hex, from_hex};
+
+// Stored hashes look like pbkdf2-sha256$<iterations>$<salt hex>$<hash hex>, so the work factor can be raised
+// later without invalidating existing passwords
+const SCHEME: &str = "pbkdf2-sha256";
+const ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = digest::SHA256_OUTPUT_LEN;
+
+pub fn hash_password(password: &str) -> Result<String, String> {
+	let mut salt = [0_u8; SALT_LEN];
+	if SystemRandom::new().fill(&mut salt).is_err() {
+		return Err(String::from("Unable to generate a password salt"));
+	}
+
+	Ok(hash_with_salt(password, &salt, ITERATIONS))
+}
+
+pub fn verify_password(password: &str, stored_hash: &str) -> bool {
+	let parts: Vec<&str> = stored_hash.split('$').collect();
+	if parts.len() != 4 || parts[0] != SCHEME {
+		return false;
+	}
+
+	let iterations = match parts[1].parse::<u32>().ok().and_then(NonZeroU32::new) {
+		Some(i) => i,
+		None => return false
+	};
+
+	match (from_hex(parts[2]), from_hex(parts[3])) {
+		(Some(salt), Some(hash)) => {
+			pbkdf2::verify(pbkdf2::PBKDF2_HMAC_SHA256, iterations, &salt, password.as_bytes(), &hash).is_ok()
+		},
+		_ => false
+	}
+}
+
+fn hash_with_salt(password: &str, salt: &[u8], iterations: u32) -> String {
+	let mut hash = [0_u8; HASH_LEN];
+	let iterations = NonZeroU32::new(iterations).expect("iterations must be greater than 0");
+	pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, password.as_bytes(), &mut hash);
+
+	format!("{}${}${}${}", SCHEME, iterations, to_hex(salt), to_hex(&hash))
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn hashes_are_salted_and_verify() {
+		let first = hash_with_salt("hunter2", b"salt-one", 10);
+		let second = hash_with_salt("hunter2", b"salt-two", 10);
+		assert_ne!(first, second);
+
+		assert!(verify_password("hunter2", &first));
+		assert!(verify_password("hunter2", &second));
+		assert!(!verify_password("hunter3", &first));
+		assert!(!verify_password("hunter2", "md5$1$00$00"));
+	}
+}
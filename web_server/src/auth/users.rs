@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::RwLock;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct User {
+	pub username: String,
+	pub password_hash: String,
+	pub roles: Vec<String>
+}
+
+impl User {
+	pub fn has_role(&self, role: &str) -> bool {
+		self.roles.iter().any(|r| r == role)
+	}
+}
+
+//TODO: add a store backed by the database crate
+pub trait UserStore: Send + Sync {
+	fn find(&self, username: &str) -> Option<User>;
+	fn save(&self, user: User);
+}
+
+pub struct MemoryUserStore {
+	users: RwLock<HashMap<String, User>>
+}
+
+impl MemoryUserStore {
+	pub fn new() -> MemoryUserStore {
+		MemoryUserStore {
+			users: RwLock::new(HashMap::new())
+		}
+	}
+
+	/// Loads users from a file of `username:password hash:role,role` lines. Blank lines and lines starting with # are skipped.
+	pub fn from_file<P: AsRef<Path>>(path: P) -> Result<MemoryUserStore, String> {
+		let contents = match fs::read_to_string(path.as_ref()) {
+			Ok(c) => c,
+			Err(e) => return Err(format!("Unable to read users file {}: {}", path.as_ref().display(), e))
+		};
+
+		let store = MemoryUserStore::new();
+		for (ix, line) in contents.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let fields: Vec<&str> = line.splitn(3, ':').collect();
+			if fields.len() < 2 || fields[0].is_empty() {
+				return Err(format!("Invalid user entry on line {}", ix + 1));
+			}
+
+			store.save(User {
+				username: fields[0].to_string(),
+				password_hash: fields[1].to_string(),
+				roles: fields.get(2).map(|r| r.split(',').map(|r| r.trim().to_string()).filter(|r| !r.is_empty()).collect()).unwrap_or_default()
+			});
+		}
+
+		Ok(store)
+	}
+}
+
+impl UserStore for MemoryUserStore {
+	fn find(&self, username: &str) -> Option<User> {
+		self.users.read().unwrap().get(username).cloned()
+	}
+
+	fn save(&self, user: User) {
+		self.users.write().unwrap().insert(user.username.clone(), user);
+	}
+}
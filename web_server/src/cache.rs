@@ -0,0 +1,549 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use ring::digest;
+
+use encoding::to_hex;
+use http::model::*;
+
+//kept from a 304 when it refreshes a stored response
+const REFRESHED: [&str; 5] = ["Cache-Control", "ETag", "Expires", "Last-Modified", "Vary"];
+
+/// Keeps GET responses that say they may be kept, e.g. `Cache-Control: public, max-age=600`, and answers
+/// later requests for the same URI with them while they're fresh. Stale ones are revalidated with a conditional request.
+pub struct ResponseCache {
+	store: Mutex<Store>,
+	max_bytes: usize
+}
+
+struct Store {
+	//by URI, one entry for each set of values the request headers named in Vary took
+	entries: HashMap<String, Vec<Entry>>,
+	bytes: usize,
+	//bumped on every use, so the least recently used entry has the lowest
+	clock: u64
+}
+
+struct Entry {
+	vary: Vec<(String, Option<String>)>,
+	response: HttpResponse,
+	stored: Instant,
+	ttl: Duration,
+	size: usize,
+	last_used: u64
+}
+
+// What a stored response can be used for
+enum Lookup {
+	Fresh(HttpResponse, Duration),
+	Stale(HttpResponse),
+	Miss
+}
+
+impl ResponseCache {
+	/// A cache holding at most `max_bytes` of responses, evicting the least recently used first.
+	pub fn new(max_bytes: usize) -> ResponseCache {
+		ResponseCache {
+			store: Mutex::new(Store {
+				entries: HashMap::new(),
+				bytes: 0,
+				clock: 0
+			}),
+			max_bytes
+		}
+	}
+
+	/// Answers a request from the cache if it can, otherwise from `handler`, keeping the response if it may be kept.
+	/// `handler` may be given a conditional version of the request when a stale response is revalidated.
+	/// `personal` is for handlers that only answer signed-in users: their responses are only kept if they say they're public.
+	pub fn respond<F>(&self, request: &HttpRequest, personal: bool, mut handler: F) -> HttpResponse
+		where F: FnMut(&HttpRequest) -> HttpResponse {
+		let key = cache_key(request);
+
+		if request.start_line.method != AllowedMethod::GET {
+			let response = handler(request);
+			//whatever was kept for a resource is out of date once it has been changed, and so are the ones under it,
+			//e.g. the rooms of a property that was deleted
+			if !request.start_line.method.is_safe() && response.status_line.status_code().get_code() < 400 {
+				self.invalidate_prefix(&key);
+				if let Some(location) = response.headers.first("Location").filter(|l| l.starts_with('/')) {
+					self.invalidate(location);
+				}
+			}
+			return response;
+		}
+
+		let directives = Directives::parse(&request.headers);
+		if directives.no_store {
+			return handler(request);
+		}
+
+		let stale = match self.lookup(&key, request, directives.no_cache || directives.max_age == Some(0)) {
+			Lookup::Fresh(response, age) => return answer(request, response, Some(age)),
+			Lookup::Stale(stored) => stored,
+			Lookup::Miss => {
+				let response = handler(request);
+				self.store(key, request, personal, &response);
+				return answer(request, response, None);
+			}
+		};
+
+		let mut conditional = request.clone();
+		conditional.headers.remove("If-None-Match");
+		conditional.headers.remove("If-Modified-Since");
+		if let Some(etag) = stale.headers.first("ETag") {
+			conditional.headers.get_or_add("If-None-Match").add(etag.to_string());
+		}
+		if let Some(modified) = stale.headers.first("Last-Modified") {
+			conditional.headers.get_or_add("If-Modified-Since").add(modified.to_string());
+		}
+
+		let response = handler(&conditional);
+		let unchanged = response.status_line.status_code() == StatusCode::not_modified()
+			|| (response.status_line.status_code() == StatusCode::ok() && etag(&response).is_some() && etag(&response) == etag(&stale));
+		if !unchanged {
+			self.store(key, request, personal, &response);
+			return answer(request, response, None);
+		}
+
+		let mut refreshed = stale;
+		for name in REFRESHED.iter() {
+			if let Some(header) = response.headers.get(name) {
+				refreshed.headers.remove(name);
+				for value in header.values() {
+					refreshed.headers.get_or_add(name).add(value.clone());
+				}
+			}
+		}
+		self.store(key, request, personal, &refreshed);
+		answer(request, refreshed, None)
+	}
+
+	/// Drops everything kept for a path, whatever its query, e.g. after the handler changed what it serves.
+	pub fn invalidate(&self, path: &str) {
+		let path = path.split('?').next().unwrap_or("");
+		self.remove_where(|key| key.split('?').next() == Some(path));
+	}
+
+	/// Drops everything kept for paths under `prefix`, e.g. `/properties` for `/properties/12` too, whatever their query.
+	pub fn invalidate_prefix(&self, prefix: &str) {
+		let prefix = prefix.split('?').next().unwrap_or("").trim_end_matches('/');
+		self.remove_where(|key| {
+			let path = key.split('?').next().unwrap_or("");
+			path == prefix || path.starts_with(&format!("{}/", prefix))
+		});
+	}
+
+	/// Drops everything, e.g. once the views it was rendered from have changed.
+	pub fn clear(&self) {
+		self.remove_where(|_| true);
+	}
+
+	fn lookup(&self, key: &str, request: &HttpRequest, revalidate: bool) -> Lookup {
+		let mut store = self.store.lock().unwrap();
+		store.clock += 1;
+		let clock = store.clock;
+
+		let entry = match store.entries.get_mut(key).and_then(|variants| variants.iter_mut().find(|e| e.matches(request))) {
+			Some(entry) => entry,
+			None => return Lookup::Miss
+		};
+		entry.last_used = clock;
+
+		let age = entry.stored.elapsed();
+		if age < entry.ttl && !revalidate {
+			Lookup::Fresh(entry.response.clone(), age)
+		} else if etag(&entry.response).is_some() || entry.response.headers.get("Last-Modified").is_some() {
+			Lookup::Stale(entry.response.clone())
+		} else {
+			Lookup::Miss
+		}
+	}
+
+	fn store(&self, key: String, request: &HttpRequest, personal: bool, response: &HttpResponse) {
+		let ttl = match storable(request, personal, response) {
+			Some(ttl) => ttl,
+			None => return
+		};
+		let size = key.len() + response.head_bytes().len() + response.body.len();
+		if size > self.max_bytes {
+			return;
+		}
+
+		let vary_names: Vec<&str> = response.headers.get("Vary")
+			.map(|h| h.values().iter().flat_map(|v| v.split(',')).map(|n| n.trim()).filter(|n| !n.is_empty()).collect())
+			.unwrap_or_default();
+		let vary = vary_names.into_iter()
+			.map(|name| (name.to_string(), request.headers.get(name).map(|h| h.values().join(", "))))
+			.collect();
+
+		let mut store = self.store.lock().unwrap();
+		store.clock += 1;
+		let entry = Entry {
+			vary,
+			response: response.clone(),
+			stored: Instant::now(),
+			ttl,
+			size,
+			last_used: store.clock
+		};
+
+		let removed = match store.entries.get_mut(&key) {
+			Some(variants) => match variants.iter().position(|e| e.vary == entry.vary) {
+				Some(ix) => variants.remove(ix).size,
+				None => 0
+			},
+			None => 0
+		};
+		store.bytes -= removed;
+
+		while store.bytes + size > self.max_bytes {
+			store.evict_least_recent();
+		}
+		store.bytes += size;
+		store.entries.entry(key).or_default().push(entry);
+	}
+
+	fn remove_where<F: Fn(&str) -> bool>(&self, matches: F) {
+		let mut store = self.store.lock().unwrap();
+		let keys: Vec<String> = store.entries.keys().filter(|k| matches(k)).cloned().collect();
+		for key in keys {
+			if let Some(variants) = store.entries.remove(&key) {
+				store.bytes -= variants.iter().map(|e| e.size).sum::<usize>();
+			}
+		}
+	}
+}
+
+impl Store {
+	fn evict_least_recent(&mut self) {
+		let oldest = self.entries.iter()
+			.flat_map(|(key, variants)| variants.iter().enumerate().map(move |(ix, e)| (e.last_used, key, ix)))
+			.min()
+			.map(|(_, key, ix)| (key.clone(), ix));
+
+		if let Some((key, ix)) = oldest {
+			let variants = self.entries.get_mut(&key).unwrap();
+			self.bytes -= variants.remove(ix).size;
+			if variants.is_empty() {
+				self.entries.remove(&key);
+			}
+		}
+	}
+}
+
+impl Entry {
+	// Whether the request sent the same values for the headers the response varies on
+	fn matches(&self, request: &HttpRequest) -> bool {
+		self.vary.iter().all(|(name, value)| request.headers.get(name).map(|h| h.values().join(", ")) == *value)
+	}
+}
+
+/// A strong validator for a body, e.g. for a handler to send as ETag.
+pub fn etag_for(body: &[u8]) -> String {
+	let hash = digest::digest(&digest::SHA256, body);
+	format!("\"{}\"", to_hex(&hash.as_ref()[..16]))
+}
+
+/// Whether a client already has the response, going by the validators it sent.
+pub fn is_not_modified(request: &HttpRequest, response: &HttpResponse) -> bool {
+	if let Some(tags) = request.headers.get("If-None-Match") {
+		let current = match etag(response) {
+			Some(tag) => tag,
+			None => return false
+		};
+		return tags.values().iter().flat_map(|v| v.split(',')).map(|t| t.trim()).any(|t| t == "*" || weak(t) == weak(current));
+	}
+
+	match (request.headers.first("If-Modified-Since"), response.headers.first("Last-Modified")) {
+		(Some(since), Some(modified)) => since == modified,
+		_ => false
+	}
+}
+
+// Cache-Control directives that matter here
+#[derive(Default)]
+struct Directives {
+	no_store: bool,
+	no_cache: bool,
+	private: bool,
+	public: bool,
+	max_age: Option<u64>,
+	s_maxage: Option<u64>
+}
+
+impl Directives {
+	fn parse(headers: &HeaderCollection) -> Directives {
+		let mut directives = Directives::default();
+		let values = match headers.get("Cache-Control") {
+			Some(h) => h.values(),
+			None => return directives
+		};
+
+		for directive in values.iter().flat_map(|v| v.split(',')) {
+			let mut parts = directive.splitn(2, '=');
+			let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+			let seconds = parts.next().and_then(|v| v.trim().trim_matches('"').parse::<u64>().ok());
+			match name.as_ref() {
+				"no-store" => directives.no_store = true,
+				"no-cache" => directives.no_cache = true,
+				"private" => directives.private = true,
+				"public" => directives.public = true,
+				"max-age" => directives.max_age = seconds,
+				"s-maxage" => directives.s_maxage = seconds,
+				_ => {}
+			}
+		}
+		directives
+	}
+}
+
+// How long a response may be used for without asking the handler again, if it may be kept at all
+fn storable(request: &HttpRequest, personal: bool, response: &HttpResponse) -> Option<Duration> {
+	if request.start_line.method != AllowedMethod::GET || !response.status_line.status_code().is_cacheable() {
+		return None;
+	}
+
+	let requested = Directives::parse(&request.headers);
+	let directives = Directives::parse(&response.headers);
+	//responses carrying a cookie or meant for one user can't be handed to another
+	if requested.no_store || directives.no_store || directives.private || response.headers.get("Set-Cookie").is_some() {
+		return None;
+	}
+	//nor can responses to a request with credentials, unless they say they can
+	if request.headers.get("Authorization").is_some() && !directives.public && directives.s_maxage.is_none() {
+		return None;
+	}
+	//or ones that may have been made for the session or the signed-in user, unless they say they're the same for everyone
+	if (personal || request.headers.get("Cookie").is_some()) && !directives.public {
+		return None;
+	}
+	if response.headers.get("Vary").map(|h| h.values().iter().any(|v| v.trim() == "*")).unwrap_or(false) {
+		return None;
+	}
+
+	let ttl = if directives.no_cache { Some(0) } else { directives.s_maxage.or(directives.max_age) };
+	let validated = etag(response).is_some() || response.headers.get("Last-Modified").is_some();
+	match ttl {
+		Some(0) if !validated => None,
+		Some(seconds) => Some(Duration::from_secs(seconds)),
+		None => None
+	}
+}
+
+// A stored or fresh response as the client should get it: 304 if it already has it
+fn answer(request: &HttpRequest, mut response: HttpResponse, age: Option<Duration>) -> HttpResponse {
+	if let Some(age) = age {
+		response.add_header("Age", &age.as_secs().to_string());
+	}
+	if response.status_line.status_code() != StatusCode::ok() || !is_not_modified(request, &response) {
+		return response;
+	}
+
+	let mut not_modified = HttpResponse::new(StatusCode::not_modified());
+	for name in REFRESHED.iter().chain(["Age"].iter()) {
+		if let Some(header) = response.headers.get(name) {
+			for value in header.values() {
+				not_modified.add_header(name, value);
+			}
+		}
+	}
+	not_modified
+}
+
+fn cache_key(request: &HttpRequest) -> String {
+	let uri = &request.start_line.uri;
+	let mut key = format!("/{}", uri.path.path_components.join("/"));
+	if let Some(ref query) = uri.query {
+		key.push('?');
+		key.push_str(query);
+	}
+	key
+}
+
+fn etag(response: &HttpResponse) -> Option<&str> {
+	response.headers.first("ETag")
+}
+
+// Tags compare weakly for If-None-Match, ignoring W/
+fn weak(tag: &str) -> &str {
+	tag.trim_start_matches("W/")
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::cell::Cell;
+	use std::thread;
+	use uri::Uri;
+
+	fn get(path: &str, headers: &[(&str, &str)]) -> HttpRequest {
+		request(AllowedMethod::GET, path, headers)
+	}
+
+	fn request(method: AllowedMethod, path: &str, headers: &[(&str, &str)]) -> HttpRequest {
+		let mut request = HttpRequest {
+			start_line: StartLine { method, uri: Uri::new(path), major_version: 1, minor_version: 1 },
+			headers: HeaderCollection::init_empty(),
+			body: Vec::new()
+		};
+		for &(name, value) in headers {
+			request.headers.get_or_add(name).add(value.to_string());
+		}
+		request
+	}
+
+	// How many responses are being kept
+	fn kept(cache: &ResponseCache) -> usize {
+		cache.store.lock().unwrap().entries.values().map(Vec::len).sum()
+	}
+
+	fn ok(body: &str, headers: &[(&str, &str)]) -> HttpResponse {
+		let mut response = HttpResponse::new(StatusCode::ok()).with_body("text/plain", body);
+		for &(name, value) in headers {
+			response.add_header(name, value);
+		}
+		response
+	}
+
+	#[test]
+	fn fresh_responses_are_served_from_the_cache() {
+		let cache = ResponseCache::new(1024);
+		let calls = Cell::new(0);
+		let handler = |_: &HttpRequest| {
+			calls.set(calls.get() + 1);
+			ok(&format!("call {}", calls.get()), &[("Cache-Control", "max-age=60")])
+		};
+
+		assert_eq!(cache.respond(&get("/list", &[]), false, &handler).body, b"call 1");
+		let cached = cache.respond(&get("/list", &[]), false, &handler);
+		assert_eq!(cached.body, b"call 1");
+		assert_eq!(cached.headers.first("Age"), Some("0"));
+		assert_eq!(cache.respond(&get("/list?page=2", &[]), false, &handler).body, b"call 2");
+		assert_eq!(calls.get(), 2);
+	}
+
+	#[test]
+	fn responses_that_may_not_be_kept_are_not() {
+		let cache = ResponseCache::new(1024);
+		let uncacheable: Vec<(HttpRequest, HttpResponse)> = vec![
+			(get("/", &[]), ok("no ttl", &[])),
+			(get("/", &[]), ok("private", &[("Cache-Control", "private, max-age=60")])),
+			(get("/", &[]), ok("no-store", &[("Cache-Control", "no-store")])),
+			(get("/", &[]), ok("cookie", &[("Cache-Control", "max-age=60"), ("Set-Cookie", "a=b")])),
+			(get("/", &[]), ok("vary", &[("Cache-Control", "max-age=60"), ("Vary", "*")])),
+			(get("/", &[("Authorization", "Basic eDp5")]), ok("credentials", &[("Cache-Control", "max-age=60")])),
+			(get("/", &[]), HttpResponse::new(StatusCode::internal_server_error()).with_header("Cache-Control", "max-age=60"))
+		];
+
+		for (request, response) in uncacheable {
+			cache.respond(&request, false, move |_| response.clone());
+			assert_eq!(kept(&cache), 0);
+		}
+	}
+
+	#[test]
+	fn responses_for_a_session_or_user_are_only_kept_when_public() {
+		let cache = ResponseCache::new(1024);
+		cache.respond(&get("/", &[("Cookie", "session=abc")]), false, |_| ok("session", &[("Cache-Control", "max-age=60")]));
+		assert_eq!(kept(&cache), 0);
+		cache.respond(&get("/", &[]), true, |_| ok("user", &[("Cache-Control", "max-age=60")]));
+		assert_eq!(kept(&cache), 0);
+
+		cache.respond(&get("/", &[("Cookie", "session=abc")]), true, |_| ok("public", &[("Cache-Control", "public, max-age=60")]));
+		assert_eq!(kept(&cache), 1);
+	}
+
+	#[test]
+	fn variants_are_kept_apart_by_vary() {
+		let cache = ResponseCache::new(1024);
+		let handler = |r: &HttpRequest| ok(r.headers.first("Accept-Language").unwrap_or("none"), &[("Cache-Control", "max-age=60"), ("Vary", "Accept-Language")]);
+
+		cache.respond(&get("/", &[("Accept-Language", "en")]), false, &handler);
+		cache.respond(&get("/", &[("Accept-Language", "fr")]), false, &handler);
+		assert_eq!(kept(&cache), 2);
+
+		let french = cache.respond(&get("/", &[("Accept-Language", "fr")]), false, |_| panic!("should have been cached"));
+		assert_eq!(french.body, b"fr");
+	}
+
+	#[test]
+	fn clients_with_the_response_get_not_modified() {
+		let cache = ResponseCache::new(1024);
+		let tag = etag_for(b"body");
+		let response = ok("body", &[("Cache-Control", "max-age=60"), ("ETag", &tag)]);
+		cache.respond(&get("/", &[]), false, |_| response.clone());
+
+		let answer = cache.respond(&get("/", &[("If-None-Match", &format!("W/{}, \"other\"", tag))]), false, |_| panic!("should have been cached"));
+		assert_eq!(answer.status_line.status_code(), StatusCode::not_modified());
+		assert_eq!(answer.headers.first("ETag"), Some(tag.as_str()));
+		assert!(answer.body.is_empty());
+	}
+
+	#[test]
+	fn stale_responses_are_revalidated() {
+		let cache = ResponseCache::new(1024);
+		let tag = etag_for(b"body");
+		let response = ok("body", &[("Cache-Control", "max-age=0, must-revalidate"), ("ETag", &tag)]);
+		cache.respond(&get("/", &[]), false, |_| response.clone());
+		assert_eq!(kept(&cache), 1);
+
+		let asked = Cell::new(None);
+		let revalidated = cache.respond(&get("/", &[]), false, |r| {
+			asked.set(r.headers.first("If-None-Match").map(|t| t.to_string()));
+			HttpResponse::new(StatusCode::not_modified()).with_header("ETag", &tag).with_header("Cache-Control", "max-age=60")
+		});
+		assert_eq!(asked.take(), Some(tag.clone()));
+		assert_eq!(revalidated.status_line.status_code(), StatusCode::ok());
+		assert_eq!(revalidated.body, b"body");
+
+		//the 304's max-age now applies
+		let fresh = cache.respond(&get("/", &[]), false, |_| panic!("should be fresh again"));
+		assert_eq!(fresh.body, b"body");
+	}
+
+	#[test]
+	fn expired_responses_without_validators_are_fetched_again() {
+		let cache = ResponseCache::new(1024);
+		cache.respond(&get("/", &[]), false, |_| ok("old", &[("Cache-Control", "max-age=1")]));
+		thread::sleep(Duration::from_millis(1100));
+
+		assert_eq!(cache.respond(&get("/", &[]), false, |_| ok("new", &[("Cache-Control", "max-age=1")])).body, b"new");
+	}
+
+	#[test]
+	fn least_recently_used_responses_are_evicted_first() {
+		let body = "x".repeat(200);
+		let cache = ResponseCache::new(700);
+		let handler = |_: &HttpRequest| ok(&body, &[("Cache-Control", "max-age=60")]);
+
+		cache.respond(&get("/first", &[]), false, &handler);
+		cache.respond(&get("/second", &[]), false, &handler);
+		//first is used again, so second is the one to go
+		cache.respond(&get("/first", &[]), false, &handler);
+		cache.respond(&get("/third", &[]), false, &handler);
+
+		assert_eq!(kept(&cache), 2);
+		cache.respond(&get("/first", &[]), false, |_| panic!("first should have been kept"));
+		cache.respond(&get("/third", &[]), false, |_| panic!("third should have been kept"));
+	}
+
+	#[test]
+	fn changes_and_handlers_invalidate() {
+		let cache = ResponseCache::new(4096);
+		let handler = |_: &HttpRequest| ok("listing", &[("Cache-Control", "max-age=60")]);
+		for path in &["/items", "/items?page=2", "/items/1", "/items/2", "/other"] {
+			cache.respond(&get(path, &[]), false, &handler);
+		}
+
+		cache.respond(&get("/itemsets", &[]), false, &handler);
+
+		cache.respond(&request(AllowedMethod::DELETE, "/items/1?confirm=yes", &[]), false, |_| HttpResponse::new(StatusCode::no_content()));
+		assert_eq!(kept(&cache), 5);
+
+		cache.respond(&request(AllowedMethod::POST, "/items", &[]), false, |_| HttpResponse::new(StatusCode::see_other()).with_header("Location", "/other"));
+		assert_eq!(kept(&cache), 1);
+		cache.respond(&get("/itemsets", &[]), false, |_| panic!("a path that only starts the same isn't under it"));
+	}
+}
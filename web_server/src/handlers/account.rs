@@ -0,0 +1,89 @@
+use encoding::parse_form;
+use handlers::view;
+use http::model::*;
+use routing::RequestContext;
+use auth::Credentials;
+
+pub fn login_page(context: &mut RequestContext) -> HttpResponse {
+	let query = context.request.start_line.uri.query.clone().unwrap_or_default();
+	let next = redirect_target(parse_form(&query).get("next"));
+
+	view(context, StatusCode::ok(), "Login.html", view_data!("next" => next))
+}
+
+pub fn login(context: &mut RequestContext) -> HttpResponse {
+	let form = parse_form(&String::from_utf8_lossy(&context.request.body));
+	let username = form.get("username").cloned().unwrap_or_default();
+	let password = form.get("password").cloned().unwrap_or_default();
+	let next = redirect_target(form.get("next"));
+
+	let app = context.app;
+	match app.auth.login(&app.sessions, &mut context.session, &username, &password) {
+		Some(user) => {
+			context.user = Some(user);
+			HttpResponse::new(StatusCode::see_other()).with_header("Location", &next)
+		},
+		None => {
+			let data = view_data!("next" => next, "username" => username, "error" => "Incorrect username or password");
+			view(context, StatusCode::unauthorized(), "Login.html", data)
+		}
+	}
+}
+
+pub fn logout(context: &mut RequestContext) -> HttpResponse {
+	context.app.auth.logout(&mut context.session);
+	context.user = None;
+	HttpResponse::new(StatusCode::see_other()).with_header("Location", "/")
+}
+
+/// Issues a bearer token to a client that authenticated some other way (usually Basic).
+pub fn issue_token(context: &mut RequestContext) -> HttpResponse {
+	let user = match context.user {
+		Some(ref u) => u,
+		None => return HttpResponse::new(StatusCode::unauthorized())
+	};
+
+	match context.app.auth.issue_token(user) {
+		Ok(token) => HttpResponse::new(StatusCode::ok())
+			.with_header("Cache-Control", "no-store")
+			.with_body("application/json; charset=UTF-8", format!("{{\"token\": \"{}\", \"token_type\": \"Bearer\"}}", token)),
+		Err(e) => {
+			error!("Failed to issue a token: {}", e);
+			HttpResponse::new(StatusCode::internal_server_error())
+		}
+	}
+}
+
+/// Revokes the bearer token the request was made with.
+pub fn revoke_token(context: &mut RequestContext) -> HttpResponse {
+	match Credentials::from_request(context.request) {
+		Some(Credentials::Bearer(ref token)) if context.app.auth.revoke_token(token) => HttpResponse::new(StatusCode::ok()),
+		_ => HttpResponse::new(StatusCode::bad_request())
+	}
+}
+
+// Only local paths are followed after login, so the login page can't be used to send users to another site.
+// `next` arrives percent-decoded, so control characters are refused too rather than written into Location
+fn redirect_target(next: Option<&String>) -> String {
+	match next {
+		Some(n) if n.starts_with('/') && !n.starts_with("//") && !n.contains('\\') && !n.chars().any(char::is_control) => n.clone(),
+		_ => String::from("/")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn only_local_paths_are_redirected_to() {
+		let target = |next: &str| redirect_target(Some(&String::from(next)));
+		assert_eq!(target("/properties?page=2"), "/properties?page=2");
+		assert_eq!(target("//evil.example"), "/");
+		assert_eq!(target("/\\evil.example"), "/");
+		assert_eq!(target("https://evil.example"), "/");
+		assert_eq!(target("/\r\nSet-Cookie: session=stolen"), "/");
+		assert_eq!(target("/properties\t"), "/");
+		assert_eq!(redirect_target(None), "/");
+	}
+}
@@ -0,0 +1,60 @@
+use std::net::TcpStream;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::Duration;
+
+use http::model::*;
+use routing::RequestContext;
+use websocket::{WebSocket, Message};
+use sse::{Event, EventStream};
+
+//TODO: publish when a maintenance task becomes due, once tasks are tracked
+/// Streams every published notification to the client until either side closes the socket.
+pub fn notifications(context: &mut RequestContext, mut socket: WebSocket<TcpStream>) {
+	let updates = context.app.notifications.subscribe();
+	let sender = socket.sender();
+
+	let forwarder = thread::spawn(move || {
+		loop {
+			match updates.recv_timeout(Duration::from_secs(1)) {
+				Ok(update) => if sender.send(Message::Text(update)).is_err() { break },
+				//wake up now and then to notice the socket was closed
+				Err(RecvTimeoutError::Timeout) => if sender.is_closed() { break },
+				Err(RecvTimeoutError::Disconnected) => break
+			}
+		}
+	});
+
+	//nothing is expected from the client, but reading is how pings get answered and closes noticed
+	loop {
+		match socket.recv() {
+			Ok(Message::Close(_)) => break,
+			Ok(_) => {},
+			Err(e) => {
+				debug!("Notifications socket closed: {}", e);
+				let _ = socket.close(::websocket::close_code::GOING_AWAY, "");
+				break;
+			}
+		}
+	}
+
+	let _ = forwarder.join();
+}
+
+/// Sends the request body to everyone listening for notifications.
+pub fn publish(context: &mut RequestContext) -> HttpResponse {
+	let message = String::from_utf8_lossy(&context.request.body).into_owned();
+	if message.is_empty() {
+		return HttpResponse::new(StatusCode::bad_request());
+	}
+
+	context.app.notifications.publish(&message);
+	HttpResponse::new(StatusCode::ok())
+}
+
+/// Streams published notifications as server-sent events, for clients that can't or won't use the websocket.
+pub fn notification_events(context: &mut RequestContext) -> Result<EventStream, HttpResponse> {
+	let updates = context.app.notifications.subscribe();
+	let events = EventStream::map(updates, |update| Event::new(&update).with_event("notification"));
+	Ok(events.with_retry(Duration::from_secs(5)))
+}
@@ -0,0 +1,129 @@
+pub mod account;
+pub mod live;
+pub mod health;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::thread;
+use std::time::Duration;
+
+use http::model::*;
+use routing::RequestContext;
+use cache;
+use templates::Value;
+use uri::{TemplateValue, UriTemplate};
+
+/// Where each property's page is, for routing to it and linking to it alike.
+pub const PROPERTY_PAGE: &str = "/properties/{id}";
+
+pub fn index(context: &mut RequestContext) -> HttpResponse {
+	let visits = context.session.get("visits").and_then(|v| v.parse::<usize>().ok()).unwrap_or(0) + 1;
+	context.session.set("visits", &visits.to_string());
+
+	view(context, StatusCode::ok(), "Index.html", view_data!("visits" => visits))
+}
+
+pub fn sleep(context: &mut RequestContext) -> HttpResponse {
+	thread::sleep(Duration::from_secs(5));
+	view(context, StatusCode::ok(), "Index.html", Value::map())
+}
+
+pub fn properties(context: &mut RequestContext) -> HttpResponse {
+	let username = context.user.as_ref().map(|u| u.username.clone()).unwrap_or_default();
+	let data = view_data!("user" => view_data!("name" => username), "properties" => demo_properties());
+	view(context, StatusCode::ok(), "Properties.html", data)
+}
+
+pub fn property(context: &mut RequestContext) -> HttpResponse {
+	let mut properties = demo_properties();
+	let id = match context.variables.get("id") {
+		Some(TemplateValue::String(id)) => id.parse::<usize>().ok().filter(|&id| id >= 1 && id <= properties.len()),
+		_ => None
+	};
+
+	match id {
+		Some(id) => {
+			let username = context.user.as_ref().map(|u| u.username.clone()).unwrap_or_default();
+			let data = view_data!("user" => view_data!("name" => username), "properties" => vec!(properties.swap_remove(id - 1)));
+			view(context, StatusCode::ok(), "Properties.html", data)
+		},
+		None => not_found(context)
+	}
+}
+
+pub fn scripts(context: &mut RequestContext) -> HttpResponse {
+	let uri = &context.request.start_line.uri;
+	debug!("Script request received: {}", uri);
+	//decoded, so `%2e%2e` is caught along with `..`
+	let segments = uri.path.decoded_segments();
+	if segments.iter().any(|s| s == ".." || s.contains('/') || s.contains('\\')) {
+		return not_found(context);
+	}
+	let script_path: String = format!("./{}", segments.join("/"));
+
+	//scripts only change with a deploy, so the response cache and browsers can keep them for a while
+	match read_static_content(&script_path) {
+		Ok(script) => HttpResponse::new(StatusCode::ok())
+			.with_header("Cache-Control", "public, max-age=300")
+			.with_header("ETag", &cache::etag_for(script.as_bytes()))
+			.with_body("application/json; charset=UTF-8", script),
+		Err(_) => not_found(context)
+	}
+}
+
+pub fn test_post(_context: &mut RequestContext) -> HttpResponse {
+	debug!("Got a post");
+	HttpResponse::new(StatusCode::ok())
+		.with_body("application/json; charset=UTF-8", "{\"test\": \"POST successful\" }")
+}
+
+pub fn not_found(context: &mut RequestContext) -> HttpResponse {
+	view(context, StatusCode::not_found(), "Error_404.html", Value::map())
+}
+
+/// Renders a view as an html response. Every view gets the logged in user as `current_user`.
+pub fn view(context: &RequestContext, status: StatusCode, name: &str, mut data: Value) -> HttpResponse {
+	if let Some(ref user) = context.user {
+		data.insert("current_user", user.username.as_str());
+	}
+
+	match context.app.views.read().unwrap().render(name, &data) {
+		Ok(content) => HttpResponse::new(status).with_body("text/html; charset=UTF-8", content),
+		Err(e) => {
+			error!("Failed to render view: {}", e);
+			HttpResponse::new(StatusCode::internal_server_error())
+		}
+	}
+}
+
+//TODO: replace with the user's properties once they are persisted
+fn demo_properties() -> Vec<Value> {
+	let rooms = vec!(
+		view_data!("name" => "Kitchen", "room_type" => "Kitchen", "area" => 120),
+		view_data!("name" => "Master bedroom", "room_type" => "Bedroom", "area" => 180),
+		view_data!("name" => "Hall", "room_type" => "Hallway")
+	);
+
+	let properties = vec!(
+		view_data!("address" => "123 Main St", "rooms" => rooms),
+		view_data!("address" => "456 Side Ave", "rooms" => Vec::<Value>::new())
+	);
+	properties.into_iter().enumerate().map(|(ix, property)| property.with("link", property_link(ix + 1))).collect()
+}
+
+fn property_link(id: usize) -> String {
+	let mut variables = HashMap::new();
+	variables.insert(String::from("id"), TemplateValue::from(id.to_string()));
+	UriTemplate::new(PROPERTY_PAGE).expand(&variables)
+}
+
+fn read_static_content(response_path: &str) -> ::std::io::Result<String> {
+	let mut file = File::open(response_path)?;
+
+	let mut contents = String::new();
+
+	file.read_to_string(&mut contents)?;
+
+	Ok(contents)
+}
@@ -1,36 +1,232 @@
 
 use http::parser::HttpRequestParser;
 use http::model::*;
-use std::net::TcpStream;
-use std::io::Write;
+use http::limits::Limits;
+use websocket::WebSocket;
+use http2;
+use proxy::{Proxy, Relayed, RequestBody};
+use sse;
+use sse::EventStream;
+use std::net::{TcpStream, IpAddr};
+use std::io;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
 
 //TODO: use a std::io::BufReader
 
 
 pub struct HttpContext {
     stream: TcpStream,
-    parser: HttpRequestParser
+    parser: HttpRequestParser,
+    limits: Limits,
+    //when the request being read has to be in by; set on the first read
+    deadline: Option<Instant>,
+    //the last request's head has been read, but not its body
+    unread_body: bool
 }
 
 impl HttpContext {
     pub fn from_stream(stream: TcpStream) ->HttpContext {
         HttpContext {
             stream: stream,
-            parser: HttpRequestParser::new()
+            parser: HttpRequestParser::new(),
+            limits: Limits::default(),
+            deadline: None,
+            unread_body: false
         }
     }
 
-    pub(crate) fn get_start_line(&mut self) -> Result<StartLine, StatusLine> {
-        self.parser.get_start_line(&mut self.stream)
+    /// Picks up a connection some other reader has already taken bytes from.
+    pub fn resume(stream: TcpStream, buffered: Vec<u8>) -> HttpContext {
+        HttpContext {
+            stream: stream,
+            parser: HttpRequestParser::with_buffered(buffered),
+            limits: Limits::default(),
+            deadline: None,
+            unread_body: false
+        }
     }
 
-    pub(crate) fn flush_request(&mut self) {
-        self.parser.get_request_body(&mut self.stream)
+    pub fn with_limits(self, limits: Limits) -> HttpContext {
+        HttpContext {
+            stream: self.stream,
+            parser: self.parser.with_limits(limits.clone()),
+            limits,
+            deadline: self.deadline,
+            unread_body: self.unread_body
+        }
     }
 
-    pub(crate) fn send_response(&mut self, response: String) {
-			self.stream.write(response.as_bytes()).unwrap();
-			self.stream.flush().unwrap();
-	}
+    pub fn peer_ip(&self) -> Option<IpAddr> {
+        self.stream.peer_addr().ok().map(|addr| addr.ip())
+    }
+
+    /// Reads the next request's start line and header fields, leaving the body to be read or forwarded.
+    /// Clients that take longer than the request timeout to send the request, in total, get a 408.
+    pub(crate) fn read_request_head(&mut self) -> Result<HttpRequest, StatusLine> {
+        let until = self.deadline();
+        let mut stream = DeadlineStream { stream: &self.stream, until };
+        let start_line = self.parser.get_start_line(&mut stream)?;
+        let headers = self.parser.get_request_headers(&mut stream)?;
+        self.unread_body = true;
+
+        Ok(HttpRequest {
+            start_line,
+            headers,
+            body: Vec::new()
+        })
+    }
+
+    pub(crate) fn read_request_body(&mut self, request: &mut HttpRequest) -> Result<(), StatusLine> {
+        let until = self.deadline();
+        let mut stream = DeadlineStream { stream: &self.stream, until };
+        request.body = self.parser.get_request_body(&mut stream, &request.headers)?;
+        self.deadline = None;
+        self.unread_body = false;
+        Ok(())
+    }
+
+    /// Forwards the request through the proxy. A body still to be read is passed on as it arrives, and the response is passed back the same way.
+    pub(crate) fn forward(&mut self, proxy: &Proxy, request: &HttpRequest) -> Result<Relayed, HttpResponse> {
+        let client = self.peer_ip();
+        let unread_body = self.unread_body;
+        self.deadline = None;
+        self.unread_body = false;
+
+        //a large upload can take as long as it likes, so long as it keeps coming
+        let _ = self.stream.set_read_timeout(Some(self.limits.request_timeout));
+        let parser = &mut self.parser;
+        let mut stream = &self.stream;
+        let mut out = &self.stream;
+
+        if unread_body {
+            let mut copy = |upstream: &mut TcpStream| parser.copy_body(&mut stream, &request.headers, upstream);
+            proxy.relay(request, client, RequestBody::Stream(&mut copy), &mut out)
+        } else {
+            proxy.relay(request, client, RequestBody::Read(&request.body), &mut out)
+        }
+    }
+
+    pub(crate) fn send_response(&mut self, response: &HttpResponse) -> io::Result<()> {
+        self.stream.write_all(&response.to_bytes())?;
+        self.stream.flush()
+    }
+
+    /// Whether the client has sent anything more, waiting up to `timeout` for it. An error once the client has closed its end.
+    pub(crate) fn wait_for_data(&mut self, timeout: Duration) -> io::Result<bool> {
+        if !self.parser.buffered().is_empty() {
+            return Ok(true);
+        }
+
+        self.stream.set_read_timeout(Some(timeout))?;
+        match self.stream.peek(&mut [0_u8]) {
+            Ok(0) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed")),
+            Ok(_) => Ok(true),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => Ok(false),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Whether the client opened with the HTTP/2 connection preface, i.e. it already knows we speak HTTP/2.
+    pub(crate) fn has_http2_preface(&mut self) -> bool {
+        let until = self.deadline();
+        let mut stream = DeadlineStream { stream: &self.stream, until };
+        self.parser.starts_with(&mut stream, http2::PREFACE)
+    }
+
+    fn deadline(&mut self) -> Instant {
+        let timeout = self.limits.request_timeout;
+        *self.deadline.get_or_insert_with(|| Instant::now() + timeout)
+    }
+
+    /// Serves the rest of the connection as HTTP/2, after answering the request that asked to upgrade to it, if any.
+    pub(crate) fn serve_http2<F>(mut self, upgraded_from: Option<HttpRequest>, service: &F) -> io::Result<()>
+        where F: Fn(HttpRequest) -> HttpResponse + Sync {
+        if upgraded_from.is_some() {
+            self.stream.write_all(&http2::upgrade_response().to_bytes())?;
+            self.stream.flush()?;
+        }
+
+        let buffered = self.parser.take_buffered();
+        http2::serve(self.stream, buffered, upgraded_from, self.limits, service)
+    }
+
+    /// Sends the handshake response and hands the connection over to the websocket protocol.
+    pub(crate) fn upgrade(mut self, handshake: &HttpResponse) -> io::Result<WebSocket<TcpStream>> {
+        self.stream.write_all(&handshake.to_bytes())?;
+        self.stream.flush()?;
+
+        //websockets sit idle until there is something to say
+        self.stream.set_read_timeout(None)?;
+        let writer = self.stream.try_clone()?;
+        Ok(WebSocket::new(self.stream, writer, self.parser.take_buffered()))
+    }
+
+    /// Sends the head of an event stream and keeps the connection for its events, until either side is done with it.
+    pub(crate) fn stream_events(self, head: &HttpResponse, events: EventStream) -> io::Result<()> {
+        let mut out = &self.stream;
+        out.write_all(&head.head_bytes())?;
+        out.flush()?;
+
+        //a client that stops reading shouldn't hold on to the worker either
+        self.stream.set_read_timeout(None)?;
+        self.stream.set_write_timeout(Some(self.limits.request_timeout))?;
+        sse::stream(&self.stream, events)
+    }
 
-}
\ No newline at end of file
+}
+
+// Bounds the total time spent reading, rather than the time per read, so trickling in a byte at a time doesn't help
+struct DeadlineStream<'a> {
+    stream: &'a TcpStream,
+    until: Instant
+}
+
+impl<'a> Read for DeadlineStream<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let now = Instant::now();
+        if now >= self.until {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "request deadline passed"));
+        }
+
+        self.stream.set_read_timeout(Some(self.until - now))?;
+        let mut stream = self.stream;
+        stream.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::net::TcpListener;
+	use std::thread;
+
+	#[test]
+	fn slow_client_times_out() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+
+		//sends a byte at a time, each well inside any per-read timeout, but never finishes
+		let client = thread::spawn(move || {
+			let mut stream = TcpStream::connect(addr).unwrap();
+			for byte in b"GET / HTTP/1.1\r\nHost: localhost\r\n".iter() {
+				if stream.write_all(&[*byte]).is_err() {
+					break;
+				}
+				thread::sleep(Duration::from_millis(20));
+			}
+		});
+
+		let (stream, _) = listener.accept().unwrap();
+		let limits = Limits { request_timeout: Duration::from_millis(200), ..Limits::default() };
+		let mut context = HttpContext::from_stream(stream).with_limits(limits);
+
+		let started = Instant::now();
+		let err = context.read_request_head().err().unwrap();
+		assert_eq!(err.status_code(), StatusCode::request_timeout());
+		assert!(started.elapsed() < Duration::from_millis(600));
+
+		drop(context);
+		client.join().unwrap();
+	}
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use http::model::*;
+
+pub use http_message::limits::Limits;
+
+/// Counts open connections per client address.
+#[derive(Clone)]
+pub struct ConnectionLimiter {
+	max_per_ip: usize,
+	open: Arc<Mutex<HashMap<IpAddr, usize>>>
+}
+
+impl ConnectionLimiter {
+	pub fn new(max_per_ip: usize) -> ConnectionLimiter {
+		ConnectionLimiter {
+			max_per_ip,
+			open: Arc::new(Mutex::new(HashMap::new()))
+		}
+	}
+
+	/// Counts a new connection from the address, or None if it already has as many as it may. The count drops with the permit.
+	pub fn acquire(&self, ip: IpAddr) -> Option<ConnectionPermit> {
+		let mut open = self.open.lock().unwrap();
+		let count = open.entry(ip).or_insert(0);
+		if *count >= self.max_per_ip {
+			return None;
+		}
+
+		*count += 1;
+		Some(ConnectionPermit {
+			ip,
+			open: Arc::clone(&self.open)
+		})
+	}
+
+	/// How many connections are open, from every address.
+	pub fn open(&self) -> usize {
+		self.open.lock().unwrap().values().sum()
+	}
+}
+
+/// What a client over its connection limit gets before being disconnected.
+pub fn too_many_connections() -> HttpResponse {
+	HttpResponse::new(StatusCode::too_many_requests())
+		.with_header("Retry-After", "5")
+		.with_header("Connection", "close")
+}
+
+pub struct ConnectionPermit {
+	ip: IpAddr,
+	open: Arc<Mutex<HashMap<IpAddr, usize>>>
+}
+
+impl Drop for ConnectionPermit {
+	fn drop(&mut self) {
+		let mut open = self.open.lock().unwrap();
+		let remaining = match open.get_mut(&self.ip) {
+			Some(count) => {
+				*count -= 1;
+				*count
+			},
+			None => return
+		};
+
+		if remaining == 0 {
+			open.remove(&self.ip);
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn connections_counted_per_ip() {
+		let limiter = ConnectionLimiter::new(2);
+		let client: IpAddr = "10.0.0.1".parse().unwrap();
+		let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+		let first = limiter.acquire(client).unwrap();
+		let _second = limiter.acquire(client).unwrap();
+		assert!(limiter.acquire(client).is_none());
+		assert!(limiter.acquire(other).is_some());
+
+		drop(first);
+		assert!(limiter.acquire(client).is_some());
+	}
+}
@@ -1,3 +1,4 @@
-pub(crate) mod parser;
+pub use http_message::{model, parser};
+
 pub(crate) mod http_context;
-pub(crate) mod model;
+pub(crate) mod limits;
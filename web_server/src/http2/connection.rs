@@ -0,0 +1,962 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::Write;
+use uri::Uri;
+
+use http::model::*;
+use http::limits::Limits;
+use http2::frame::*;
+use http2::hpack::{Decoder, Encoder, HeaderList, HpackError};
+
+const MAX_CONCURRENT_STREAMS: u32 = 100;
+const DEFAULT_WINDOW_SIZE: i64 = 65_535;
+const MAX_WINDOW_SIZE: i64 = (1 << 31) - 1;
+const HEADER_TABLE_SIZE: usize = 4096;
+//header blocks are buffered until the last CONTINUATION arrives, so they need a cap
+const MAX_HEADER_BLOCK_LEN: usize = 64 * 1024;
+
+const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+const SETTINGS_MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+//headers that only mean something for a single HTTP/1.1 connection (RFC 7540 8.1.2.2)
+const CONNECTION_HEADERS: [&str; 5] = ["connection", "keep-alive", "proxy-connection", "transfer-encoding", "upgrade"];
+
+#[derive(Debug)]
+pub enum H2Error {
+	/// Ends the whole connection with a GOAWAY.
+	Connection(ErrorCode, &'static str),
+	Io(io::Error)
+}
+
+impl From<io::Error> for H2Error {
+	fn from(e: io::Error) -> H2Error {
+		H2Error::Io(e)
+	}
+}
+
+fn protocol_error<T>(message: &'static str) -> Result<T, H2Error> {
+	Err(H2Error::Connection(ErrorCode::ProtocolError, message))
+}
+
+#[derive(PartialEq, Debug)]
+enum StreamState {
+	Open,
+	//the request is complete and the response hasn't finished sending
+	HalfClosedRemote
+}
+
+struct Stream {
+	state: StreamState,
+	headers: HeaderList,
+	body: Vec<u8>,
+	send_window: i64,
+	//how much more the client may send on the stream before it hears back from us
+	recv_window: i64,
+	//response body waiting on flow control, and how much of it has been sent
+	pending: Option<(Vec<u8>, usize)>
+}
+
+/// The server side of an HTTP/2 connection: takes frames from the client, hands back complete
+/// requests and sends the responses to them. Threads and I/O for reading are left to the caller.
+pub struct Connection<W: Write> {
+	writer: W,
+	decoder: Decoder,
+	encoder: Encoder,
+	streams: HashMap<u32, Stream>,
+	//highest stream id the client has opened; anything higher is idle, anything lower not in `streams` is closed
+	last_stream_id: u32,
+	//a header block still waiting on CONTINUATION frames: stream id, block so far and whether it ends the stream
+	continuation: Option<(u32, Vec<u8>, bool)>,
+	peer_max_frame_size: usize,
+	peer_initial_window: i64,
+	send_window: i64,
+	//request bodies buffered on open streams are only handed back once they're taken off the connection, which caps them all together
+	recv_window: i64,
+	limits: Limits,
+	settings_received: bool,
+	going_away: bool,
+	ready: Vec<(u32, HttpRequest)>,
+	//streams handed to a handler and not answered yet; a reset doesn't stop the handler, so they count against the limit until it answers
+	handling: HashSet<u32>
+}
+
+impl<W: Write> Connection<W> {
+	pub fn new(writer: W) -> Connection<W> {
+		let limits = Limits::default();
+		Connection {
+			writer,
+			decoder: decoder(&limits),
+			encoder: Encoder::new(),
+			streams: HashMap::new(),
+			last_stream_id: 0,
+			continuation: None,
+			peer_max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+			peer_initial_window: DEFAULT_WINDOW_SIZE,
+			send_window: DEFAULT_WINDOW_SIZE,
+			recv_window: DEFAULT_WINDOW_SIZE,
+			limits,
+			settings_received: false,
+			going_away: false,
+			ready: Vec::new(),
+			handling: HashSet::new()
+		}
+	}
+
+	/// Requests over the limits get the same 413 or 431 they would over HTTP/1.1.
+	pub fn with_limits(mut self, limits: Limits) -> Connection<W> {
+		self.decoder = decoder(&limits);
+		self.limits = limits;
+		self
+	}
+
+	/// Sends the server's connection preface, which must be the first thing on the connection.
+	pub fn start(&mut self) -> io::Result<()> {
+		let mut settings = Vec::new();
+		settings.extend_from_slice(&SETTINGS_MAX_CONCURRENT_STREAMS.to_be_bytes());
+		settings.extend_from_slice(&MAX_CONCURRENT_STREAMS.to_be_bytes());
+		settings.extend_from_slice(&SETTINGS_MAX_HEADER_LIST_SIZE.to_be_bytes());
+		settings.extend_from_slice(&(self.limits.max_header_bytes.min(u32::MAX as usize) as u32).to_be_bytes());
+		self.write(Frame::new(FrameType::Settings, 0, 0, settings))?;
+
+		//the connection window has to fit the largest body, since it only comes back once the body is complete
+		let increment = (self.limits.max_body_len as i64).min(MAX_WINDOW_SIZE) - DEFAULT_WINDOW_SIZE;
+		if increment > 0 {
+			self.recv_window += increment;
+			self.write(window_update(0, increment))?;
+		}
+		Ok(())
+	}
+
+	/// Takes on the HTTP/1.1 request that asked for the upgrade as stream 1, with the settings from its HTTP2-Settings header.
+	pub fn upgraded(&mut self, request: HttpRequest, settings: &[u8]) -> Result<(), H2Error> {
+		if !settings.len().is_multiple_of(6) {
+			return protocol_error("invalid HTTP2-Settings");
+		}
+		self.apply_settings(settings)?;
+
+		self.last_stream_id = 1;
+		let stream = self.new_stream(Vec::new(), StreamState::HalfClosedRemote);
+		self.streams.insert(1, stream);
+		self.ready.push((1, request));
+		Ok(())
+	}
+
+	/// Requests that are complete and waiting on a response, with their stream ids.
+	/// Each one must be answered with `on_response`, even once its stream is reset.
+	pub fn take_ready(&mut self) -> Vec<(u32, HttpRequest)> {
+		let ready = ::std::mem::take(&mut self.ready);
+		self.handling.extend(ready.iter().map(|&(stream_id, _)| stream_id));
+		ready
+	}
+
+	/// Whether the client has asked to stop and every stream has been answered.
+	pub fn is_finished(&self) -> bool {
+		self.going_away && self.streams.is_empty()
+	}
+
+	pub fn go_away(&mut self, code: ErrorCode) -> io::Result<()> {
+		self.going_away = true;
+		let mut payload = self.last_stream_id.to_be_bytes().to_vec();
+		payload.extend_from_slice(&code.to_u32().to_be_bytes());
+		self.write(Frame::new(FrameType::GoAway, 0, 0, payload))
+	}
+
+	pub fn on_frame(&mut self, frame: Frame) -> Result<(), H2Error> {
+		if !self.settings_received && frame.frame_type != FrameType::Settings {
+			return protocol_error("the client preface must start with SETTINGS");
+		}
+		if self.continuation.is_some() && frame.frame_type != FrameType::Continuation {
+			return protocol_error("expected CONTINUATION");
+		}
+
+		match frame.frame_type {
+			FrameType::Settings => self.on_settings(frame),
+			FrameType::Headers => self.on_headers(frame),
+			FrameType::Continuation => self.on_continuation(frame),
+			FrameType::Data => self.on_data(frame),
+			FrameType::WindowUpdate => self.on_window_update(frame),
+			FrameType::Ping => self.on_ping(frame),
+			FrameType::RstStream => self.on_rst_stream(frame),
+			FrameType::Priority => self.on_priority(frame),
+			FrameType::GoAway => {
+				if frame.stream_id != 0 {
+					return protocol_error("GOAWAY on a stream");
+				}
+				self.going_away = true;
+				Ok(())
+			},
+			FrameType::PushPromise => protocol_error("clients can't push"),
+			FrameType::Unknown(_) => Ok(())
+		}
+	}
+
+	/// Sends the response on its stream. Responses for streams the client has since reset are dropped.
+	pub fn on_response(&mut self, stream_id: u32, response: HttpResponse) -> io::Result<()> {
+		self.handling.remove(&stream_id);
+		if !self.streams.contains_key(&stream_id) {
+			return Ok(());
+		}
+
+		let code = response.status_line.status_code().get_code();
+		let mut fields = vec!((String::from(":status"), code.to_string()));
+		for header in response.headers.iter() {
+			let name = header.name().to_lowercase();
+			if CONNECTION_HEADERS.contains(&name.as_str()) || name == "content-length" {
+				continue;
+			}
+			fields.extend(header.values().iter().map(|v| (name.clone(), v.clone())));
+		}
+		if code >= 200 && code != 204 {
+			fields.push((String::from("content-length"), response.body.len().to_string()));
+		}
+
+		let block = self.encoder.encode(&fields);
+		let end_stream = response.body.is_empty();
+		self.write_headers(stream_id, block, end_stream)?;
+
+		if end_stream {
+			self.streams.remove(&stream_id);
+		} else {
+			if let Some(stream) = self.streams.get_mut(&stream_id) {
+				stream.pending = Some((response.body, 0));
+			}
+			self.flush_stream(stream_id)?;
+		}
+		Ok(())
+	}
+
+	fn on_settings(&mut self, frame: Frame) -> Result<(), H2Error> {
+		if frame.stream_id != 0 {
+			return protocol_error("SETTINGS on a stream");
+		}
+		if frame.has_flag(flags::ACK) {
+			if !frame.payload.is_empty() {
+				return Err(H2Error::Connection(ErrorCode::FrameSizeError, "SETTINGS ack with a payload"));
+			}
+			return Ok(());
+		}
+		if !frame.payload.len().is_multiple_of(6) {
+			return Err(H2Error::Connection(ErrorCode::FrameSizeError, "SETTINGS length not a multiple of 6"));
+		}
+
+		self.apply_settings(&frame.payload)?;
+		self.settings_received = true;
+		self.write(Frame::new(FrameType::Settings, flags::ACK, 0, Vec::new()))?;
+		self.flush_all()
+	}
+
+	fn apply_settings(&mut self, payload: &[u8]) -> Result<(), H2Error> {
+		for setting in payload.chunks(6) {
+			let id = u16::from_be_bytes([setting[0], setting[1]]);
+			let value = u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+
+			match id {
+				SETTINGS_ENABLE_PUSH if value > 1 => return protocol_error("invalid SETTINGS_ENABLE_PUSH"),
+				SETTINGS_INITIAL_WINDOW_SIZE => {
+					let value = value as i64;
+					if value > MAX_WINDOW_SIZE {
+						return Err(H2Error::Connection(ErrorCode::FlowControlError, "SETTINGS_INITIAL_WINDOW_SIZE too large"));
+					}
+
+					//the change applies to every open stream's window, not just new ones
+					let delta = value - self.peer_initial_window;
+					for stream in self.streams.values_mut() {
+						stream.send_window += delta;
+						if stream.send_window > MAX_WINDOW_SIZE {
+							return Err(H2Error::Connection(ErrorCode::FlowControlError, "stream window too large"));
+						}
+					}
+					self.peer_initial_window = value;
+				},
+				SETTINGS_MAX_FRAME_SIZE => {
+					if value < DEFAULT_MAX_FRAME_SIZE as u32 || value > 0xff_ffff {
+						return protocol_error("invalid SETTINGS_MAX_FRAME_SIZE");
+					}
+					self.peer_max_frame_size = value as usize;
+				},
+				//we never use the dynamic table when encoding
+				SETTINGS_HEADER_TABLE_SIZE => {},
+				//nor push, and unknown settings are ignored
+				_ => {}
+			}
+		}
+		Ok(())
+	}
+
+	fn on_headers(&mut self, frame: Frame) -> Result<(), H2Error> {
+		if frame.stream_id == 0 {
+			return protocol_error("HEADERS on stream 0");
+		}
+
+		let block = {
+			let payload = match frame.unpadded_payload() {
+				Some(p) => p,
+				None => return protocol_error("padding longer than the frame")
+			};
+			//priorities are advisory and ignored
+			if frame.has_flag(flags::PRIORITY) {
+				if payload.len() < 5 {
+					return Err(H2Error::Connection(ErrorCode::FrameSizeError, "HEADERS too short for its priority"));
+				}
+				payload[5..].to_vec()
+			} else {
+				payload.to_vec()
+			}
+		};
+
+		let end_stream = frame.has_flag(flags::END_STREAM);
+		if frame.has_flag(flags::END_HEADERS) {
+			self.on_header_block(frame.stream_id, block, end_stream)
+		} else {
+			self.continuation = Some((frame.stream_id, block, end_stream));
+			Ok(())
+		}
+	}
+
+	fn on_continuation(&mut self, frame: Frame) -> Result<(), H2Error> {
+		let (stream_id, mut block, end_stream) = match self.continuation.take() {
+			Some(c) => c,
+			None => return protocol_error("CONTINUATION without HEADERS")
+		};
+		if stream_id != frame.stream_id {
+			return protocol_error("CONTINUATION on a different stream");
+		}
+
+		block.extend_from_slice(&frame.payload);
+		if block.len() > MAX_HEADER_BLOCK_LEN {
+			return Err(H2Error::Connection(ErrorCode::EnhanceYourCalm, "header block too large"));
+		}
+
+		if frame.has_flag(flags::END_HEADERS) {
+			self.on_header_block(stream_id, block, end_stream)
+		} else {
+			self.continuation = Some((stream_id, block, end_stream));
+			Ok(())
+		}
+	}
+
+	fn on_header_block(&mut self, stream_id: u32, block: Vec<u8>, end_stream: bool) -> Result<(), H2Error> {
+		//always decoded, even for streams that are then refused, to keep the table in step with the client's
+		let (headers, too_large) = match self.decoder.decode(&block) {
+			Ok(h) => (h, false),
+			Err(HpackError::ListTooLarge) => (Vec::new(), true),
+			Err(_) => return Err(H2Error::Connection(ErrorCode::CompressionError, "invalid header block"))
+		};
+
+		let trailers_state = self.streams.get(&stream_id).map(|s| s.state == StreamState::Open);
+		match trailers_state {
+			//trailers are accepted and dropped, but have to end the request
+			Some(true) if end_stream => {
+				self.complete(stream_id)?;
+				return Ok(());
+			},
+			Some(true) => return self.reset(stream_id, ErrorCode::ProtocolError).map_err(H2Error::from),
+			Some(false) => return self.reset(stream_id, ErrorCode::StreamClosed).map_err(H2Error::from),
+			None => {}
+		}
+
+		if stream_id.is_multiple_of(2) || stream_id <= self.last_stream_id {
+			return protocol_error("HEADERS on a closed stream or a server stream id");
+		}
+		self.last_stream_id = stream_id;
+
+		if self.going_away {
+			return Ok(());
+		}
+		let abandoned = self.handling.iter().filter(|id| !self.streams.contains_key(id)).count();
+		if self.streams.len() + abandoned >= MAX_CONCURRENT_STREAMS as usize {
+			return self.reset(stream_id, ErrorCode::RefusedStream).map_err(H2Error::from);
+		}
+
+		//a body declared too large is turned away before it's sent
+		let max_body_len = self.limits.max_body_len;
+		let body_too_large = headers.iter().any(|(name, value)| name == "content-length" && value.parse::<usize>().map(|l| l > max_body_len).unwrap_or(false));
+
+		let stream = self.new_stream(headers, StreamState::Open);
+		self.streams.insert(stream_id, stream);
+		if too_large {
+			let status_line = StatusLine::init(StatusCode::request_header_fields_too_large(), String::from("Request Header Fields Too Large"));
+			self.turn_away(stream_id, status_line, !end_stream)?;
+		} else if end_stream {
+			self.complete(stream_id)?;
+		} else if body_too_large {
+			self.turn_away(stream_id, content_too_large(), true)?;
+		}
+		Ok(())
+	}
+
+	fn on_data(&mut self, frame: Frame) -> Result<(), H2Error> {
+		if frame.stream_id == 0 {
+			return protocol_error("DATA on stream 0");
+		}
+		if frame.stream_id > self.last_stream_id {
+			return protocol_error("DATA on an idle stream");
+		}
+
+		//the whole frame counts against flow control, padding included
+		let len = frame.payload.len() as i64;
+		if len > self.recv_window {
+			return Err(H2Error::Connection(ErrorCode::FlowControlError, "DATA beyond the connection window"));
+		}
+		self.recv_window -= len;
+
+		let data = match frame.unpadded_payload() {
+			Some(d) => d,
+			None => return protocol_error("padding longer than the frame")
+		};
+		//padding is done with as soon as it arrives, the data only once its request is complete or turned away
+		self.consumed(frame.payload.len() - data.len())?;
+
+		let max_body_len = self.limits.max_body_len;
+		let stream_id = frame.stream_id;
+		let state = self.streams.get(&stream_id).map(|s| (s.state == StreamState::Open, s.recv_window, s.body.len()));
+		match state {
+			Some((true, window, _)) if len > window => {
+				self.consumed(data.len())?;
+				return self.reset(stream_id, ErrorCode::FlowControlError).map_err(H2Error::from);
+			},
+			Some((true, _, body_len)) if data.len() > max_body_len - body_len => {
+				self.consumed(data.len())?;
+				return self.turn_away(stream_id, content_too_large(), true);
+			},
+			Some((true, _, _)) => {},
+			_ => {
+				self.consumed(data.len())?;
+				return self.reset(stream_id, ErrorCode::StreamClosed).map_err(H2Error::from);
+			}
+		}
+
+		let end_stream = frame.has_flag(flags::END_STREAM);
+		let increment = match self.streams.get_mut(&stream_id) {
+			Some(stream) => {
+				stream.recv_window -= len;
+				stream.body.extend_from_slice(data);
+				//the client only gets credit for as much as the body may still grow by
+				let room = (max_body_len - stream.body.len()) as i64 - stream.recv_window;
+				let increment = if end_stream { 0 } else { room.min(len) };
+				if increment > 0 {
+					stream.recv_window += increment;
+				}
+				increment
+			},
+			None => 0
+		};
+
+		if end_stream {
+			self.complete(stream_id)
+		} else {
+			if increment > 0 {
+				self.write(window_update(stream_id, increment))?;
+			}
+			Ok(())
+		}
+	}
+
+	fn on_window_update(&mut self, frame: Frame) -> Result<(), H2Error> {
+		if frame.payload.len() != 4 {
+			return Err(H2Error::Connection(ErrorCode::FrameSizeError, "WINDOW_UPDATE must be 4 bytes"));
+		}
+		let increment = (u32::from_be_bytes([frame.payload[0], frame.payload[1], frame.payload[2], frame.payload[3]]) & 0x7fff_ffff) as i64;
+
+		if frame.stream_id == 0 {
+			if increment == 0 {
+				return protocol_error("WINDOW_UPDATE of 0");
+			}
+			self.send_window += increment;
+			if self.send_window > MAX_WINDOW_SIZE {
+				return Err(H2Error::Connection(ErrorCode::FlowControlError, "connection window too large"));
+			}
+			return self.flush_all();
+		}
+
+		if frame.stream_id > self.last_stream_id {
+			return protocol_error("WINDOW_UPDATE on an idle stream");
+		}
+
+		let error = match self.streams.get_mut(&frame.stream_id) {
+			None => return Ok(()),
+			Some(_) if increment == 0 => Some(ErrorCode::ProtocolError),
+			Some(stream) => {
+				stream.send_window += increment;
+				if stream.send_window > MAX_WINDOW_SIZE { Some(ErrorCode::FlowControlError) } else { None }
+			}
+		};
+
+		match error {
+			Some(code) => self.reset(frame.stream_id, code).map_err(H2Error::from),
+			None => self.flush_stream(frame.stream_id).map_err(H2Error::from)
+		}
+	}
+
+	fn on_ping(&mut self, frame: Frame) -> Result<(), H2Error> {
+		if frame.stream_id != 0 {
+			return protocol_error("PING on a stream");
+		}
+		if frame.payload.len() != 8 {
+			return Err(H2Error::Connection(ErrorCode::FrameSizeError, "PING must be 8 bytes"));
+		}
+
+		if !frame.has_flag(flags::ACK) {
+			self.write(Frame::new(FrameType::Ping, flags::ACK, 0, frame.payload))?;
+		}
+		Ok(())
+	}
+
+	fn on_rst_stream(&mut self, frame: Frame) -> Result<(), H2Error> {
+		if frame.stream_id == 0 || frame.stream_id > self.last_stream_id {
+			return protocol_error("RST_STREAM on stream 0 or an idle stream");
+		}
+		if frame.payload.len() != 4 {
+			return Err(H2Error::Connection(ErrorCode::FrameSizeError, "RST_STREAM must be 4 bytes"));
+		}
+
+		self.discard(frame.stream_id).map_err(H2Error::from)
+	}
+
+	fn on_priority(&mut self, frame: Frame) -> Result<(), H2Error> {
+		if frame.stream_id == 0 {
+			return protocol_error("PRIORITY on stream 0");
+		}
+		if frame.payload.len() != 5 {
+			return self.reset(frame.stream_id, ErrorCode::FrameSizeError).map_err(H2Error::from);
+		}
+		Ok(())
+	}
+
+	// The request on the stream is complete: queue it for a handler, or answer it here if it can't be handled
+	fn complete(&mut self, stream_id: u32) -> Result<(), H2Error> {
+		let (headers, body) = match self.streams.get_mut(&stream_id) {
+			Some(stream) => {
+				stream.state = StreamState::HalfClosedRemote;
+				(::std::mem::take(&mut stream.headers), ::std::mem::take(&mut stream.body))
+			},
+			None => return Ok(())
+		};
+
+		self.consumed(body.len())?;
+
+		match to_request(headers, body, self.limits.max_body_len) {
+			Ok(request) => self.ready.push((stream_id, request)),
+			Err(Rejection::Malformed) => self.reset(stream_id, ErrorCode::ProtocolError)?,
+			Err(Rejection::Status(status_line)) => self.on_response(stream_id, HttpResponse::from_status_line(status_line))?
+		}
+		Ok(())
+	}
+
+	fn new_stream(&self, headers: HeaderList, state: StreamState) -> Stream {
+		Stream {
+			state,
+			headers,
+			body: Vec::new(),
+			send_window: self.peer_initial_window,
+			recv_window: DEFAULT_WINDOW_SIZE,
+			pending: None
+		}
+	}
+
+	// Answers a request that is over the limits without waiting for the rest of it, then, if the client is still sending, asks it to stop (RFC 7540 8.1)
+	fn turn_away(&mut self, stream_id: u32, status_line: StatusLine, still_sending: bool) -> Result<(), H2Error> {
+		let discarded = match self.streams.get_mut(&stream_id) {
+			Some(stream) => {
+				stream.state = StreamState::HalfClosedRemote;
+				::std::mem::take(&mut stream.body).len()
+			},
+			None => return Ok(())
+		};
+		self.consumed(discarded)?;
+
+		self.on_response(stream_id, HttpResponse::from_status_line(status_line))?;
+		if still_sending && !self.streams.contains_key(&stream_id) {
+			self.write(Frame::new(FrameType::RstStream, 0, stream_id, ErrorCode::NoError.to_u32().to_be_bytes().to_vec()))?;
+		}
+		Ok(())
+	}
+
+	fn reset(&mut self, stream_id: u32, code: ErrorCode) -> io::Result<()> {
+		self.discard(stream_id)?;
+		self.write(Frame::new(FrameType::RstStream, 0, stream_id, code.to_u32().to_be_bytes().to_vec()))
+	}
+
+	// Closes the stream, handing back the connection window its unfinished body was holding
+	fn discard(&mut self, stream_id: u32) -> io::Result<()> {
+		let buffered = self.streams.remove(&stream_id).map(|s| s.body.len()).unwrap_or(0);
+		self.consumed(buffered)
+	}
+
+	// Lets the client send `len` more bytes on the connection, now that what it sent has been dealt with
+	fn consumed(&mut self, len: usize) -> io::Result<()> {
+		if len == 0 {
+			return Ok(());
+		}
+		self.recv_window += len as i64;
+		self.write(window_update(0, len as i64))
+	}
+
+	fn write_headers(&mut self, stream_id: u32, block: Vec<u8>, end_stream: bool) -> io::Result<()> {
+		let end_flag = if end_stream { flags::END_STREAM } else { 0 };
+		let mut chunks = block.chunks(self.peer_max_frame_size).peekable();
+		let mut frame_type = FrameType::Headers;
+
+		//an empty block still needs its HEADERS frame
+		if chunks.peek().is_none() {
+			return self.write(Frame::new(FrameType::Headers, flags::END_HEADERS | end_flag, stream_id, Vec::new()));
+		}
+
+		while let Some(chunk) = chunks.next() {
+			let mut frame_flags = if frame_type == FrameType::Headers { end_flag } else { 0 };
+			if chunks.peek().is_none() {
+				frame_flags |= flags::END_HEADERS;
+			}
+			let frame = Frame::new(frame_type, frame_flags, stream_id, chunk.to_vec());
+			frame.write(&mut self.writer)?;
+			frame_type = FrameType::Continuation;
+		}
+		self.writer.flush()
+	}
+
+	// Sends as much of the stream's pending body as both flow control windows allow
+	fn flush_stream(&mut self, stream_id: u32) -> io::Result<()> {
+		loop {
+			let max_frame_size = self.peer_max_frame_size as i64;
+			let connection_window = self.send_window;
+
+			let (frame, finished) = {
+				let stream = match self.streams.get_mut(&stream_id) {
+					Some(s) => s,
+					None => return Ok(())
+				};
+				let (body, sent) = match stream.pending {
+					Some((ref body, ref mut sent)) => (body, sent),
+					None => return Ok(())
+				};
+
+				let len = (body.len() - *sent) as i64;
+				let len = len.min(max_frame_size).min(connection_window).min(stream.send_window);
+				if len <= 0 {
+					return Ok(());
+				}
+
+				let chunk = body[*sent..*sent + len as usize].to_vec();
+				*sent += len as usize;
+				stream.send_window -= len;
+				let finished = *sent == body.len();
+				(Frame::new(FrameType::Data, if finished { flags::END_STREAM } else { 0 }, stream_id, chunk), finished)
+			};
+
+			self.send_window -= frame.payload.len() as i64;
+			self.write(frame)?;
+
+			if finished {
+				self.streams.remove(&stream_id);
+				return Ok(());
+			}
+		}
+	}
+
+	fn flush_all(&mut self) -> Result<(), H2Error> {
+		let mut waiting: Vec<u32> = self.streams.iter().filter(|&(_, s)| s.pending.is_some()).map(|(id, _)| *id).collect();
+		waiting.sort();
+
+		for stream_id in waiting {
+			self.flush_stream(stream_id)?;
+		}
+		Ok(())
+	}
+
+	fn write(&mut self, frame: Frame) -> io::Result<()> {
+		frame.write(&mut self.writer)?;
+		self.writer.flush()
+	}
+}
+
+fn decoder(limits: &Limits) -> Decoder {
+	Decoder::new(HEADER_TABLE_SIZE).with_limits(limits.max_header_bytes, limits.max_header_count)
+}
+
+fn content_too_large() -> StatusLine {
+	StatusLine::init(StatusCode::content_too_large(), String::from("Content Too Large"))
+}
+
+fn window_update(stream_id: u32, increment: i64) -> Frame {
+	Frame::new(FrameType::WindowUpdate, 0, stream_id, (increment as u32).to_be_bytes().to_vec())
+}
+
+enum Rejection {
+	/// Reset the stream with PROTOCOL_ERROR.
+	Malformed,
+	/// Well formed, but answered with this status instead of reaching a handler.
+	Status(StatusLine)
+}
+
+// Maps the pseudo-headers onto the same request line HTTP/1.1 requests have (RFC 7540 8.1.2)
+fn to_request(fields: HeaderList, body: Vec<u8>, max_body_len: usize) -> Result<HttpRequest, Rejection> {
+	let mut method = None;
+	let mut path = None;
+	let mut scheme = None;
+	let mut authority = None;
+	let mut headers = HeaderCollection::init_empty();
+	let mut regular_seen = false;
+
+	for (name, value) in fields {
+		if name.bytes().any(|b| b.is_ascii_uppercase()) {
+			return Err(Rejection::Malformed);
+		}
+
+		if name.starts_with(':') {
+			let field = match name.as_ref() {
+				":method" => &mut method,
+				":path" => &mut path,
+				":scheme" => &mut scheme,
+				":authority" => &mut authority,
+				_ => return Err(Rejection::Malformed)
+			};
+			//pseudo-headers come first, once each
+			if regular_seen || field.is_some() {
+				return Err(Rejection::Malformed);
+			}
+			*field = Some(value);
+			continue;
+		}
+
+		regular_seen = true;
+		if CONNECTION_HEADERS.contains(&name.as_str()) || (name == "te" && value != "trailers") {
+			return Err(Rejection::Malformed);
+		}
+		headers.get_or_add(&name).add(value);
+	}
+
+	let (method, path) = match (method, path, scheme) {
+		(Some(m), Some(p), Some(_)) if !p.is_empty() => (m, p),
+		_ => return Err(Rejection::Malformed)
+	};
+
+	if let Some(length) = headers.first("content-length") {
+		if length.parse::<usize>().ok() != Some(body.len()) {
+			return Err(Rejection::Malformed);
+		}
+	}
+	if body.len() > max_body_len {
+		return Err(Rejection::Status(content_too_large()));
+	}
+
+	if let Some(authority) = authority {
+		if headers.get("Host").is_none() {
+			headers.get_or_add("Host").add(authority);
+		}
+	}
+
+	let method = match AllowedMethod::from_utf8(method) {
+		Ok(m) => m,
+		Err((code, reason)) => return Err(Rejection::Status(StatusLine::init(code, reason)))
+	};
+
+	let uri = match Uri::parse(&path) {
+		Ok(ref uri) if !uri.path.absolute_path => return Err(Rejection::Malformed),
+		Ok(uri) => uri,
+		Err(e) => return Err(Rejection::Status(StatusLine::init(StatusCode::bad_request(), format!("Invalid request target: {}", e))))
+	};
+
+	Ok(HttpRequest {
+		start_line: StartLine {
+			method,
+			uri,
+			major_version: 2,
+			minor_version: 0
+		},
+		headers,
+		body
+	})
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn headers_frame(stream_id: u32, frame_flags: u8, fields: &[(&str, &str)]) -> Frame {
+		let fields: HeaderList = fields.iter().map(|&(n, v)| (n.to_string(), v.to_string())).collect();
+		Frame::new(FrameType::Headers, frame_flags, stream_id, Encoder::new().encode(&fields))
+	}
+
+	fn started() -> Connection<Vec<u8>> {
+		let mut connection = Connection::new(Vec::new());
+		connection.on_frame(Frame::new(FrameType::Settings, 0, 0, Vec::new())).unwrap();
+		connection.writer.clear();
+		connection
+	}
+
+	fn written(connection: &mut Connection<Vec<u8>>) -> Vec<Frame> {
+		let bytes = ::std::mem::take(&mut connection.writer);
+		let mut reader = &bytes[..];
+		let mut frames = Vec::new();
+		while !reader.is_empty() {
+			frames.push(Frame::read(&mut reader, 1 << 24).unwrap());
+		}
+		frames
+	}
+
+	#[test]
+	fn settings_must_come_first_and_are_acked() {
+		let mut connection = Connection::new(Vec::new());
+		assert!(connection.on_frame(Frame::new(FrameType::Ping, 0, 0, vec![0; 8])).is_err());
+
+		let mut connection = Connection::new(Vec::new());
+		connection.on_frame(Frame::new(FrameType::Settings, 0, 0, vec![0, 5, 0, 0, 0x80, 0])).unwrap();
+		assert_eq!(connection.peer_max_frame_size, 32768);
+		assert_eq!(written(&mut connection), vec![Frame::new(FrameType::Settings, flags::ACK, 0, Vec::new())]);
+	}
+
+	#[test]
+	fn interleaved_streams_become_requests() {
+		let mut connection = started();
+		let post = headers_frame(1, flags::END_HEADERS,
+			&[(":method", "POST"), (":scheme", "http"), (":path", "/test/post")]);
+		let get = headers_frame(3, flags::END_HEADERS | flags::END_STREAM,
+			&[(":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "localhost")]);
+
+		connection.on_frame(post).unwrap();
+		connection.on_frame(get).unwrap();
+		connection.on_frame(Frame::new(FrameType::Data, flags::END_STREAM, 1, b"body".to_vec())).unwrap();
+
+		let ready = connection.take_ready();
+		assert_eq!(ready.len(), 2);
+		assert_eq!(ready[0].0, 3);
+		assert_eq!(ready[0].1.headers.first("Host"), Some("localhost"));
+		assert_eq!(ready[1].1.start_line.method, AllowedMethod::POST);
+		assert_eq!(ready[1].1.body, b"body".to_vec());
+	}
+
+	#[test]
+	fn reset_streams_count_against_the_limit_until_answered() {
+		let mut connection = started();
+		let get = |id| headers_frame(id, flags::END_HEADERS | flags::END_STREAM, &[(":method", "GET"), (":scheme", "http"), (":path", "/")]);
+		for i in 0..MAX_CONCURRENT_STREAMS {
+			let stream_id = 2 * i + 1;
+			connection.on_frame(get(stream_id)).unwrap();
+			assert_eq!(connection.take_ready().len(), 1);
+			connection.on_frame(Frame::new(FrameType::RstStream, 0, stream_id, vec![0, 0, 0, 8])).unwrap();
+		}
+		assert!(connection.streams.is_empty());
+		written(&mut connection);
+
+		let refused = 2 * MAX_CONCURRENT_STREAMS + 1;
+		connection.on_frame(get(refused)).unwrap();
+		assert!(connection.take_ready().is_empty());
+		assert_eq!(written(&mut connection), vec![Frame::new(FrameType::RstStream, 0, refused, vec![0, 0, 0, 7])]);
+
+		//the handler's answer goes nowhere, but frees its place
+		connection.on_response(1, HttpResponse::new(StatusCode::ok())).unwrap();
+		assert!(written(&mut connection).is_empty());
+		connection.on_frame(get(refused + 2)).unwrap();
+		assert_eq!(connection.take_ready().len(), 1);
+	}
+
+	#[test]
+	fn malformed_requests_reset_the_stream() {
+		let mut connection = started();
+		let frame = headers_frame(1, flags::END_HEADERS | flags::END_STREAM,
+			&[(":method", "GET"), (":scheme", "http"), (":path", "/"), ("connection", "keep-alive")]);
+		connection.on_frame(frame).unwrap();
+
+		assert!(connection.take_ready().is_empty());
+		assert_eq!(written(&mut connection), vec![Frame::new(FrameType::RstStream, 0, 1, vec![0, 0, 0, 1])]);
+
+		let frame = headers_frame(1, flags::END_HEADERS, &[]);
+		assert!(connection.on_frame(frame).is_err(), "stream ids can't be reused");
+	}
+
+	#[test]
+	fn response_body_waits_for_flow_control() {
+		let mut connection = started();
+		connection.on_frame(Frame::new(FrameType::Settings, 0, 0, vec![0, 4, 0, 0, 0, 10])).unwrap();
+		let frame = headers_frame(1, flags::END_HEADERS | flags::END_STREAM,
+			&[(":method", "GET"), (":scheme", "http"), (":path", "/")]);
+		connection.on_frame(frame).unwrap();
+		connection.take_ready();
+		written(&mut connection);
+
+		connection.on_response(1, HttpResponse::new(StatusCode::ok()).with_body("text/plain", "fifteen bytes!!")).unwrap();
+		let frames = written(&mut connection);
+		assert_eq!(frames.len(), 2);
+		let fields = Decoder::new(4096).decode(&frames[0].payload).unwrap();
+		assert!(fields.contains(&(String::from(":status"), String::from("200"))));
+		assert!(fields.contains(&(String::from("content-length"), String::from("15"))));
+		assert_eq!(frames[1], Frame::new(FrameType::Data, 0, 1, b"fifteen by".to_vec()));
+
+		connection.on_frame(Frame::new(FrameType::WindowUpdate, 0, 1, vec![0, 0, 0, 10])).unwrap();
+		assert_eq!(written(&mut connection), vec![Frame::new(FrameType::Data, flags::END_STREAM, 1, b"tes!!".to_vec())]);
+		assert!(connection.streams.is_empty());
+	}
+
+	#[test]
+	fn request_window_only_comes_back_once_the_body_is_taken() {
+		let mut connection = started();
+		let post = headers_frame(1, flags::END_HEADERS, &[(":method", "POST"), (":scheme", "http"), (":path", "/")]);
+		connection.on_frame(post).unwrap();
+
+		connection.on_frame(Frame::new(FrameType::Data, 0, 1, b"some".to_vec())).unwrap();
+		assert_eq!(written(&mut connection), vec![window_update(1, 4)]);
+		connection.on_frame(Frame::new(FrameType::Data, flags::END_STREAM, 1, b" body".to_vec())).unwrap();
+		assert_eq!(written(&mut connection), vec![window_update(0, 9)]);
+		assert_eq!(connection.take_ready()[0].1.body, b"some body".to_vec());
+
+		let get = headers_frame(3, flags::END_HEADERS, &[(":method", "POST"), (":scheme", "http"), (":path", "/")]);
+		connection.on_frame(get).unwrap();
+		let flood = Frame::new(FrameType::Data, 0, 3, vec![0; DEFAULT_WINDOW_SIZE as usize + 1]);
+		assert!(matches!(connection.on_frame(flood), Err(H2Error::Connection(ErrorCode::FlowControlError, _))));
+	}
+
+	#[test]
+	fn bodies_over_the_limit_are_turned_away() {
+		let limits = Limits { max_body_len: 8, ..Limits::default() };
+		let mut connection = Connection::new(Vec::new()).with_limits(limits);
+		connection.on_frame(Frame::new(FrameType::Settings, 0, 0, Vec::new())).unwrap();
+		let post = |id| headers_frame(id, flags::END_HEADERS, &[(":method", "POST"), (":scheme", "http"), (":path", "/")]);
+
+		connection.on_frame(post(1)).unwrap();
+		connection.on_frame(Frame::new(FrameType::Data, 0, 1, b"12345".to_vec())).unwrap();
+		written(&mut connection);
+		connection.on_frame(Frame::new(FrameType::Data, 0, 1, b"6789".to_vec())).unwrap();
+		let frames = written(&mut connection);
+		assert_eq!(frames[0], window_update(0, 4));
+		assert_eq!(frames[1], window_update(0, 5));
+		let fields = Decoder::new(4096).decode(&frames[2].payload).unwrap();
+		assert!(fields.contains(&(String::from(":status"), String::from("413"))));
+		assert_eq!(frames.last(), Some(&Frame::new(FrameType::RstStream, 0, 1, vec![0, 0, 0, 0])));
+		assert!(connection.take_ready().is_empty());
+
+		let declared = headers_frame(3, flags::END_HEADERS,
+			&[(":method", "POST"), (":scheme", "http"), (":path", "/"), ("content-length", "9")]);
+		connection.on_frame(declared).unwrap();
+		let frames = written(&mut connection);
+		let fields = Decoder::new(4096).decode(&frames[0].payload).unwrap();
+		assert!(fields.contains(&(String::from(":status"), String::from("413"))));
+	}
+
+	#[test]
+	fn header_lists_over_the_limits_get_431() {
+		let limits = Limits { max_header_count: 2, ..Limits::default() };
+		let mut connection = Connection::new(Vec::new()).with_limits(limits);
+		connection.start().unwrap();
+		let settings = written(&mut connection).remove(0);
+		assert!(settings.payload.chunks(6).any(|s| s == [0, 6, 0, 1, 0, 0]), "advertises SETTINGS_MAX_HEADER_LIST_SIZE");
+		connection.on_frame(Frame::new(FrameType::Settings, 0, 0, Vec::new())).unwrap();
+		written(&mut connection);
+
+		let frame = headers_frame(1, flags::END_HEADERS | flags::END_STREAM,
+			&[(":method", "GET"), (":scheme", "http"), (":path", "/"), ("a", "1"), ("b", "2"), ("c", "3")]);
+		connection.on_frame(frame).unwrap();
+		assert!(connection.take_ready().is_empty());
+		let frames = written(&mut connection);
+		let fields = Decoder::new(4096).decode(&frames[0].payload).unwrap();
+		assert!(fields.contains(&(String::from(":status"), String::from("431"))));
+
+		let frame = headers_frame(3, flags::END_HEADERS | flags::END_STREAM, &[(":method", "GET"), (":scheme", "http"), (":path", "/")]);
+		connection.on_frame(frame).unwrap();
+		assert_eq!(connection.take_ready().len(), 1);
+	}
+}
@@ -0,0 +1,180 @@
+use std::io;
+use std::io::{Read, Write};
+
+pub const HEADER_LEN: usize = 9;
+//SETTINGS_MAX_FRAME_SIZE starts at this and we never ask for more
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16_384;
+
+pub mod flags {
+	pub const END_STREAM: u8 = 0x1;
+	pub const ACK: u8 = 0x1;
+	pub const END_HEADERS: u8 = 0x4;
+	pub const PADDED: u8 = 0x8;
+	pub const PRIORITY: u8 = 0x20;
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FrameType {
+	Data,
+	Headers,
+	Priority,
+	RstStream,
+	Settings,
+	PushPromise,
+	Ping,
+	GoAway,
+	WindowUpdate,
+	Continuation,
+	/// Frames of unknown types must be ignored (RFC 7540 4.1).
+	Unknown(u8)
+}
+
+impl FrameType {
+	fn from_byte(b: u8) -> FrameType {
+		match b {
+			0x0 => FrameType::Data,
+			0x1 => FrameType::Headers,
+			0x2 => FrameType::Priority,
+			0x3 => FrameType::RstStream,
+			0x4 => FrameType::Settings,
+			0x5 => FrameType::PushPromise,
+			0x6 => FrameType::Ping,
+			0x7 => FrameType::GoAway,
+			0x8 => FrameType::WindowUpdate,
+			0x9 => FrameType::Continuation,
+			b => FrameType::Unknown(b)
+		}
+	}
+
+	fn to_byte(self) -> u8 {
+		match self {
+			FrameType::Data => 0x0,
+			FrameType::Headers => 0x1,
+			FrameType::Priority => 0x2,
+			FrameType::RstStream => 0x3,
+			FrameType::Settings => 0x4,
+			FrameType::PushPromise => 0x5,
+			FrameType::Ping => 0x6,
+			FrameType::GoAway => 0x7,
+			FrameType::WindowUpdate => 0x8,
+			FrameType::Continuation => 0x9,
+			FrameType::Unknown(b) => b
+		}
+	}
+}
+
+/// RFC 7540 section 7. Only the codes the server sends; ones the client sends are never looked at.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ErrorCode {
+	NoError,
+	ProtocolError,
+	FlowControlError,
+	StreamClosed,
+	FrameSizeError,
+	RefusedStream,
+	CompressionError,
+	EnhanceYourCalm
+}
+
+impl ErrorCode {
+	pub fn to_u32(self) -> u32 {
+		match self {
+			ErrorCode::NoError => 0x0,
+			ErrorCode::ProtocolError => 0x1,
+			ErrorCode::FlowControlError => 0x3,
+			ErrorCode::StreamClosed => 0x5,
+			ErrorCode::FrameSizeError => 0x6,
+			ErrorCode::RefusedStream => 0x7,
+			ErrorCode::CompressionError => 0x9,
+			ErrorCode::EnhanceYourCalm => 0xb
+		}
+	}
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Frame {
+	pub frame_type: FrameType,
+	pub flags: u8,
+	pub stream_id: u32,
+	pub payload: Vec<u8>
+}
+
+impl Frame {
+	pub fn new(frame_type: FrameType, flags: u8, stream_id: u32, payload: Vec<u8>) -> Frame {
+		Frame { frame_type, flags, stream_id, payload }
+	}
+
+	pub fn has_flag(&self, flag: u8) -> bool {
+		self.flags & flag != 0
+	}
+
+	/// Reads the next frame. Frames longer than `max_size` are a FRAME_SIZE_ERROR, returned as `InvalidData`.
+	pub fn read<R: Read>(reader: &mut R, max_size: usize) -> io::Result<Frame> {
+		let mut header = [0_u8; HEADER_LEN];
+		reader.read_exact(&mut header)?;
+
+		let len = (header[0] as usize) << 16 | (header[1] as usize) << 8 | header[2] as usize;
+		if len > max_size {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "frame larger than SETTINGS_MAX_FRAME_SIZE"));
+		}
+
+		let mut payload = vec![0_u8; len];
+		reader.read_exact(&mut payload)?;
+
+		Ok(Frame {
+			frame_type: FrameType::from_byte(header[3]),
+			flags: header[4],
+			//the reserved high bit is ignored
+			stream_id: u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & 0x7fff_ffff,
+			payload
+		})
+	}
+
+	pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+		let len = self.payload.len();
+		let mut bytes = Vec::with_capacity(HEADER_LEN + len);
+		bytes.extend_from_slice(&[(len >> 16) as u8, (len >> 8) as u8, len as u8, self.frame_type.to_byte(), self.flags]);
+		bytes.extend_from_slice(&self.stream_id.to_be_bytes());
+		bytes.extend_from_slice(&self.payload);
+		writer.write_all(&bytes)
+	}
+
+	/// The payload without padding, for frame types that may be PADDED. None if the padding is longer than the payload.
+	pub fn unpadded_payload(&self) -> Option<&[u8]> {
+		if !self.has_flag(flags::PADDED) {
+			return Some(&self.payload);
+		}
+
+		let pad_len = *self.payload.first()? as usize;
+		if pad_len >= self.payload.len() {
+			return None;
+		}
+		Some(&self.payload[1..self.payload.len() - pad_len])
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn frames_round_trip() {
+		let frame = Frame::new(FrameType::Headers, flags::END_HEADERS | flags::END_STREAM, 3, vec![0x82, 0x86]);
+		let mut bytes = Vec::new();
+		frame.write(&mut bytes).unwrap();
+		assert_eq!(bytes, vec![0, 0, 2, 1, 5, 0, 0, 0, 3, 0x82, 0x86]);
+		assert_eq!(Frame::read(&mut &bytes[..], DEFAULT_MAX_FRAME_SIZE).unwrap(), frame);
+
+		assert!(Frame::read(&mut &bytes[..], 1).is_err());
+	}
+
+	#[test]
+	fn padding_removed() {
+		let frame = Frame::new(FrameType::Data, flags::PADDED, 1, vec![2, b'h', b'i', 0, 0]);
+		assert_eq!(frame.unpadded_payload(), Some(&b"hi"[..]));
+
+		let frame = Frame::new(FrameType::Data, flags::PADDED, 1, vec![4, b'h', 0, 0]);
+		assert_eq!(frame.unpadded_payload(), None);
+	}
+}
@@ -0,0 +1,309 @@
+mod huffman;
+mod table;
+
+use std::fmt;
+
+use self::table::{DynamicTable, STATIC_TABLE};
+
+pub type HeaderList = Vec<(String, String)>;
+
+//what each field adds to a header list's size besides its name and value (RFC 7540 6.5.2)
+const FIELD_OVERHEAD: usize = 32;
+
+#[derive(PartialEq, Debug)]
+pub enum HpackError {
+	Truncated,
+	IntegerOverflow,
+	InvalidIndex(usize),
+	Huffman,
+	TableSizeUpdate,
+	/// The block decoded fine, but to more fields or bytes than allowed.
+	ListTooLarge
+}
+
+impl fmt::Display for HpackError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			HpackError::Truncated => write!(f, "header block ended in the middle of a field"),
+			HpackError::IntegerOverflow => write!(f, "integer too large"),
+			HpackError::InvalidIndex(ix) => write!(f, "no header table entry at index {}", ix),
+			HpackError::Huffman => write!(f, "invalid huffman encoded string"),
+			HpackError::TableSizeUpdate => write!(f, "invalid dynamic table size update"),
+			HpackError::ListTooLarge => write!(f, "header list larger than allowed")
+		}
+	}
+}
+
+/// Decodes header blocks from one peer. Blocks must be decoded in the order they were sent,
+/// since each one can change the dynamic table the next one refers to.
+pub struct Decoder {
+	table: DynamicTable,
+	//the SETTINGS_HEADER_TABLE_SIZE we advertised; the peer may only ask for less
+	max_table_size: usize,
+	//the SETTINGS_MAX_HEADER_LIST_SIZE we advertised
+	max_list_size: usize,
+	max_field_count: usize
+}
+
+impl Decoder {
+	pub fn new(max_table_size: usize) -> Decoder {
+		Decoder {
+			table: DynamicTable::new(max_table_size),
+			max_table_size,
+			max_list_size: usize::MAX,
+			max_field_count: usize::MAX
+		}
+	}
+
+	/// Blocks that decode to a list larger than `max_list_size`, counted as RFC 7540 6.5.2 does, or with more than
+	/// `max_field_count` fields besides the pseudo-headers, are a `ListTooLarge`. A few bytes can refer to a long table entry
+	/// over and over, so this is what bounds the memory a block takes.
+	pub fn with_limits(mut self, max_list_size: usize, max_field_count: usize) -> Decoder {
+		self.max_list_size = max_list_size;
+		self.max_field_count = max_field_count;
+		self
+	}
+
+	/// Decodes the next block. One that is too large is still decoded to the end, to keep the table in step with the peer's.
+	pub fn decode(&mut self, block: &[u8]) -> Result<HeaderList, HpackError> {
+		let mut headers = Vec::new();
+		//size and field count of the list so far, whether or not the fields were kept
+		let mut totals = (0, 0);
+		let mut pos = 0;
+
+		while pos < block.len() {
+			let first = block[pos];
+
+			if first & 0x80 != 0 {
+				let index = decode_integer(block, &mut pos, 7)?;
+				let (name, value) = self.entry(index)?;
+				if self.within_limits(&mut totals, name, value) {
+					headers.push((name.to_string(), value.to_string()));
+				}
+			} else if first & 0x40 != 0 {
+				let (name, value) = self.decode_literal(block, &mut pos, 6)?;
+				self.table.insert(name.clone(), value.clone());
+				if self.within_limits(&mut totals, &name, &value) {
+					headers.push((name, value));
+				}
+			} else if first & 0x20 != 0 {
+				//size updates are only allowed before the first field of a block
+				if totals.0 > 0 {
+					return Err(HpackError::TableSizeUpdate);
+				}
+				let size = decode_integer(block, &mut pos, 5)?;
+				if size > self.max_table_size {
+					return Err(HpackError::TableSizeUpdate);
+				}
+				self.table.set_max_size(size);
+			} else {
+				//without indexing (0000) and never indexed (0001) only differ for intermediaries
+				let (name, value) = self.decode_literal(block, &mut pos, 4)?;
+				if self.within_limits(&mut totals, &name, &value) {
+					headers.push((name, value));
+				}
+			}
+		}
+
+		if totals.0 > self.max_list_size || totals.1 > self.max_field_count {
+			return Err(HpackError::ListTooLarge);
+		}
+		Ok(headers)
+	}
+
+	// Counts the field towards the list, and says whether the list is still small enough to keep
+	fn within_limits(&self, totals: &mut (usize, usize), name: &str, value: &str) -> bool {
+		totals.0 += name.len() + value.len() + FIELD_OVERHEAD;
+		//pseudo-headers stand in for the request line, which HTTP/1.1 doesn't count as a field either
+		if !name.starts_with(':') {
+			totals.1 += 1;
+		}
+		totals.0 <= self.max_list_size && totals.1 <= self.max_field_count
+	}
+
+	fn entry(&self, index: usize) -> Result<(&str, &str), HpackError> {
+		self.table.get(index).ok_or(HpackError::InvalidIndex(index))
+	}
+
+	fn decode_literal(&self, block: &[u8], pos: &mut usize, prefix: u8) -> Result<(String, String), HpackError> {
+		let index = decode_integer(block, pos, prefix)?;
+		let name = if index == 0 {
+			decode_string(block, pos)?
+		} else {
+			self.entry(index)?.0.to_string()
+		};
+
+		Ok((name, decode_string(block, pos)?))
+	}
+}
+
+/// Encodes header blocks without using the dynamic table, so the peer's table size settings never matter.
+pub struct Encoder;
+
+impl Encoder {
+	pub fn new() -> Encoder {
+		Encoder
+	}
+
+	pub fn encode(&mut self, headers: &[(String, String)]) -> Vec<u8> {
+		let mut block = Vec::new();
+
+		for (name, value) in headers {
+			let full_match = STATIC_TABLE.iter().position(|&(n, v)| n == name && v == value);
+			if let Some(ix) = full_match {
+				encode_integer(&mut block, 0x80, 7, ix + 1);
+				continue;
+			}
+
+			match STATIC_TABLE.iter().position(|&(n, _)| n == name) {
+				Some(ix) => encode_integer(&mut block, 0x00, 4, ix + 1),
+				None => {
+					block.push(0x00);
+					encode_string(&mut block, name.as_bytes());
+				}
+			}
+			encode_string(&mut block, value.as_bytes());
+		}
+
+		block
+	}
+}
+
+fn decode_integer(block: &[u8], pos: &mut usize, prefix: u8) -> Result<usize, HpackError> {
+	let max_prefix = (1_usize << prefix) - 1;
+	let mut value = (*block.get(*pos).ok_or(HpackError::Truncated)? as usize) & max_prefix;
+	*pos += 1;
+
+	if value < max_prefix {
+		return Ok(value);
+	}
+
+	let mut shift = 0;
+	loop {
+		let byte = *block.get(*pos).ok_or(HpackError::Truncated)?;
+		*pos += 1;
+
+		//nothing legitimate needs more than 28 bits
+		if shift > 21 {
+			return Err(HpackError::IntegerOverflow);
+		}
+		value += ((byte & 0x7f) as usize) << shift;
+		shift += 7;
+
+		if byte & 0x80 == 0 {
+			return Ok(value);
+		}
+	}
+}
+
+fn encode_integer(block: &mut Vec<u8>, flags: u8, prefix: u8, value: usize) {
+	let max_prefix = (1_usize << prefix) - 1;
+	if value < max_prefix {
+		block.push(flags | value as u8);
+		return;
+	}
+
+	block.push(flags | max_prefix as u8);
+	let mut rest = value - max_prefix;
+	while rest >= 0x80 {
+		block.push((rest & 0x7f) as u8 | 0x80);
+		rest >>= 7;
+	}
+	block.push(rest as u8);
+}
+
+fn decode_string(block: &[u8], pos: &mut usize) -> Result<String, HpackError> {
+	let huffman_coded = *block.get(*pos).ok_or(HpackError::Truncated)? & 0x80 != 0;
+	let len = decode_integer(block, pos, 7)?;
+	if block.len() - *pos < len {
+		return Err(HpackError::Truncated);
+	}
+
+	let raw = &block[*pos..*pos + len];
+	*pos += len;
+
+	let bytes = if huffman_coded { huffman::decode(raw)? } else { raw.to_vec() };
+	Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn encode_string(block: &mut Vec<u8>, raw: &[u8]) {
+	if huffman::encoded_len(raw) < raw.len() {
+		let encoded = huffman::encode(raw);
+		encode_integer(block, 0x80, 7, encoded.len());
+		block.extend_from_slice(&encoded);
+	} else {
+		encode_integer(block, 0x00, 7, raw.len());
+		block.extend_from_slice(raw);
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn headers(list: &[(&str, &str)]) -> HeaderList {
+		list.iter().map(|&(n, v)| (n.to_string(), v.to_string())).collect()
+	}
+
+	#[test]
+	fn integers() {
+		//RFC 7541 C.1
+		let mut block = Vec::new();
+		encode_integer(&mut block, 0, 5, 10);
+		encode_integer(&mut block, 0, 5, 1337);
+		assert_eq!(block, vec![0x0a, 0x1f, 0x9a, 0x0a]);
+
+		let mut pos = 0;
+		assert_eq!(decode_integer(&block, &mut pos, 5), Ok(10));
+		assert_eq!(decode_integer(&block, &mut pos, 5), Ok(1337));
+		assert_eq!(decode_integer(&[0x1f, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01], &mut 0, 5), Err(HpackError::IntegerOverflow));
+	}
+
+	#[test]
+	fn rfc_request_examples_with_huffman() {
+		//RFC 7541 C.4, three requests sharing one dynamic table
+		let mut decoder = Decoder::new(4096);
+
+		let first = [0x82, 0x86, 0x84, 0x41, 0x8c, 0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4, 0xff];
+		assert_eq!(decoder.decode(&first).unwrap(), headers(&[
+			(":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com")]));
+
+		let second = [0x82, 0x86, 0x84, 0xbe, 0x58, 0x86, 0xa8, 0xeb, 0x10, 0x64, 0x9c, 0xbf];
+		assert_eq!(decoder.decode(&second).unwrap(), headers(&[
+			(":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com"), ("cache-control", "no-cache")]));
+
+		let third = [0x82, 0x87, 0x85, 0xbf, 0x40, 0x88, 0x25, 0xa8, 0x49, 0xe9, 0x5b, 0xa9, 0x7d, 0x7f, 0x89, 0x25, 0xa8, 0x49, 0xe9, 0x5b, 0xb8, 0xe8, 0xb4, 0xbf];
+		assert_eq!(decoder.decode(&third).unwrap(), headers(&[
+			(":method", "GET"), (":scheme", "https"), (":path", "/index.html"), (":authority", "www.example.com"), ("custom-key", "custom-value")]));
+	}
+
+	#[test]
+	fn encoded_headers_decode() {
+		let list = headers(&[(":status", "200"), (":status", "303"), ("content-type", "text/html; charset=UTF-8"), ("x-custom", "a")]);
+		let block = Encoder::new().encode(&list);
+		assert_eq!(block[0], 0x88);
+		assert_eq!(Decoder::new(4096).decode(&block).unwrap(), list);
+
+		assert_eq!(Decoder::new(4096).decode(&[0xc0]), Err(HpackError::InvalidIndex(64)));
+		assert_eq!(Decoder::new(4096).decode(&[0x3f, 0xe2, 0x1f]), Err(HpackError::TableSizeUpdate));
+	}
+
+	#[test]
+	fn large_lists_are_refused_but_keep_the_table() {
+		let mut decoder = Decoder::new(4096).with_limits(200, 2);
+		let long = "x".repeat(100);
+		let mut block = Encoder::new().encode(&headers(&[(":method", "GET")]));
+		//a literal with incremental indexing, then two references to it
+		block.push(0x40);
+		encode_string(&mut block, b"x-long");
+		encode_string(&mut block, long.as_bytes());
+		block.extend_from_slice(&[0xbe, 0xbe]);
+		assert_eq!(decoder.decode(&block), Err(HpackError::ListTooLarge));
+		assert_eq!(decoder.decode(&[0xbe]).unwrap(), headers(&[("x-long", &long)]));
+
+		let mut decoder = Decoder::new(4096).with_limits(usize::MAX, 2);
+		let block = Encoder::new().encode(&headers(&[(":path", "/"), ("a", "1"), ("b", "2"), ("c", "3")]));
+		assert_eq!(decoder.decode(&block), Err(HpackError::ListTooLarge));
+	}
+}
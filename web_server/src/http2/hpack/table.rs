@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+
+/// RFC 7541 Appendix A; index 1 is the first entry.
+pub const STATIC_TABLE: [(&str, &str); 61] = [
+	(":authority", ""),
+	(":method", "GET"),
+	(":method", "POST"),
+	(":path", "/"),
+	(":path", "/index.html"),
+	(":scheme", "http"),
+	(":scheme", "https"),
+	(":status", "200"),
+	(":status", "204"),
+	(":status", "206"),
+	(":status", "304"),
+	(":status", "400"),
+	(":status", "404"),
+	(":status", "500"),
+	("accept-charset", ""),
+	("accept-encoding", "gzip, deflate"),
+	("accept-language", ""),
+	("accept-ranges", ""),
+	("accept", ""),
+	("access-control-allow-origin", ""),
+	("age", ""),
+	("allow", ""),
+	("authorization", ""),
+	("cache-control", ""),
+	("content-disposition", ""),
+	("content-encoding", ""),
+	("content-language", ""),
+	("content-length", ""),
+	("content-location", ""),
+	("content-range", ""),
+	("content-type", ""),
+	("cookie", ""),
+	("date", ""),
+	("etag", ""),
+	("expect", ""),
+	("expires", ""),
+	("from", ""),
+	("host", ""),
+	("if-match", ""),
+	("if-modified-since", ""),
+	("if-none-match", ""),
+	("if-range", ""),
+	("if-unmodified-since", ""),
+	("last-modified", ""),
+	("link", ""),
+	("location", ""),
+	("max-forwards", ""),
+	("proxy-authenticate", ""),
+	("proxy-authorization", ""),
+	("range", ""),
+	("referer", ""),
+	("refresh", ""),
+	("retry-after", ""),
+	("server", ""),
+	("set-cookie", ""),
+	("strict-transport-security", ""),
+	("transfer-encoding", ""),
+	("user-agent", ""),
+	("vary", ""),
+	("via", ""),
+	("www-authenticate", ""),
+];
+
+//every entry costs 32 bytes on top of its name and value (RFC 7541 4.1)
+const ENTRY_OVERHEAD: usize = 32;
+
+pub struct DynamicTable {
+	entries: VecDeque<(String, String)>,
+	size: usize,
+	max_size: usize
+}
+
+impl DynamicTable {
+	pub fn new(max_size: usize) -> DynamicTable {
+		DynamicTable {
+			entries: VecDeque::new(),
+			size: 0,
+			max_size
+		}
+	}
+
+	/// Looks up an index in the combined static and dynamic index space.
+	pub fn get(&self, index: usize) -> Option<(&str, &str)> {
+		if index == 0 {
+			None
+		} else if index <= STATIC_TABLE.len() {
+			Some(STATIC_TABLE[index - 1])
+		} else {
+			self.entries.get(index - STATIC_TABLE.len() - 1).map(|(n, v)| (n.as_str(), v.as_str()))
+		}
+	}
+
+	pub fn insert(&mut self, name: String, value: String) {
+		let size = name.len() + value.len() + ENTRY_OVERHEAD;
+		self.size += size;
+		self.entries.push_front((name, value));
+		self.evict();
+	}
+
+	pub fn set_max_size(&mut self, max_size: usize) {
+		self.max_size = max_size;
+		self.evict();
+	}
+
+	fn evict(&mut self) {
+		while self.size > self.max_size {
+			match self.entries.pop_back() {
+				Some((n, v)) => self.size -= n.len() + v.len() + ENTRY_OVERHEAD,
+				None => break
+			}
+		}
+	}
+}
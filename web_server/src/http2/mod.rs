@@ -0,0 +1,152 @@
+mod connection;
+mod frame;
+mod hpack;
+
+pub use self::connection::{Connection, H2Error};
+pub use self::frame::{Frame, ErrorCode};
+
+use std::io;
+use std::io::{Cursor, Read};
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::channel;
+use std::thread;
+
+use encoding::from_base64;
+use http::model::*;
+use http::limits::Limits;
+use self::frame::DEFAULT_MAX_FRAME_SIZE;
+
+/// What a client sends first on an HTTP/2 connection, whether it knew to use HTTP/2 or upgraded to it.
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+enum Event {
+	Frame(Frame),
+	//the read failed; true if it was because the frame was too large
+	ReadFailed(bool),
+	Response(u32, HttpResponse)
+}
+
+/// Whether an HTTP/1.1 request asks to continue the connection as cleartext HTTP/2 (RFC 7540 3.2).
+pub fn is_upgrade_request(request: &HttpRequest) -> bool {
+	has_token(request, "Upgrade", "h2c") && nominates(request, &HttpHeaderType::Http2Settings)
+		&& request.headers.get("HTTP2-Settings").map(|h| h.values().len() == 1).unwrap_or(false)
+}
+
+pub fn upgrade_response() -> HttpResponse {
+	HttpResponse::new(StatusCode::switching_protocols())
+		.with_header("Connection", "Upgrade")
+		.with_header("Upgrade", "h2c")
+}
+
+fn has_token(request: &HttpRequest, header: &str, token: &str) -> bool {
+	request.headers.get(header)
+		.map(|h| h.values().iter().flat_map(|v| v.split(',')).any(|t| t.trim().eq_ignore_ascii_case(token)))
+		.unwrap_or(false)
+}
+
+//Connection names the header fields meant only for this hop
+fn nominates(request: &HttpRequest, header_type: &HttpHeaderType) -> bool {
+	request.headers.get("Connection")
+		.map(|h| h.values().iter().flat_map(|v| v.split(',')).any(|t| HttpHeaderType::from_raw_name(t.trim()) == *header_type))
+		.unwrap_or(false)
+}
+
+/// Serves HTTP/2 on the connection until the client goes away. Each request is answered by `service`
+/// on its own thread, so slow handlers don't hold up the other streams.
+/// `upgraded_from` is the HTTP/1.1 request that asked for the upgrade, already answered with a 101.
+/// Request bodies are held to the same limits as on HTTP/1.1.
+pub fn serve<F>(stream: TcpStream, buffered: Vec<u8>, upgraded_from: Option<HttpRequest>, limits: Limits, service: &F) -> io::Result<()>
+	where F: Fn(HttpRequest) -> HttpResponse + Sync {
+	stream.set_read_timeout(None)?;
+	let mut reader = Cursor::new(buffered).chain(stream.try_clone()?);
+	let mut connection = Connection::new(stream.try_clone()?).with_limits(limits);
+	connection.start()?;
+
+	if let Some(request) = upgraded_from {
+		//HTTP2-Settings is a SETTINGS payload in the url-safe base64 alphabet, without padding
+		let settings = request.headers.first("HTTP2-Settings")
+			.and_then(|s| from_base64(&s.replace('-', "+").replace('_', "/")))
+			.unwrap_or_else(|| vec![0]);
+
+		if let Err(H2Error::Connection(code, _)) = connection.upgraded(request, &settings) {
+			return connection.go_away(code);
+		}
+	}
+
+	let mut preface = [0_u8; 24];
+	reader.read_exact(&mut preface)?;
+	if preface != PREFACE {
+		return connection.go_away(ErrorCode::ProtocolError);
+	}
+
+	let (events_sender, events) = channel();
+
+	thread::scope(|scope| {
+		let frames = events_sender.clone();
+		scope.spawn(move || {
+			loop {
+				match Frame::read(&mut reader, DEFAULT_MAX_FRAME_SIZE) {
+					Ok(frame) => if frames.send(Event::Frame(frame)).is_err() { break },
+					Err(e) => {
+						let _ = frames.send(Event::ReadFailed(e.kind() == io::ErrorKind::InvalidData));
+						break;
+					}
+				}
+			}
+		});
+
+		let result = loop {
+			for (stream_id, request) in connection.take_ready() {
+				let responses = events_sender.clone();
+				scope.spawn(move || {
+					let _ = responses.send(Event::Response(stream_id, service(request)));
+				});
+			}
+
+			if connection.is_finished() {
+				break Ok(());
+			}
+
+			let handled = match events.recv() {
+				Ok(Event::Frame(frame)) => connection.on_frame(frame),
+				Ok(Event::Response(stream_id, response)) => connection.on_response(stream_id, response).map_err(H2Error::from),
+				Ok(Event::ReadFailed(true)) => Err(H2Error::Connection(ErrorCode::FrameSizeError, "frame too large")),
+				Ok(Event::ReadFailed(false)) | Err(_) => break Ok(())
+			};
+
+			match handled {
+				Ok(()) => {},
+				Err(H2Error::Connection(code, message)) => {
+					warn!("HTTP/2 connection error: {}", message);
+					break connection.go_away(code);
+				},
+				Err(H2Error::Io(e)) => break Err(e)
+			}
+		};
+
+		//unblocks the reader thread so the scope can end
+		let _ = stream.shutdown(Shutdown::Both);
+		result
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use routing::Routable;
+
+	#[test]
+	fn upgrades_need_the_settings_header_named_in_connection() {
+		let upgrade = |connection: &str| {
+			let mut headers = HeaderCollection::init_empty();
+			headers.get_or_add("Upgrade").add(String::from("h2c"));
+			headers.get_or_add("Connection").add(connection.to_string());
+			headers.get_or_add("HTTP2-Settings").add(String::from("AAMAAABkAAQAAP__"));
+			HttpRequest { start_line: Routable::get(&"/"), headers, body: Vec::new() }
+		};
+
+		assert!(is_upgrade_request(&upgrade("Upgrade, HTTP2-Settings")));
+		assert!(is_upgrade_request(&upgrade("upgrade, http2-settings")));
+		assert!(!is_upgrade_request(&upgrade("Upgrade")));
+	}
+}
@@ -0,0 +1,341 @@
+use std::fmt::Write as FmtWrite;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::{Log, Metadata, Record, LevelFilter};
+
+use encoding::json_escape;
+use http::model::*;
+
+/// Access log lines are logged under this target, and written out as they are.
+pub const ACCESS_TARGET: &str = "access";
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessFormat {
+	/// NCSA common log format, followed by the time taken in milliseconds.
+	Common,
+	/// Common plus the referer and user agent, as Apache and nginx log by default.
+	Combined,
+	/// One JSON object per line.
+	Json
+}
+
+impl FromStr for AccessFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<AccessFormat, String> {
+		match s {
+			"common" => Ok(AccessFormat::Common),
+			"combined" => Ok(AccessFormat::Combined),
+			"json" => Ok(AccessFormat::Json),
+			_ => Err(format!("Unknown access log format '{}', expected common, combined or json", s))
+		}
+	}
+}
+
+/// Where logs go and how much of it.
+pub struct LogConfig {
+	pub level: LevelFilter,
+	/// Appended to; stderr when not set.
+	pub file: Option<String>,
+	pub access_format: AccessFormat
+}
+
+impl Default for LogConfig {
+	fn default() -> LogConfig {
+		LogConfig {
+			level: LevelFilter::Info,
+			file: None,
+			access_format: AccessFormat::Combined
+		}
+	}
+}
+
+impl LogConfig {
+	/// Reads `--log-level <level>`, `--log-file <path>` and `--access-log <format>`.
+	pub fn from_args(args: &[String]) -> Result<LogConfig, String> {
+		let mut config = LogConfig::default();
+
+		let mut args = args.iter();
+		while let Some(arg) = args.next() {
+			let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+			match arg.as_str() {
+				"--log-level" => {
+					let level = value()?;
+					config.level = level.parse().map_err(|_| format!("Unknown log level '{}'", level))?;
+				},
+				"--log-file" => config.file = Some(value()?.clone()),
+				"--access-log" => config.access_format = value()?.parse()?,
+				_ => {}
+			}
+		}
+
+		Ok(config)
+	}
+}
+
+struct Logger {
+	level: LevelFilter,
+	out: Mutex<Box<dyn Write + Send>>
+}
+
+impl Log for Logger {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		metadata.level() <= self.level
+	}
+
+	fn log(&self, record: &Record) {
+		if !self.enabled(record.metadata()) {
+			return;
+		}
+
+		let line = if record.target() == ACCESS_TARGET {
+			format!("{}\n", record.args())
+		} else {
+			format!("{} {:<5} {}: {}\n", iso_timestamp(SystemTime::now()), record.level(), record.target(), record.args())
+		};
+
+		//a poisoned lock only means another thread panicked mid-write; the log is still worth having
+		let mut out = match self.out.lock() {
+			Ok(out) => out,
+			Err(poisoned) => poisoned.into_inner()
+		};
+		let _ = out.write_all(line.as_bytes());
+	}
+
+	fn flush(&self) {
+		if let Ok(mut out) = self.out.lock() {
+			let _ = out.flush();
+		}
+	}
+}
+
+/// Installs the logger for the `log` macros. Only the first call has any effect.
+pub fn init(config: &LogConfig) -> io::Result<()> {
+	let out: Box<dyn Write + Send> = match config.file {
+		Some(ref path) => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+		None => Box::new(io::stderr())
+	};
+
+	//the logger lives for the rest of the program
+	let logger: &'static Logger = Box::leak(Box::new(Logger { level: config.level, out: Mutex::new(out) }));
+	log::set_logger(logger)
+		.map(|_| log::set_max_level(config.level))
+		.or(Ok(()))
+}
+
+/// One line of the access log.
+pub struct AccessRecord<'a> {
+	pub client: Option<IpAddr>,
+	/// None when the request couldn't be parsed.
+	pub request: Option<&'a HttpRequest>,
+	pub user: Option<&'a str>,
+	pub status: u32,
+	/// Length of the response body.
+	pub bytes: usize,
+	pub duration: Duration,
+	pub time: SystemTime
+}
+
+impl<'a> AccessRecord<'a> {
+	pub fn new(client: Option<IpAddr>, request: Option<&'a HttpRequest>, response: &HttpResponse, started: Instant) -> AccessRecord<'a> {
+		AccessRecord::from_parts(client, request, response.status_line.status_code().get_code(), response.body.len(), started)
+	}
+
+	/// For responses that were sent without ever being held whole.
+	pub fn from_parts(client: Option<IpAddr>, request: Option<&'a HttpRequest>, status: u32, bytes: usize, started: Instant) -> AccessRecord<'a> {
+		AccessRecord {
+			client,
+			request,
+			user: None,
+			status,
+			bytes,
+			duration: started.elapsed(),
+			time: SystemTime::now()
+		}
+	}
+
+	pub fn with_user(mut self, user: Option<&'a str>) -> AccessRecord<'a> {
+		self.user = user;
+		self
+	}
+
+	pub fn format(&self, format: AccessFormat) -> String {
+		let client = self.client.map(|c| c.to_string());
+		let millis = self.duration.as_secs() as f64 * 1000.0 + f64::from(self.duration.subsec_nanos()) / 1_000_000.0;
+
+		match format {
+			AccessFormat::Common | AccessFormat::Combined => {
+				let request_line = match self.request {
+					Some(r) => format!("{} {} HTTP/{}.{}", r.start_line.method, r.start_line.uri, r.start_line.major_version, r.start_line.minor_version),
+					None => String::from("-")
+				};
+
+				let mut line = format!("{} - {} [{}] \"{}\" {} {}",
+					client.as_deref().unwrap_or("-"),
+					self.user.unwrap_or("-"),
+					clf_timestamp(self.time),
+					clf_escape(&request_line),
+					self.status,
+					self.bytes);
+
+				if format == AccessFormat::Combined {
+					let _ = write!(line, " \"{}\" \"{}\"", clf_escape(self.header("referer").unwrap_or("-")), clf_escape(self.header("user-agent").unwrap_or("-")));
+				}
+				let _ = write!(line, " {:.3}", millis);
+				line
+			},
+			AccessFormat::Json => {
+				let mut line = format!("{{\"time\": \"{}\"", iso_timestamp(self.time));
+				push_json_field(&mut line, "client", client.as_deref());
+				push_json_field(&mut line, "user", self.user);
+				let method = self.request.map(|r| r.start_line.method.to_string());
+				push_json_field(&mut line, "method", method.as_deref());
+				let target = self.request.map(|r| r.start_line.uri.to_string());
+				push_json_field(&mut line, "path", target.as_deref());
+				let version = self.request.map(|r| format!("HTTP/{}.{}", r.start_line.major_version, r.start_line.minor_version));
+				push_json_field(&mut line, "protocol", version.as_deref());
+				let _ = write!(line, ", \"status\": {}, \"bytes\": {}, \"duration_ms\": {:.3}", self.status, self.bytes, millis);
+				push_json_field(&mut line, "referer", self.header("referer"));
+				push_json_field(&mut line, "user_agent", self.header("user-agent"));
+				line.push('}');
+				line
+			}
+		}
+	}
+
+	fn header(&self, name: &str) -> Option<&str> {
+		self.request.and_then(|r| r.headers.first(name))
+	}
+}
+
+/// Logs the record to the access log.
+pub fn access(record: &AccessRecord, format: AccessFormat) {
+	info!(target: ACCESS_TARGET, "{}", record.format(format));
+}
+
+fn push_json_field(line: &mut String, name: &str, value: Option<&str>) {
+	match value {
+		Some(v) => { let _ = write!(line, ", \"{}\": \"{}\"", name, json_escape(v)); },
+		None => { let _ = write!(line, ", \"{}\": null", name); }
+	}
+}
+
+// Quotes and control characters would let a client forge log lines
+fn clf_escape(raw: &str) -> String {
+	let mut escaped = String::with_capacity(raw.len());
+	for c in raw.chars() {
+		match c {
+			'"' => escaped.push_str("\\\""),
+			'\\' => escaped.push_str("\\\\"),
+			c if c.is_control() => { let _ = write!(escaped, "\\x{:02x}", c as u32); },
+			c => escaped.push(c)
+		}
+	}
+	escaped
+}
+
+// e.g. 10/Oct/2000:13:55:36 +0000
+fn clf_timestamp(time: SystemTime) -> String {
+	let (year, month, day, hour, minute, second) = utc_fields(time);
+	format!("{:02}/{}/{}:{:02}:{:02}:{:02} +0000", day, MONTHS[(month - 1) as usize], year, hour, minute, second)
+}
+
+// e.g. 2000-10-10T13:55:36Z
+fn iso_timestamp(time: SystemTime) -> String {
+	let (year, month, day, hour, minute, second) = utc_fields(time);
+	format!("{}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, hour, minute, second)
+}
+
+fn utc_fields(time: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+	let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+	let (days, rem) = (secs / 86400, secs % 86400);
+
+	//civil-from-days, counting eras of 400 years from 0000-03-01 so leap days fall at the end of each year
+	let z = days + 719468;
+	let era = z / 146097;
+	let day_of_era = z - era * 146097;
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let mp = (5 * day_of_year + 2) / 153;
+	let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+	let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+	(year, month, day, (rem / 3600) as u32, (rem % 3600 / 60) as u32, (rem % 60) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use uri::Uri;
+
+	fn request() -> HttpRequest {
+		let mut headers = HeaderCollection::init_empty();
+		headers.get_or_add("Referer").add(String::from("http://localhost/"));
+		headers.get_or_add("User-Agent").add(String::from("curl/8.0 \"quoted\""));
+		HttpRequest {
+			start_line: StartLine { method: AllowedMethod::GET, uri: Uri::new("/properties?page=2"), major_version: 1, minor_version: 1 },
+			headers,
+			body: Vec::new()
+		}
+	}
+
+	fn record(request: Option<&HttpRequest>) -> AccessRecord<'_> {
+		AccessRecord {
+			client: Some("127.0.0.1".parse().unwrap()),
+			request,
+			user: Some("luke"),
+			status: 200,
+			bytes: 2326,
+			duration: Duration::from_micros(1500),
+			time: UNIX_EPOCH + Duration::from_secs(971185536)
+		}
+	}
+
+	#[test]
+	fn common_and_combined_lines() {
+		let request = request();
+		assert_eq!(record(Some(&request)).format(AccessFormat::Common),
+			"127.0.0.1 - luke [10/Oct/2000:13:45:36 +0000] \"GET /properties?page=2 HTTP/1.1\" 200 2326 1.500");
+		assert_eq!(record(Some(&request)).format(AccessFormat::Combined),
+			"127.0.0.1 - luke [10/Oct/2000:13:45:36 +0000] \"GET /properties?page=2 HTTP/1.1\" 200 2326 \"http://localhost/\" \"curl/8.0 \\\"quoted\\\"\" 1.500");
+		assert_eq!(record(None).format(AccessFormat::Common),
+			"127.0.0.1 - luke [10/Oct/2000:13:45:36 +0000] \"-\" 200 2326 1.500");
+	}
+
+	#[test]
+	fn json_lines() {
+		let request = request();
+		assert_eq!(record(Some(&request)).format(AccessFormat::Json),
+			"{\"time\": \"2000-10-10T13:45:36Z\", \"client\": \"127.0.0.1\", \"user\": \"luke\", \"method\": \"GET\", \"path\": \"/properties?page=2\", \"protocol\": \"HTTP/1.1\", \
+			\"status\": 200, \"bytes\": 2326, \"duration_ms\": 1.500, \"referer\": \"http://localhost/\", \"user_agent\": \"curl/8.0 \\\"quoted\\\"\"}");
+		assert!(record(None).format(AccessFormat::Json).contains("\"method\": null"));
+	}
+
+	#[test]
+	fn timestamps_handle_leap_years() {
+		assert_eq!(iso_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+		assert_eq!(iso_timestamp(UNIX_EPOCH + Duration::from_secs(951782400)), "2000-02-29T00:00:00Z");
+		assert_eq!(clf_timestamp(UNIX_EPOCH + Duration::from_secs(1709251199)), "29/Feb/2024:23:59:59 +0000");
+	}
+
+	#[test]
+	fn config_from_args() {
+		let args: Vec<String> = ["web_server", "--log-level", "debug", "--access-log", "json", "--log-file", "server.log"].iter().map(|s| s.to_string()).collect();
+		let config = LogConfig::from_args(&args).unwrap();
+		assert_eq!(config.level, LevelFilter::Debug);
+		assert_eq!(config.access_format, AccessFormat::Json);
+		assert_eq!(config.file, Some(String::from("server.log")));
+
+		assert!(LogConfig::from_args(&[String::from("--access-log"), String::from("xml")]).is_err());
+		assert!(LogConfig::from_args(&[String::from("--log-level")]).is_err());
+	}
+}
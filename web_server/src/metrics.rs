@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+use thread_pool::PoolStats;
+
+//upper bounds of the latency histogram's buckets, in seconds
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Counts requests as they are answered, for `/metrics` to show in the Prometheus text format.
+pub struct Metrics {
+	series: Mutex<Series>
+}
+
+#[derive(Default)]
+struct Series {
+	//by route, method and status
+	requests: BTreeMap<(String, String, u32), u64>,
+	latencies: BTreeMap<String, Histogram>,
+	connections: u64
+}
+
+#[derive(Default)]
+struct Histogram {
+	//how many observations fell in each bucket, not counting the ones below it
+	buckets: [u64; 11],
+	count: u64,
+	sum: f64
+}
+
+impl Metrics {
+	pub fn new() -> Metrics {
+		Metrics { series: Mutex::new(Series::default()) }
+	}
+
+	/// Counts one answered request. `route` is what the request was routed by, e.g. `/scripts/*`, rather than its
+	/// own path, so there is one series per route however many paths it serves.
+	pub fn observe(&self, route: &str, method: &str, status: u32, duration: Duration) {
+		let seconds = duration.as_secs_f64();
+		let mut series = self.series.lock().unwrap();
+
+		*series.requests.entry((route.to_string(), method.to_string(), status)).or_insert(0) += 1;
+
+		let histogram = series.latencies.entry(route.to_string()).or_default();
+		if let Some(ix) = BUCKETS.iter().position(|&le| seconds <= le) {
+			histogram.buckets[ix] += 1;
+		}
+		histogram.count += 1;
+		histogram.sum += seconds;
+	}
+
+	/// Counts a connection taken on. Together with the requests, shows how many each connection carries.
+	pub fn accepted(&self) {
+		self.series.lock().unwrap().connections += 1;
+	}
+
+	/// Everything counted so far, and how busy the pool is now, in the Prometheus text format.
+	pub fn render(&self, pool: &PoolStats) -> String {
+		let series = self.series.lock().unwrap();
+		let mut out = String::new();
+
+		out.push_str("# HELP http_requests_total Requests answered, by route, method and status.\n");
+		out.push_str("# TYPE http_requests_total counter\n");
+		for (&(ref route, ref method, status), count) in series.requests.iter() {
+			let _ = writeln!(out, "http_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}", escape(route), escape(method), status, count);
+		}
+
+		out.push_str("# HELP http_request_duration_seconds How long requests took to answer, by route.\n");
+		out.push_str("# TYPE http_request_duration_seconds histogram\n");
+		for (route, histogram) in series.latencies.iter() {
+			let route = escape(route);
+			let mut cumulative = 0;
+			for (le, count) in BUCKETS.iter().zip(histogram.buckets.iter()) {
+				cumulative += count;
+				let _ = writeln!(out, "http_request_duration_seconds_bucket{{route=\"{}\",le=\"{}\"}} {}", route, le, cumulative);
+			}
+			let _ = writeln!(out, "http_request_duration_seconds_bucket{{route=\"{}\",le=\"+Inf\"}} {}", route, histogram.count);
+			let _ = writeln!(out, "http_request_duration_seconds_sum{{route=\"{}\"}} {}", route, histogram.sum);
+			let _ = writeln!(out, "http_request_duration_seconds_count{{route=\"{}\"}} {}", route, histogram.count);
+		}
+
+		out.push_str("# HELP http_connections_accepted_total Connections taken on, not counting ones refused for being over the limit.\n");
+		out.push_str("# TYPE http_connections_accepted_total counter\n");
+		let _ = writeln!(out, "http_connections_accepted_total {}", series.connections);
+
+		gauge(&mut out, "thread_pool_threads", "Threads in the worker pool.", pool.size());
+		gauge(&mut out, "thread_pool_busy_threads", "Worker threads running a job, including long-lived connections.", pool.busy());
+		gauge(&mut out, "thread_pool_queued_jobs", "Jobs waiting for a worker thread.", pool.queued());
+		out
+	}
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: usize) {
+	let _ = write!(out, "# HELP {} {}\n# TYPE {} gauge\n{} {}\n", name, help, name, name, value);
+}
+
+// Label values are quoted, so quotes, backslashes and newlines in them are escaped
+fn escape(value: &str) -> String {
+	value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use thread_pool::ThreadPool;
+
+	#[test]
+	fn requests_are_counted_by_route_and_status() {
+		let metrics = Metrics::new();
+		metrics.observe("/", "GET", 200, Duration::from_millis(3));
+		metrics.observe("/", "GET", 200, Duration::from_millis(30));
+		metrics.observe("/scripts/*", "GET", 404, Duration::from_secs(20));
+		metrics.observe("/say \"hi\"", "POST", 200, Duration::from_millis(1));
+		metrics.accepted();
+
+		let text = metrics.render(&ThreadPool::new(2).ok().unwrap().stats());
+		assert!(text.contains("http_requests_total{route=\"/\",method=\"GET\",status=\"200\"} 2\n"));
+		assert!(text.contains("http_requests_total{route=\"/scripts/*\",method=\"GET\",status=\"404\"} 1\n"));
+		assert!(text.contains("route=\"/say \\\"hi\\\"\""));
+
+		//buckets count everything at or below their bound
+		assert!(text.contains("http_request_duration_seconds_bucket{route=\"/\",le=\"0.005\"} 1\n"));
+		assert!(text.contains("http_request_duration_seconds_bucket{route=\"/\",le=\"0.05\"} 2\n"));
+		assert!(text.contains("http_request_duration_seconds_bucket{route=\"/scripts/*\",le=\"10\"} 0\n"));
+		assert!(text.contains("http_request_duration_seconds_bucket{route=\"/scripts/*\",le=\"+Inf\"} 1\n"));
+		assert!(text.contains("http_request_duration_seconds_count{route=\"/\"} 2\n"));
+
+		assert!(text.contains("http_connections_accepted_total 1\n"));
+		assert!(text.contains("thread_pool_threads 2\n"));
+		assert!(text.contains("thread_pool_busy_threads 0\n"));
+	}
+}
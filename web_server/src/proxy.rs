@@ -0,0 +1,524 @@
+use std::io;
+use std::io::Write;
+use std::net::{TcpStream, IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use uri::{Host, Uri};
+
+use http::model::*;
+use http::limits::Limits;
+use http::parser::{HttpRequestParser, CopyError};
+
+/// How this server identifies itself in Via.
+const VIA: &str = "1.1 welling";
+
+/// How long to wait on an upstream to connect, or between reads and writes.
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+//only mean something between one client and the next server, so they aren't passed on
+const HOP_BY_HOP: [&str; 9] = ["Connection", "Keep-Alive", "Proxy-Connection", "Proxy-Authenticate", "Proxy-Authorization", "TE", "Trailer", "Transfer-Encoding", "Upgrade"];
+
+/// A server requests can be forwarded to, e.g. `http://127.0.0.1:9000/api`.
+#[derive(Debug, PartialEq)]
+pub struct Upstream {
+	host: Host,
+	port: u16,
+	//prepended to forwarded paths
+	base_path: String
+}
+
+impl Upstream {
+	pub fn parse(target: &str) -> Result<Upstream, String> {
+		let uri = Uri::parse(target).map_err(|e| format!("Upstream '{}' is not a valid URI: {}", target, e))?;
+		if uri.scheme.as_ref().map(|s| s.eq_ignore_ascii_case("http")) != Some(true) {
+			return Err(format!("Upstream '{}' must be an http:// URI", target));
+		}
+
+		uri.validate().map_err(|e| format!("Upstream '{}' is not a valid URI: {}", target, e))?;
+
+		let host = uri.path.authority.as_ref().and_then(|a| a.host.clone()).ok_or_else(|| format!("Upstream '{}' has no host", target))?;
+		let port = uri.effective_port().map_err(|e| format!("Upstream '{}' is not a valid URI: {}", target, e))?.unwrap_or(80);
+
+		let base_path = uri.path.path_components.join("/");
+		Ok(Upstream {
+			host,
+			port,
+			base_path: if base_path.is_empty() { String::new() } else { format!("/{}", base_path.trim_end_matches('/')) }
+		})
+	}
+
+	/// What goes in Host when talking to this upstream.
+	pub fn authority(&self) -> String {
+		if self.port == 80 {
+			self.host.to_string()
+		} else {
+			format!("{}:{}", self.host, self.port)
+		}
+	}
+
+	fn connect(&self, timeout: Duration) -> io::Result<TcpStream> {
+		let addrs: Vec<SocketAddr> = self.host.resolve(self.port)?.collect();
+
+		let mut last_error = io::Error::new(io::ErrorKind::NotFound, "upstream host did not resolve");
+		for addr in addrs {
+			match TcpStream::connect_timeout(&addr, timeout) {
+				Ok(stream) => {
+					stream.set_read_timeout(Some(timeout))?;
+					stream.set_write_timeout(Some(timeout))?;
+					return Ok(stream);
+				},
+				Err(e) => last_error = e
+			}
+		}
+		Err(last_error)
+	}
+}
+
+/// Forwards requests to a set of upstream servers, taking turns between them.
+pub struct Proxy {
+	upstreams: Vec<Upstream>,
+	next: AtomicUsize,
+	strip_prefix: Option<String>,
+	limits: Limits
+}
+
+/// What came back from an upstream whose response was passed straight on to the client.
+pub struct Relayed {
+	pub status: u32,
+	pub bytes: usize
+}
+
+// Where the body of a forwarded request comes from
+pub(crate) enum RequestBody<'a> {
+	/// Already read in full.
+	Read(&'a [u8]),
+	/// Still to come, and copied over by the function as it arrives.
+	Stream(&'a mut dyn FnMut(&mut TcpStream) -> Result<usize, CopyError>)
+}
+
+impl Proxy {
+	pub fn new(targets: &[&str]) -> Result<Proxy, String> {
+		if targets.is_empty() {
+			return Err(String::from("A proxy needs at least one upstream"));
+		}
+
+		let upstreams = targets.iter().map(|t| Upstream::parse(t)).collect::<Result<Vec<_>, _>>()?;
+		Ok(Proxy {
+			upstreams,
+			next: AtomicUsize::new(0),
+			strip_prefix: None,
+			limits: Limits::default()
+		})
+	}
+
+	/// Removes `prefix` from the start of forwarded paths, e.g. so `/api/users` reaches the upstream as `/users`.
+	pub fn strip_prefix(mut self, prefix: &str) -> Proxy {
+		self.strip_prefix = Some(prefix.trim_end_matches('/').to_string());
+		self
+	}
+
+	/// Holds upstream responses to the same limits as requests from clients.
+	pub fn with_limits(mut self, limits: Limits) -> Proxy {
+		self.limits = limits;
+		self
+	}
+
+	/// Forwards a request that has been read in full, and reads in the whole response.
+	/// Responses with bodies over the body limit are answered with a 502 instead.
+	pub fn forward(&self, request: &HttpRequest, client: Option<IpAddr>) -> HttpResponse {
+		let max_body_len = self.limits.max_body_len;
+		let (mut upstream, mut parser, status_line, mut headers) = match self.exchange(request, client, RequestBody::Read(&request.body), max_body_len) {
+			Ok(exchange) => exchange,
+			Err(response) => return response
+		};
+
+		let mut body = Vec::new();
+		if has_body(&status_line) {
+			let read = if is_framed(&headers) {
+				parser.get_request_body(&mut upstream, &headers).map(|b| body = b).map_err(CopyError::Read)
+			} else {
+				parser.copy_until_closed(&mut upstream, &mut body).map(|_| ())
+			};
+			if let Err(e) = read {
+				return upstream_failure(e);
+			}
+		}
+
+		strip_hop_by_hop(&mut headers);
+		append(&mut headers, "Via", VIA);
+		let mut response = HttpResponse::from_status_line(status_line);
+		response.headers = headers;
+		response.body = body;
+		response
+	}
+
+	/// Forwards a request, passing the response on to `out` as it arrives. Only failures from before anything has been sent come back as a response.
+	pub(crate) fn relay<W: Write>(&self, request: &HttpRequest, client: Option<IpAddr>, body: RequestBody, out: &mut W) -> Result<Relayed, HttpResponse> {
+		//passed on as it comes, so there's nothing to hold in memory
+		let (mut upstream, mut parser, status_line, mut headers) = self.exchange(request, client, body, usize::MAX)?;
+		let status = status_line.status_code().get_code();
+
+		let chunked = headers.first("Transfer-Encoding").map(|e| e.eq_ignore_ascii_case("chunked")).unwrap_or(false);
+		strip_hop_by_hop(&mut headers);
+		if chunked {
+			headers.get_or_add("Transfer-Encoding").add(String::from("chunked"));
+		}
+		//the connection is closed after the response either way, which also ends an unframed body
+		headers.get_or_add("Connection").add(String::from("close"));
+		append(&mut headers, "Via", VIA);
+
+		let mut response = HttpResponse::from_status_line(status_line);
+		response.headers = headers;
+		if out.write_all(&response.head_bytes()).is_err() {
+			return Ok(Relayed { status, bytes: 0 });
+		}
+
+		let mut bytes = 0;
+		if has_body(&response.status_line) {
+			let copied = if is_framed(&response.headers) {
+				parser.copy_body(&mut upstream, &response.headers, out)
+			} else {
+				parser.copy_until_closed(&mut upstream, out)
+			};
+			match copied {
+				Ok(len) => bytes = len,
+				//too late to tell the client anything; closing the connection cuts the body short, which they'll notice
+				Err(CopyError::Read(status)) => warn!("Upstream response body failed: {}", status),
+				Err(CopyError::Write(_)) => {}
+			}
+		}
+		let _ = out.flush();
+
+		Ok(Relayed { status, bytes })
+	}
+
+	// Sends the request to the next upstream that will take a connection and reads the response's head.
+	// The parser that comes back reads bodies of up to `max_body_len`.
+	fn exchange(&self, request: &HttpRequest, client: Option<IpAddr>, body: RequestBody, max_body_len: usize) -> Result<(TcpStream, HttpRequestParser, StatusLine, HeaderCollection), HttpResponse> {
+		let (mut upstream, target) = self.connect()?;
+
+		let streamed = match body {
+			RequestBody::Read(_) => false,
+			RequestBody::Stream(_) => true
+		};
+		let head = self.request_head(request, target, client, streamed);
+		if let Err(e) = upstream.write_all(head.as_bytes()) {
+			return Err(upstream_failure(CopyError::Write(e)));
+		}
+
+		let sent = match body {
+			RequestBody::Read(body) => upstream.write_all(body).map_err(CopyError::Write),
+			RequestBody::Stream(copy) => copy(&mut upstream).map(|_| ())
+		};
+		if let Err(e) = sent {
+			return Err(match e {
+				//the client's body was at fault, so they get told why
+				CopyError::Read(status) => HttpResponse::from_status_line(status),
+				e => upstream_failure(e)
+			});
+		}
+
+		let mut parser = HttpRequestParser::new().with_limits(Limits { max_body_len, ..self.limits.clone() });
+		loop {
+			let status_line = parser.get_status_line(&mut upstream).map_err(|s| upstream_failure(CopyError::Read(s)))?;
+			let headers = parser.get_request_headers(&mut upstream).map_err(|s| upstream_failure(CopyError::Read(s)))?;
+
+			//interim responses aren't passed on; the request didn't ask for any
+			//passed on in this server's own version, whatever the upstream speaks
+			if status_line.status_code().get_code() >= 200 {
+				return Ok((upstream, parser, status_line.with_version(1, 1), headers));
+			}
+		}
+	}
+
+	// Takes turns between upstreams, moving on to the next when one won't connect
+	fn connect(&self) -> Result<(TcpStream, &Upstream), HttpResponse> {
+		let start = self.next.fetch_add(1, Ordering::Relaxed);
+
+		for i in 0..self.upstreams.len() {
+			let upstream = &self.upstreams[(start + i) % self.upstreams.len()];
+			match upstream.connect(UPSTREAM_TIMEOUT) {
+				Ok(stream) => return Ok((stream, upstream)),
+				Err(e) => warn!("Upstream {} is unavailable: {}", upstream.authority(), e)
+			}
+		}
+
+		Err(HttpResponse::new(StatusCode::bad_gateway()))
+	}
+
+	fn request_head(&self, request: &HttpRequest, upstream: &Upstream, client: Option<IpAddr>, streamed: bool) -> String {
+		let start_line = &request.start_line;
+		let mut path = format!("/{}", start_line.uri.path.path_components.join("/"));
+		if let Some(ref prefix) = self.strip_prefix {
+			if path == *prefix || path.starts_with(&format!("{}/", prefix)) {
+				path = path[prefix.len()..].to_string();
+			}
+		}
+		if path.is_empty() {
+			path.push('/');
+		}
+		let mut target = format!("{}{}", upstream.base_path, path);
+		if let Some(ref query) = start_line.uri.query {
+			target.push('?');
+			target.push_str(query);
+		}
+
+		let mut headers = HeaderCollection::init_empty();
+		for header in request.headers.iter() {
+			for value in header.values() {
+				headers.get_or_add(header.name()).add(value.clone());
+			}
+		}
+		let chunked = headers.first("Transfer-Encoding").map(|e| e.eq_ignore_ascii_case("chunked")).unwrap_or(false);
+		strip_hop_by_hop(&mut headers);
+		let original_host = headers.remove("Host");
+		headers.remove("Content-Length");
+		//this end doesn't wait for 100 Continue, so there's no point asking for it
+		headers.remove("Expect");
+
+		headers.get_or_add("Host").add(upstream.authority());
+		append(&mut headers, "Via", VIA);
+		if let Some(client) = client {
+			append(&mut headers, "X-Forwarded-For", &client.to_string());
+		}
+		if let Some(host) = original_host.as_ref().and_then(|h| h.values().first()) {
+			headers.remove("X-Forwarded-Host");
+			headers.get_or_add("X-Forwarded-Host").add(host.clone());
+		}
+		headers.remove("X-Forwarded-Proto");
+		headers.get_or_add("X-Forwarded-Proto").add(String::from("http"));
+		headers.get_or_add("Connection").add(String::from("close"));
+
+		if streamed && chunked {
+			headers.get_or_add("Transfer-Encoding").add(String::from("chunked"));
+		} else if streamed {
+			if let Some(length) = request.headers.first("Content-Length") {
+				headers.get_or_add("Content-Length").add(length.to_string());
+			}
+		} else if !request.body.is_empty() || request.headers.get("Content-Length").is_some() || chunked {
+			headers.get_or_add("Content-Length").add(request.body.len().to_string());
+		}
+
+		let mut head = format!("{} {} HTTP/1.1\r\n", start_line.method, target);
+		for header in headers.iter() {
+			for value in header.values() {
+				head.push_str(&format!("{}: {}\r\n", header.name(), value));
+			}
+		}
+		head.push_str("\r\n");
+		head
+	}
+}
+
+// Removes the hop-by-hop fields, along with any the Connection field names
+fn strip_hop_by_hop(headers: &mut HeaderCollection) {
+	let named: Vec<String> = headers.get("Connection")
+		.map(|c| c.values().iter().flat_map(|v| v.split(',')).map(|n| n.trim().to_string()).filter(|n| !n.is_empty()).collect())
+		.unwrap_or_default();
+
+	for name in HOP_BY_HOP.iter().copied().chain(named.iter().map(|n| n.as_str())) {
+		headers.remove(name);
+	}
+}
+
+// Adds to the end of a comma separated list field, as intermediaries do with Via and X-Forwarded-For
+fn append(headers: &mut HeaderCollection, name: &str, value: &str) {
+	let list = match headers.remove(name) {
+		Some(existing) => {
+			let mut values = existing.values().clone();
+			values.push(value.to_string());
+			values.join(", ")
+		},
+		None => value.to_string()
+	};
+	headers.get_or_add(name).add(list);
+}
+
+fn has_body(status_line: &StatusLine) -> bool {
+	let code = status_line.status_code().get_code();
+	code >= 200 && code != 204 && code != 304
+}
+
+fn is_framed(headers: &HeaderCollection) -> bool {
+	headers.get("Content-Length").is_some() || headers.get("Transfer-Encoding").is_some()
+}
+
+fn upstream_failure(error: CopyError) -> HttpResponse {
+	match error {
+		CopyError::Read(ref status) if status.status_code() == StatusCode::request_timeout() => {
+			warn!("Upstream timed out");
+			HttpResponse::new(StatusCode::gateway_timeout())
+		},
+		CopyError::Read(ref status) if status.status_code() == StatusCode::content_too_large() => {
+			warn!("Upstream response body is too large to pass on");
+			HttpResponse::new(StatusCode::bad_gateway())
+		},
+		CopyError::Read(status) => {
+			warn!("Upstream sent an invalid response: {}", status);
+			HttpResponse::new(StatusCode::bad_gateway())
+		},
+		CopyError::Write(ref e) if e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock => {
+			warn!("Upstream timed out");
+			HttpResponse::new(StatusCode::gateway_timeout())
+		},
+		CopyError::Write(e) => {
+			warn!("Failed to send the request upstream: {}", e);
+			HttpResponse::new(StatusCode::bad_gateway())
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::{Read, BufRead, BufReader};
+	use std::net::{Ipv4Addr, TcpListener};
+	use std::sync::mpsc::{channel, Receiver};
+	use std::thread;
+	use routing::Routable;
+
+	// Answers `count` requests with `response`, passing on each request head it got
+	fn stand_in(response: &str, count: usize) -> (String, Receiver<String>) {
+		let response = response.to_string();
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let address = format!("http://{}", listener.local_addr().unwrap());
+		let (heads, received) = channel();
+
+		thread::spawn(move || {
+			for stream in listener.incoming().take(count) {
+				let mut stream = stream.unwrap();
+				let mut reader = BufReader::new(stream.try_clone().unwrap());
+				let mut head = String::new();
+				loop {
+					let mut line = String::new();
+					reader.read_line(&mut line).unwrap();
+					head.push_str(&line);
+					if line == "\r\n" {
+						break;
+					}
+				}
+
+				//echo chunked bodies back as they came
+				if head.contains("Transfer-Encoding: chunked") {
+					let mut body = String::new();
+					loop {
+						let mut line = String::new();
+						reader.read_line(&mut line).unwrap();
+						body.push_str(&line);
+						if line == "0\r\n" {
+							reader.read_line(&mut line).unwrap();
+							break;
+						}
+					}
+					head.push_str(&body);
+				}
+
+				heads.send(head).unwrap();
+				stream.write_all(response.as_bytes()).unwrap();
+			}
+		});
+
+		(address, received)
+	}
+
+	fn request(raw_target: &str, headers: &[(&str, &str)], body: &[u8]) -> HttpRequest {
+		let mut collection = HeaderCollection::init_empty();
+		for &(name, value) in headers {
+			collection.get_or_add(name).add(value.to_string());
+		}
+		HttpRequest { start_line: Routable::get(&raw_target), headers: collection, body: body.to_vec() }
+	}
+
+	#[test]
+	fn upstream_parsed_from_uri() {
+		assert_eq!(Upstream::parse("http://127.0.0.1:9000/api/").unwrap(), Upstream { host: Host::Ipv4(Ipv4Addr::new(127, 0, 0, 1)), port: 9000, base_path: String::from("/api") });
+		assert_eq!(Upstream::parse("http://example.com").unwrap().authority(), "example.com");
+		assert_eq!(Upstream::parse("http://[::1]:9000").unwrap().authority(), "[::1]:9000");
+		assert!(Upstream::parse("https://example.com").is_err());
+	}
+
+	#[test]
+	fn forwarded_headers_rewritten() {
+		let (address, heads) = stand_in("HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: keep-alive, X-Secret\r\nX-Secret: 1\r\nVia: 1.0 cache\r\n\r\nhi", 1);
+		let proxy = Proxy::new(&[address.as_str()]).unwrap().strip_prefix("/api");
+
+		let request = request("/api/users?page=2", &[
+			("Host", "welling.local"),
+			("X-Forwarded-For", "10.0.0.1"),
+			("Connection", "keep-alive"),
+			("Keep-Alive", "timeout=5")
+		], b"");
+		let response = proxy.forward(&request, Some("127.0.0.1".parse().unwrap()));
+
+		let head = heads.recv().unwrap();
+		assert!(head.starts_with("GET /users?page=2 HTTP/1.1\r\n"));
+		assert!(head.contains(&format!("Host: {}\r\n", &address["http://".len()..])));
+		assert!(head.contains("X-Forwarded-For: 10.0.0.1, 127.0.0.1\r\n"));
+		assert!(head.contains("X-Forwarded-Host: welling.local\r\n"));
+		assert!(head.contains("X-Forwarded-Proto: http\r\n"));
+		assert!(head.contains("Via: 1.1 welling\r\n"));
+		assert!(!head.contains("Keep-Alive"));
+
+		assert_eq!(response.status_line.status_code(), StatusCode::ok());
+		assert_eq!(response.body, b"hi".to_vec());
+		assert_eq!(response.headers.first("Via"), Some("1.0 cache, 1.1 welling"));
+		assert!(response.headers.get("X-Secret").is_none());
+		assert!(response.headers.get("Connection").is_none());
+	}
+
+	#[test]
+	fn upstreams_take_turns_and_dead_ones_are_skipped() {
+		let (first, first_heads) = stand_in("HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\n1", 2);
+		let (second, second_heads) = stand_in("HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\n2", 2);
+		//nothing listens here once the listener is dropped
+		let dead = format!("http://{}", TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap());
+
+		let proxy = Proxy::new(&[first.as_str(), dead.as_str(), second.as_str()]).unwrap();
+		let bodies: Vec<Vec<u8>> = (0..4).map(|_| proxy.forward(&request("/", &[], b""), None).body).collect();
+
+		//the dead upstream's turns go to the one after it
+		assert_eq!(bodies, vec!(b"1".to_vec(), b"2".to_vec(), b"2".to_vec(), b"1".to_vec()));
+		assert_eq!(first_heads.iter().take(2).count(), 2);
+		assert_eq!(second_heads.iter().take(2).count(), 2);
+	}
+
+	#[test]
+	fn unreachable_upstream_is_bad_gateway() {
+		let dead = format!("http://{}", TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap());
+		let proxy = Proxy::new(&[dead.as_str()]).unwrap();
+		assert_eq!(proxy.forward(&request("/", &[], b""), None).status_line.status_code(), StatusCode::bad_gateway());
+	}
+
+	#[test]
+	fn responses_over_the_body_limit_are_bad_gateway() {
+		let (address, _heads) = stand_in("HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\n123456789", 1);
+		let proxy = Proxy::new(&[address.as_str()]).unwrap().with_limits(Limits { max_body_len: 8, ..Limits::default() });
+		assert_eq!(proxy.forward(&request("/", &[], b""), None).status_line.status_code(), StatusCode::bad_gateway());
+	}
+
+	#[test]
+	fn bodies_streamed_both_ways() {
+		let (address, heads) = stand_in("HTTP/1.1 201 Created\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n", 1);
+		let proxy = Proxy::new(&[address.as_str()]).unwrap();
+
+		let request = request("/upload", &[("Transfer-Encoding", "chunked")], b"");
+		let mut client_body = io::Cursor::new(b"4\r\nwiki\r\n0\r\n\r\n".to_vec());
+		let mut parser = HttpRequestParser::new();
+		let mut copy = |upstream: &mut TcpStream| parser.copy_body(&mut client_body, &request.headers, upstream);
+
+		let mut out = Vec::new();
+		let relayed = proxy.relay(&request, None, RequestBody::Stream(&mut copy), &mut out).ok().unwrap();
+		assert_eq!(relayed.status, 201);
+		assert_eq!(relayed.bytes, 5);
+
+		let head = heads.recv().unwrap();
+		assert!(head.contains("Transfer-Encoding: chunked\r\n"));
+		assert!(head.ends_with("\r\n\r\n4\r\nwiki\r\n0\r\n"));
+
+		let mut sent = String::new();
+		io::Cursor::new(out).read_to_string(&mut sent).unwrap();
+		assert!(sent.starts_with("HTTP/1.1 201 Created\r\n"));
+		assert!(sent.contains("Transfer-Encoding: chunked\r\n"));
+		assert!(sent.ends_with("\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n"));
+	}
+}
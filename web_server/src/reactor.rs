@@ -0,0 +1,466 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::{Read, Write};
+use std::net;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::{Duration, Instant};
+use mio::{Events, Interest, Poll, Token, Waker};
+use mio::net::{TcpListener, TcpStream};
+use thread_pool::ThreadPool;
+
+use http::http_context::HttpContext;
+use http::limits::{Limits, ConnectionLimiter, ConnectionPermit, too_many_connections};
+use http::model::*;
+use http::parser::{IncrementalParser, Progress};
+use http2;
+use websocket;
+use logging::AccessRecord;
+use {App, respond, serve, handle_request, keep_alive, keep_serving, record_access, wait_for_connections};
+
+const LISTENER: Token = Token(0);
+const WAKER: Token = Token(1);
+const FIRST_CONNECTION: usize = 2;
+//how often connections are checked for having taken too long
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(PartialEq)]
+enum State {
+	Reading,
+	//a worker is building the response; anything else the client sends waits in the socket
+	Dispatched,
+	Writing
+}
+
+struct Connection {
+	stream: TcpStream,
+	parser: IncrementalParser,
+	state: State,
+	outgoing: Vec<u8>,
+	written: usize,
+	keep_alive: bool,
+	//the HTTP/2 preface can only come before the first request
+	fresh: bool,
+	//when the first byte of the request being read arrived
+	request_started: Option<Instant>,
+	//when the connection last finished a response, or opened
+	last_active: Instant,
+	client: Option<IpAddr>,
+	permit: ConnectionPermit
+}
+
+/// Serves connections from one thread using readiness events (epoll on Linux). Sockets are only read when
+/// they have data, so slow clients don't hold anything up, and only complete requests go to the pool.
+pub struct Reactor {
+	poll: Poll,
+	listener: TcpListener,
+	connections: HashMap<Token, Connection>,
+	next_token: usize,
+	waker: Arc<Waker>,
+	responses: Receiver<(Token, HttpResponse)>,
+	response_sender: Sender<(Token, HttpResponse)>,
+	limits: Limits,
+	limiter: ConnectionLimiter
+}
+
+impl Reactor {
+	pub fn new(listener: net::TcpListener, limits: Limits) -> io::Result<Reactor> {
+		listener.set_nonblocking(true)?;
+		let mut listener = TcpListener::from_std(listener);
+
+		let poll = Poll::new()?;
+		poll.registry().register(&mut listener, LISTENER, Interest::READABLE)?;
+		let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+		let (response_sender, responses) = channel();
+
+		Ok(Reactor {
+			poll,
+			listener,
+			connections: HashMap::new(),
+			next_token: FIRST_CONNECTION,
+			waker,
+			responses,
+			response_sender,
+			limiter: ConnectionLimiter::new(limits.max_connections_per_ip),
+			limits
+		})
+	}
+
+	/// Wakes the reactor from another thread, e.g. to notice it has been asked to stop.
+	pub fn waker(&self) -> Arc<Waker> {
+		Arc::clone(&self.waker)
+	}
+
+	/// Serves connections until the app is asked to stop, then gives the ones still open a while to finish.
+	pub fn run(&mut self, pool: &ThreadPool, app: &Arc<App>) -> io::Result<()> {
+		let mut events = Events::with_capacity(1024);
+		let mut last_sweep = Instant::now();
+
+		while !app.is_stopping() {
+			if let Err(e) = self.poll.poll(&mut events, Some(SWEEP_INTERVAL)) {
+				if e.kind() == io::ErrorKind::Interrupted {
+					continue;
+				}
+				return Err(e);
+			}
+
+			for event in events.iter() {
+				match event.token() {
+					LISTENER => self.accept(app)?,
+					WAKER => self.send_responses(pool, app),
+					token => self.on_ready(token, pool, app)
+				}
+			}
+
+			if last_sweep.elapsed() >= SWEEP_INTERVAL {
+				self.sweep(pool, app);
+				last_sweep = Instant::now();
+			}
+		}
+
+		self.poll.registry().deregister(&mut self.listener)?;
+		self.drain(pool, app, &mut events)?;
+		Ok(())
+	}
+
+	// Finishes the requests already started, closing each connection once it has its response, until none are left
+	// or the grace period is up. Connections handed off to a worker finish there.
+	fn drain(&mut self, pool: &ThreadPool, app: &Arc<App>, events: &mut Events) -> io::Result<()> {
+		let deadline = Instant::now() + ::SHUTDOWN_GRACE;
+		while !self.connections.is_empty() && Instant::now() < deadline {
+			let waiting: Vec<Token> = self.connections.iter()
+				.filter(|&(_, c)| c.state == State::Reading && c.parser.buffered().is_empty())
+				.map(|(token, _)| *token)
+				.collect();
+			for token in waiting {
+				self.close(token);
+			}
+			for connection in self.connections.values_mut() {
+				connection.keep_alive = false;
+			}
+
+			if let Err(e) = self.poll.poll(events, Some(SWEEP_INTERVAL)) {
+				if e.kind() == io::ErrorKind::Interrupted {
+					continue;
+				}
+				return Err(e);
+			}
+			for event in events.iter() {
+				match event.token() {
+					WAKER => self.send_responses(pool, app),
+					token => self.on_ready(token, pool, app)
+				}
+			}
+		}
+
+		wait_for_connections(&self.limiter, deadline);
+		Ok(())
+	}
+
+	// Times out requests that are taking too long to arrive and connections that have sat idle too long
+	fn sweep(&mut self, pool: &ThreadPool, app: &Arc<App>) {
+		let now = Instant::now();
+		let mut timed_out = Vec::new();
+		let mut idle = Vec::new();
+
+		for (token, connection) in self.connections.iter() {
+			if connection.state != State::Reading {
+				continue;
+			}
+			match connection.request_started {
+				Some(started) if now - started > self.limits.request_timeout => timed_out.push(*token),
+				None if now - connection.last_active > self.limits.idle_timeout => idle.push(*token),
+				_ => {}
+			}
+		}
+
+		for token in timed_out {
+			let response = HttpResponse::new(StatusCode::request_timeout());
+			if let Some(connection) = self.connections.get_mut(&token) {
+				connection.keep_alive = false;
+				let started = connection.request_started.unwrap_or(now);
+				record_access(app, &AccessRecord::new(connection.client, None, &response, started));
+			}
+			self.start_write(token, response, pool, app);
+		}
+		for token in idle {
+			self.close(token);
+		}
+	}
+
+	fn accept(&mut self, app: &Arc<App>) -> io::Result<()> {
+		loop {
+			//the address comes from accept itself, which unlike peer_addr can't fail once the connection is taken
+			let (mut stream, address) = match self.listener.accept() {
+				Ok(s) => s,
+				Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+				Err(e) => {
+					//e.g. out of file descriptors; the client will retry
+					error!("Failed to accept a connection: {}", e);
+					return Ok(());
+				}
+			};
+
+			let client = address.ip();
+			let permit = match self.limiter.acquire(client) {
+				Some(p) => p,
+				None => {
+					warn!("Refusing a connection from {}: too many open connections", client);
+					//best effort: the socket is new, so the response almost certainly fits in its send buffer
+					let _ = stream.write_all(&too_many_connections().to_bytes());
+					continue;
+				}
+			};
+
+			app.metrics.accepted();
+			let token = Token(self.next_token);
+			self.next_token += 1;
+			self.poll.registry().register(&mut stream, token, Interest::READABLE)?;
+
+			self.connections.insert(token, Connection {
+				stream,
+				parser: IncrementalParser::new(self.limits.clone()),
+				state: State::Reading,
+				outgoing: Vec::new(),
+				written: 0,
+				keep_alive: false,
+				fresh: true,
+				request_started: None,
+				last_active: Instant::now(),
+				client: Some(client),
+				permit
+			});
+		}
+	}
+
+	fn on_ready(&mut self, token: Token, pool: &ThreadPool, app: &Arc<App>) {
+		let state = match self.connections.get(&token) {
+			Some(c) => &c.state,
+			None => return
+		};
+
+		match *state {
+			State::Reading => self.read(token, pool, app),
+			State::Writing => self.write(token, pool, app),
+			State::Dispatched => {}
+		}
+	}
+
+	fn read(&mut self, token: Token, pool: &ThreadPool, app: &Arc<App>) {
+		loop {
+			let read = match self.connections.get_mut(&token) {
+				Some(connection) => read_available(connection),
+				None => return
+			};
+			self.process(token, read == ReadOutcome::Closed, pool, app);
+
+			//readiness is edge triggered, so if reading stopped short of the socket running dry, only this picks it up again
+			let more = read == ReadOutcome::Enough && self.connections.get(&token)
+				.map(|c| c.state == State::Reading && !c.parser.has_enough())
+				.unwrap_or(false);
+			if !more {
+				return;
+			}
+		}
+	}
+
+	// Acts on whatever the client has sent so far
+	fn process(&mut self, token: Token, closed: bool, pool: &ThreadPool, app: &Arc<App>) {
+		let progress = {
+			let connection = match self.connections.get_mut(&token) {
+				Some(c) => c,
+				None => return
+			};
+
+			let buffered = connection.parser.buffered();
+			let preface_len = buffered.len().min(http2::PREFACE.len());
+			if preface_len > 0 && connection.fresh && !connection.parser.has_start_line() && buffered[..preface_len] == http2::PREFACE[..preface_len] {
+				if preface_len == http2::PREFACE.len() {
+					self.hand_off(token, None, pool, app);
+				} else if closed {
+					self.close(token);
+				}
+				return;
+			}
+
+			connection.parser.next_request()
+		};
+
+		match progress {
+			Progress::Complete(request) => {
+				let request = *request;
+				//these keep the connection to themselves, so need a worker of their own
+				if websocket::is_upgrade_request(&request) || http2::is_upgrade_request(&request) || app.router.streams_events(&request.start_line) {
+					self.hand_off(token, Some(request), pool, app);
+					return;
+				}
+				self.dispatch(token, request, pool, app);
+			},
+			Progress::Incomplete => if closed { self.close(token) },
+			Progress::Invalid(status_line) => {
+				let response = HttpResponse::from_status_line(status_line);
+				if let Some(connection) = self.connections.get_mut(&token) {
+					connection.keep_alive = false;
+					let started = connection.request_started.unwrap_or_else(Instant::now);
+					record_access(app, &AccessRecord::new(connection.client, None, &response, started));
+				}
+				self.start_write(token, response, pool, app);
+			}
+		}
+	}
+
+	fn dispatch(&mut self, token: Token, request: HttpRequest, pool: &ThreadPool, app: &Arc<App>) {
+		if let Some(connection) = self.connections.get_mut(&token) {
+			connection.keep_alive = keep_alive(&request) && !app.is_stopping();
+			connection.state = State::Dispatched;
+			connection.fresh = false;
+			connection.request_started = None;
+		}
+
+		let client = self.connections.get(&token).and_then(|c| c.client);
+		let app = Arc::clone(app);
+		let responses = self.response_sender.clone();
+		let waker = Arc::clone(&self.waker);
+		let queued = pool.execute(move || {
+			let response = respond(&app, client, request);
+			if responses.send((token, response)).is_ok() {
+				let _ = waker.wake();
+			}
+		});
+		if let Err(e) = queued {
+			error!("Dropping a request: {}", e.message);
+			self.close(token);
+		}
+	}
+
+	// Websockets and HTTP/2 keep a worker for the life of the connection, so they go back to blocking I/O
+	fn hand_off(&mut self, token: Token, request: Option<HttpRequest>, pool: &ThreadPool, app: &Arc<App>) {
+		let mut connection = match self.connections.remove(&token) {
+			Some(c) => c,
+			None => return
+		};
+		let _ = self.poll.registry().deregister(&mut connection.stream);
+
+		let stream = net::TcpStream::from(connection.stream);
+		if let Err(e) = stream.set_nonblocking(false) {
+			warn!("Failed to hand off connection: {}", e);
+			return;
+		}
+
+		let context = HttpContext::resume(stream, connection.parser.take_buffered()).with_limits(self.limits.clone());
+		let app = Arc::clone(app);
+		let permit = connection.permit;
+		let queued = pool.execute(move || {
+			let _permit = permit;
+			match request {
+				Some(request) => keep_serving(handle_request(context, request, &app), &app),
+				None => keep_serving(serve(context, &app), &app)
+			}
+		});
+		if let Err(e) = queued {
+			error!("Dropping a connection: {}", e.message);
+		}
+	}
+
+	fn send_responses(&mut self, pool: &ThreadPool, app: &Arc<App>) {
+		while let Ok((token, response)) = self.responses.try_recv() {
+			self.start_write(token, response, pool, app);
+		}
+	}
+
+	fn start_write(&mut self, token: Token, mut response: HttpResponse, pool: &ThreadPool, app: &Arc<App>) {
+		match self.connections.get_mut(&token) {
+			Some(connection) => {
+				if !connection.keep_alive {
+					response.add_header("Connection", "close");
+				}
+				connection.outgoing = response.to_bytes();
+				connection.written = 0;
+				connection.state = State::Writing;
+			},
+			None => return
+		}
+
+		self.write(token, pool, app);
+	}
+
+	fn write(&mut self, token: Token, pool: &ThreadPool, app: &Arc<App>) {
+		let keep_alive = {
+			let connection = match self.connections.get_mut(&token) {
+				Some(c) => c,
+				None => return
+			};
+
+			while connection.written < connection.outgoing.len() {
+				match connection.stream.write(&connection.outgoing[connection.written..]) {
+					Ok(n) => connection.written += n,
+					Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+					Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+						if self.poll.registry().reregister(&mut connection.stream, token, Interest::WRITABLE).is_err() {
+							break;
+						}
+						return;
+					},
+					Err(_) => break
+				}
+			}
+
+			let finished = connection.written == connection.outgoing.len();
+			connection.outgoing = Vec::new();
+			connection.written = 0;
+			connection.state = State::Reading;
+			connection.last_active = Instant::now();
+			//a pipelined request may already be waiting
+			connection.request_started = if connection.parser.buffered().is_empty() { None } else { Some(connection.last_active) };
+			finished && connection.keep_alive
+				&& self.poll.registry().reregister(&mut connection.stream, token, Interest::READABLE).is_ok()
+		};
+
+		if keep_alive {
+			//readiness is edge triggered, so anything that arrived while the request was being handled has to be read now
+			self.read(token, pool, app);
+		} else {
+			self.close(token);
+		}
+	}
+
+	fn close(&mut self, token: Token) {
+		if let Some(mut connection) = self.connections.remove(&token) {
+			let _ = self.poll.registry().deregister(&mut connection.stream);
+		}
+	}
+}
+
+#[derive(PartialEq)]
+enum ReadOutcome {
+	WouldBlock,
+	/// The client closed its end, or the connection failed.
+	Closed,
+	/// The parser has enough to get on with, so the rest is left in the socket for now.
+	Enough
+}
+
+// Reads until the socket would block, or until the parser has as much as it can use; a client can't make it buffer more than the limits allow
+fn read_available(connection: &mut Connection) -> ReadOutcome {
+	let mut buf = [0_u8; 4096];
+
+	loop {
+		if connection.parser.has_enough() {
+			return ReadOutcome::Enough;
+		}
+		match connection.stream.read(&mut buf) {
+			Ok(0) => return ReadOutcome::Closed,
+			Ok(n) => {
+				if connection.request_started.is_none() {
+					connection.request_started = Some(Instant::now());
+				}
+				connection.parser.feed(&buf[..n]);
+			},
+			Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return ReadOutcome::WouldBlock,
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+			Err(_) => return ReadOutcome::Closed
+		}
+	}
+}
@@ -0,0 +1,235 @@
+use std::time::Duration;
+
+use http::model::*;
+
+//request headers a browser sends cross-origin without asking first
+const SAFELISTED: [&str; 4] = ["accept", "accept-language", "content-language", "content-type"];
+
+/// Which other origins' pages may call the routes it applies to, and how, e.g.
+/// `CorsPolicy::new().allow_origin("https://app.example.com").allow_headers(&["Authorization"])`.
+#[derive(Clone, Debug)]
+pub struct CorsPolicy {
+	//any origin when None
+	origins: Option<Vec<String>>,
+	methods: Vec<AllowedMethod>,
+	headers: Vec<String>,
+	exposed: Vec<String>,
+	credentials: bool,
+	max_age: Option<Duration>
+}
+
+impl CorsPolicy {
+	/// A policy no origin passes yet, allowing GET and POST once one does.
+	pub fn new() -> CorsPolicy {
+		CorsPolicy {
+			origins: Some(Vec::new()),
+			methods: vec![AllowedMethod::GET, AllowedMethod::POST],
+			headers: Vec::new(),
+			exposed: Vec::new(),
+			credentials: false,
+			max_age: None
+		}
+	}
+
+	/// Allows an origin, e.g. `https://app.example.com`; `*` allows them all.
+	pub fn allow_origin(mut self, origin: &str) -> CorsPolicy {
+		let origin = origin.trim_end_matches('/');
+		if origin == "*" {
+			self.origins = None;
+		} else if let Some(ref mut origins) = self.origins {
+			origins.push(origin.to_ascii_lowercase());
+		}
+		self
+	}
+
+	pub fn allow_methods(mut self, methods: &[AllowedMethod]) -> CorsPolicy {
+		self.methods = methods.to_vec();
+		self
+	}
+
+	/// Request headers pages may send beyond the ones browsers always allow.
+	pub fn allow_headers(mut self, headers: &[&str]) -> CorsPolicy {
+		self.headers = headers.iter().map(|h| h.to_ascii_lowercase()).collect();
+		self
+	}
+
+	/// Response headers pages may read beyond the ones browsers always show them.
+	pub fn expose_headers(mut self, headers: &[&str]) -> CorsPolicy {
+		self.exposed = headers.iter().map(|h| h.to_string()).collect();
+		self
+	}
+
+	/// Lets pages send cookies and Authorization along, and read the responses that come back.
+	pub fn allow_credentials(mut self) -> CorsPolicy {
+		self.credentials = true;
+		self
+	}
+
+	/// How long browsers may remember a preflight's answer.
+	pub fn max_age(mut self, max_age: Duration) -> CorsPolicy {
+		self.max_age = Some(max_age);
+		self
+	}
+
+	/// Lets the requesting page read the response, if its origin is allowed.
+	pub fn apply(&self, request: &HttpRequest, response: &mut HttpResponse) {
+		if let Some(origin) = self.allowed_origin(request, response) {
+			response.add_header("Access-Control-Allow-Origin", &origin);
+			if self.credentials {
+				response.add_header("Access-Control-Allow-Credentials", "true");
+			}
+			if !self.exposed.is_empty() {
+				response.add_header("Access-Control-Expose-Headers", &self.exposed.join(", "));
+			}
+		}
+	}
+
+	/// Answers a preflight, telling the browser the request it asked about may be sent. Leaves the response alone if it may not.
+	pub fn preflight(&self, request: &HttpRequest, response: &mut HttpResponse) {
+		let method = match request.headers.first("Access-Control-Request-Method").map(|m| AllowedMethod::from_utf8(m.trim().to_string())) {
+			Some(Ok(method)) => method,
+			_ => return
+		};
+		if !self.methods.contains(&method) {
+			return;
+		}
+
+		let requested: Vec<String> = request.headers.get("Access-Control-Request-Headers")
+			.map(|h| h.values().iter().flat_map(|v| v.split(',')).map(|n| n.trim().to_ascii_lowercase()).filter(|n| !n.is_empty()).collect())
+			.unwrap_or_default();
+		if !requested.iter().all(|h| SAFELISTED.contains(&h.as_str()) || self.headers.contains(h)) {
+			return;
+		}
+
+		let origin = match self.allowed_origin(request, response) {
+			Some(origin) => origin,
+			None => return
+		};
+		response.add_header("Access-Control-Allow-Origin", &origin);
+		response.add_header("Access-Control-Allow-Methods", &self.methods.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(", "));
+		if !requested.is_empty() {
+			response.add_header("Access-Control-Allow-Headers", &requested.join(", "));
+		}
+		if self.credentials {
+			response.add_header("Access-Control-Allow-Credentials", "true");
+		}
+		if let Some(max_age) = self.max_age {
+			response.add_header("Access-Control-Max-Age", &max_age.as_secs().to_string());
+		}
+	}
+
+	// What goes in Access-Control-Allow-Origin for the request, if anything. Answers that depend on the
+	// origin say so in Vary, so a cache doesn't give one origin's answer to another.
+	fn allowed_origin(&self, request: &HttpRequest, response: &mut HttpResponse) -> Option<String> {
+		let origin = request.headers.first("Origin")?;
+
+		match self.origins {
+			None if !self.credentials => Some(String::from("*")),
+			ref origins => {
+				response.add_header("Vary", "Origin");
+				let allowed = origins.as_ref().map(|o| o.contains(&origin.trim_end_matches('/').to_ascii_lowercase())).unwrap_or(true);
+				if allowed { Some(origin.to_string()) } else { None }
+			}
+		}
+	}
+}
+
+impl Default for CorsPolicy {
+	fn default() -> CorsPolicy {
+		CorsPolicy::new()
+	}
+}
+
+/// Whether the request is a browser asking whether it may send another, rather than a request in its own right.
+pub fn is_preflight(request: &HttpRequest) -> bool {
+	request.start_line.method == AllowedMethod::OPTIONS
+		&& request.headers.get("Origin").is_some()
+		&& request.headers.get("Access-Control-Request-Method").is_some()
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use uri::Uri;
+
+	fn request(method: AllowedMethod, headers: &[(&str, &str)]) -> HttpRequest {
+		let mut request = HttpRequest {
+			start_line: StartLine { method, uri: Uri::new("/api/tokens"), major_version: 1, minor_version: 1 },
+			headers: HeaderCollection::init_empty(),
+			body: Vec::new()
+		};
+		for &(name, value) in headers {
+			request.headers.get_or_add(name).add(value.to_string());
+		}
+		request
+	}
+
+	fn preflight(policy: &CorsPolicy, headers: &[(&str, &str)]) -> HttpResponse {
+		let mut response = HttpResponse::new(StatusCode::no_content());
+		policy.preflight(&request(AllowedMethod::OPTIONS, headers), &mut response);
+		response
+	}
+
+	#[test]
+	fn allowed_origins_are_echoed() {
+		let policy = CorsPolicy::new().allow_origin("https://app.example.com/");
+
+		let mut response = HttpResponse::new(StatusCode::ok());
+		policy.apply(&request(AllowedMethod::GET, &[("Origin", "https://APP.example.com")]), &mut response);
+		assert_eq!(response.headers.first("Access-Control-Allow-Origin"), Some("https://APP.example.com"));
+		assert_eq!(response.headers.first("Vary"), Some("Origin"));
+
+		let mut response = HttpResponse::new(StatusCode::ok());
+		policy.apply(&request(AllowedMethod::GET, &[("Origin", "https://evil.example.com")]), &mut response);
+		assert!(response.headers.get("Access-Control-Allow-Origin").is_none());
+	}
+
+	#[test]
+	fn any_origin_is_a_wildcard_unless_credentials_are_allowed() {
+		let origin = [("Origin", "https://app.example.com")];
+
+		let mut response = HttpResponse::new(StatusCode::ok());
+		CorsPolicy::new().allow_origin("*").apply(&request(AllowedMethod::GET, &origin), &mut response);
+		assert_eq!(response.headers.first("Access-Control-Allow-Origin"), Some("*"));
+
+		let mut response = HttpResponse::new(StatusCode::ok());
+		CorsPolicy::new().allow_origin("*").allow_credentials().expose_headers(&["ETag"]).apply(&request(AllowedMethod::GET, &origin), &mut response);
+		assert_eq!(response.headers.first("Access-Control-Allow-Origin"), Some("https://app.example.com"));
+		assert_eq!(response.headers.first("Access-Control-Allow-Credentials"), Some("true"));
+		assert_eq!(response.headers.first("Access-Control-Expose-Headers"), Some("ETag"));
+	}
+
+	#[test]
+	fn preflights_check_method_and_headers() {
+		let policy = CorsPolicy::new()
+			.allow_origin("https://app.example.com")
+			.allow_methods(&[AllowedMethod::GET, AllowedMethod::DELETE])
+			.allow_headers(&["Authorization"])
+			.max_age(Duration::from_secs(600));
+
+		let allowed = preflight(&policy, &[
+			("Origin", "https://app.example.com"),
+			("Access-Control-Request-Method", "DELETE"),
+			("Access-Control-Request-Headers", "authorization, content-type")
+		]);
+		assert_eq!(allowed.headers.first("Access-Control-Allow-Methods"), Some("GET, DELETE"));
+		assert_eq!(allowed.headers.first("Access-Control-Allow-Headers"), Some("authorization, content-type"));
+		assert_eq!(allowed.headers.first("Access-Control-Max-Age"), Some("600"));
+
+		for refused in &[
+			[("Origin", "https://app.example.com"), ("Access-Control-Request-Method", "PUT"), ("Access-Control-Request-Headers", "")],
+			[("Origin", "https://app.example.com"), ("Access-Control-Request-Method", "GET"), ("Access-Control-Request-Headers", "X-Secret")],
+			[("Origin", "https://other.example.com"), ("Access-Control-Request-Method", "GET"), ("Access-Control-Request-Headers", "")]
+		] {
+			assert!(preflight(&policy, refused).headers.get("Access-Control-Allow-Origin").is_none());
+		}
+	}
+
+	#[test]
+	fn preflights_are_told_apart_from_plain_options_requests() {
+		assert!(is_preflight(&request(AllowedMethod::OPTIONS, &[("Origin", "https://a.example.com"), ("Access-Control-Request-Method", "POST")])));
+		assert!(!is_preflight(&request(AllowedMethod::OPTIONS, &[])));
+		assert!(!is_preflight(&request(AllowedMethod::POST, &[("Origin", "https://a.example.com"), ("Access-Control-Request-Method", "POST")])));
+	}
+}
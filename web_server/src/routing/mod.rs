@@ -1,6 +1,21 @@
-use uri::Uri;
+use std::collections::HashMap;
+use std::mem;
+use std::net::TcpStream;
+use uri::{TemplateValue, Uri, UriTemplate};
 
 use http::model::*;
+use auth::User;
+use session::Session;
+use websocket;
+use websocket::WebSocket;
+use proxy::Proxy;
+use sse;
+use sse::EventStream;
+use App;
+
+mod cors;
+
+pub use self::cors::CorsPolicy;
 
 //consider integrating constructor with Routable or replacing it
 macro_rules! get {
@@ -47,4 +62,338 @@ impl Routable for String {
 			minor_version: 1
 		}
 	}
-}
\ No newline at end of file
+}
+
+
+pub type Handler = Box<dyn Fn(&mut RequestContext) -> HttpResponse + Send + Sync>;
+/// Takes over the connection once the websocket handshake is done, for as long as the socket stays open.
+pub type WebSocketHandler = Box<dyn Fn(&mut RequestContext, WebSocket<TcpStream>) + Send + Sync>;
+/// Starts an event stream, or refuses to with a response of its own.
+pub type EventStreamHandler = Box<dyn Fn(&mut RequestContext) -> Result<EventStream, HttpResponse> + Send + Sync>;
+
+/// What a client needs before a route's handler will run.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Access {
+	Public,
+	Authenticated,
+	Role(String)
+}
+
+/// Everything a handler gets to work with for one request.
+pub struct RequestContext<'a> {
+	pub app: &'a App,
+	pub request: &'a HttpRequest,
+	pub session: Session,
+	pub user: Option<User>,
+	/// What the route's template matched, e.g. `id` for `/properties/{id}`; empty for other routes.
+	pub variables: HashMap<String, TemplateValue>
+}
+
+pub struct Route {
+	//any method when None
+	method: Option<AllowedMethod>,
+	path: Vec<String>,
+	//matches any path starting with `path`
+	prefix: bool,
+	//matched instead of `path` when set
+	template: Option<UriTemplate>,
+	access: Access,
+	endpoint: Endpoint,
+	//overrides the router's policy when set
+	cors: Option<CorsPolicy>
+}
+
+enum Endpoint {
+	Http(Handler),
+	WebSocket(WebSocketHandler),
+	EventStream(EventStreamHandler),
+	Proxy(Proxy)
+}
+
+/// What the connection should do with the request.
+pub enum Dispatch<'r> {
+	Response(HttpResponse),
+	/// Send the handshake response, then hand the connection to the handler.
+	Upgrade(HttpResponse, &'r WebSocketHandler),
+	/// Send the head, then keep the connection open for the stream's events.
+	Events(HttpResponse, EventStream),
+	/// Forward the request upstream and pass back whatever comes of it.
+	Proxy(&'r Proxy)
+}
+
+impl Route {
+	pub fn matches(&self, start_line: &StartLine) -> bool {
+		if self.method.as_ref().map(|m| *m != start_line.method).unwrap_or(false) {
+			return false;
+		}
+
+		self.matches_path(&start_line.uri)
+	}
+
+	pub fn matches_path(&self, uri: &Uri) -> bool {
+		if let Some(ref template) = self.template {
+			return template.matches(uri).is_some();
+		}
+
+		let path = &uri.path.path_components;
+		if self.prefix {
+			path.starts_with(&self.path)
+		} else {
+			*path == self.path
+		}
+	}
+
+	/// Lets other origins call this route as the policy allows, instead of as the router's does.
+	pub fn with_cors(&mut self, policy: CorsPolicy) -> &mut Route {
+		self.cors = Some(policy);
+		self
+	}
+}
+
+pub struct Router {
+	routes: Vec<Route>,
+	fallback: Handler,
+	cors: Option<CorsPolicy>
+}
+
+impl Router {
+	pub fn new<F>(fallback: F) -> Router
+		where F: Fn(&mut RequestContext) -> HttpResponse + Send + Sync + 'static {
+		Router {
+			routes: Vec::new(),
+			fallback: Box::new(fallback),
+			cors: None
+		}
+	}
+
+	/// Lets other origins call every route as the policy allows, unless the route has a policy of its own.
+	pub fn set_cors(&mut self, policy: CorsPolicy) {
+		self.cors = Some(policy);
+	}
+
+	/// Adds a route for the method and path of a start line, e.g. `router.add(get!("/"), Access::Public, index)`.
+	pub fn add<F>(&mut self, route: StartLine, access: Access, handler: F) -> &mut Route
+		where F: Fn(&mut RequestContext) -> HttpResponse + Send + Sync + 'static {
+		self.push(Some(route.method), route.uri, false, access, Endpoint::Http(Box::new(handler)))
+	}
+
+	/// Adds a route matching every path under the route's path.
+	pub fn add_prefix<F>(&mut self, route: StartLine, access: Access, handler: F) -> &mut Route
+		where F: Fn(&mut RequestContext) -> HttpResponse + Send + Sync + 'static {
+		self.push(Some(route.method), route.uri, true, access, Endpoint::Http(Box::new(handler)))
+	}
+
+	/// Adds a websocket endpoint, e.g. `router.add_websocket(get!("/ws"), Access::Authenticated, live)`.
+	pub fn add_websocket<F>(&mut self, route: StartLine, access: Access, handler: F) -> &mut Route
+		where F: Fn(&mut RequestContext, WebSocket<TcpStream>) + Send + Sync + 'static {
+		self.push(Some(route.method), route.uri, false, access, Endpoint::WebSocket(Box::new(handler)))
+	}
+
+	/// Adds a server-sent events endpoint, e.g. `router.add_event_stream(get!("/events"), Access::Authenticated, updates)`.
+	pub fn add_event_stream<F>(&mut self, route: StartLine, access: Access, handler: F) -> &mut Route
+		where F: Fn(&mut RequestContext) -> Result<EventStream, HttpResponse> + Send + Sync + 'static {
+		self.push(Some(route.method), route.uri, false, access, Endpoint::EventStream(Box::new(handler)))
+	}
+
+	/// Adds a route for every path the URI template matches, e.g. `router.add_template(AllowedMethod::GET, "/properties/{id}", Access::Authenticated, property)`.
+	/// The handler finds what the template matched in the context's `variables`. Links to the route can be expanded from the same template.
+	pub fn add_template<F>(&mut self, method: AllowedMethod, template: &str, access: Access, handler: F) -> &mut Route
+		where F: Fn(&mut RequestContext) -> HttpResponse + Send + Sync + 'static {
+		let route = self.push(Some(method), Uri::new("/"), false, access, Endpoint::Http(Box::new(handler)));
+		route.template = Some(UriTemplate::new(template));
+		route
+	}
+
+	/// Forwards every request under `path`, whatever its method, e.g. `router.add_proxy("/api", Access::Public, proxy)`.
+	pub fn add_proxy(&mut self, path: &str, access: Access, proxy: Proxy) -> &mut Route {
+		self.push(None, Uri::new(path), true, access, Endpoint::Proxy(proxy))
+	}
+
+	/// Whether the request will be forwarded, in which case its body is passed on as it arrives rather than read up front.
+	pub fn forwards(&self, start_line: &StartLine) -> bool {
+		match self.find(start_line) {
+			Some(&Route { endpoint: Endpoint::Proxy(_), .. }) => true,
+			_ => false
+		}
+	}
+
+	/// Whether the request starts an event stream, which keeps a connection, and a worker, to itself.
+	pub fn streams_events(&self, start_line: &StartLine) -> bool {
+		matches!(self.find(start_line), Some(&Route { endpoint: Endpoint::EventStream(_), .. }))
+	}
+
+	/// The path the request is routed by, e.g. `/scripts/*` for `/scripts/test.js` or `/properties/{id}` for `/properties/42`,
+	/// or `unmatched` when no route takes it.
+	pub fn route_label(&self, start_line: &StartLine) -> String {
+		match self.find(start_line) {
+			Some(&Route { template: Some(ref template), .. }) => template.to_string(),
+			Some(route) => {
+				let path = format!("/{}", route.path.join("/"));
+				match (route.prefix, path.ends_with('/')) {
+					(true, true) => path + "*",
+					(true, false) => path + "/*",
+					_ => path
+				}
+			},
+			None => String::from("unmatched")
+		}
+	}
+
+	fn push(&mut self, method: Option<AllowedMethod>, uri: Uri, prefix: bool, access: Access, endpoint: Endpoint) -> &mut Route {
+		let mut path = uri.path.path_components;
+		if prefix && path.last().map(|p| p.is_empty()).unwrap_or(false) {
+			path.pop();
+		}
+
+		self.routes.push(Route {
+			method,
+			path,
+			prefix,
+			template: None,
+			access,
+			endpoint,
+			cors: None
+		});
+		self.routes.last_mut().unwrap()
+	}
+
+	pub fn find(&self, start_line: &StartLine) -> Option<&Route> {
+		self.routes.iter().find(|r| r.matches(start_line))
+	}
+
+	/// Runs the matching route's handler if the client is allowed to, otherwise responds with the challenge.
+	/// Other origins can read the response if the route's CORS policy lets them.
+	pub fn dispatch(&self, context: &mut RequestContext) -> Dispatch<'_> {
+		let request = context.request;
+		if request.start_line.method == AllowedMethod::OPTIONS && self.find(&request.start_line).is_none() {
+			return Dispatch::Response(self.options(context));
+		}
+
+		match self.route(context) {
+			Dispatch::Response(mut response) => {
+				if let Some(policy) = self.cors_for(&request.start_line) {
+					policy.apply(request, &mut response);
+				}
+				Dispatch::Response(response)
+			},
+			Dispatch::Events(mut head, events) => {
+				if let Some(policy) = self.cors_for(&request.start_line) {
+					policy.apply(request, &mut head);
+				}
+				Dispatch::Events(head, events)
+			},
+			dispatch => dispatch
+		}
+	}
+
+	// Answers OPTIONS for a path with which methods it takes, and for a CORS preflight, whether the page may use them
+	fn options(&self, context: &mut RequestContext) -> HttpResponse {
+		let request = context.request;
+		let mut methods = vec![AllowedMethod::OPTIONS];
+		for route in self.routes.iter().filter(|r| r.matches_path(&request.start_line.uri)) {
+			if let Some(ref method) = route.method {
+				if !methods.contains(method) {
+					methods.push(method.clone());
+				}
+			}
+		}
+		if methods.len() == 1 {
+			return (self.fallback)(context);
+		}
+
+		let allow = methods.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(", ");
+		let mut response = HttpResponse::new(StatusCode::no_content()).with_header("Allow", &allow);
+
+		//the policy is the one for the request the browser is asking about
+		if cors::is_preflight(request) {
+			let requested = request.headers.first("Access-Control-Request-Method").and_then(|m| AllowedMethod::from_utf8(m.trim().to_string()).ok());
+			let start_line = requested.map(|method| StartLine { method, ..request.start_line.clone() });
+			if let Some(policy) = start_line.and_then(|s| self.cors_for(&s)) {
+				policy.preflight(request, &mut response);
+			}
+		}
+		response
+	}
+
+	fn cors_for(&self, start_line: &StartLine) -> Option<&CorsPolicy> {
+		self.find(start_line).and_then(|r| r.cors.as_ref()).or(self.cors.as_ref())
+	}
+
+	fn route(&self, context: &mut RequestContext) -> Dispatch<'_> {
+		let route = match self.find(&context.request.start_line) {
+			Some(r) => r,
+			None => return Dispatch::Response((self.fallback)(context))
+		};
+
+		let allowed = match (&route.access, &context.user) {
+			(&Access::Public, _) => true,
+			(_, &None) => return Dispatch::Response(challenge(context.request)),
+			(&Access::Role(ref role), &Some(ref user)) => user.has_role(role),
+			(&Access::Authenticated, &Some(_)) => true
+		};
+
+		if !allowed {
+			return Dispatch::Response(HttpResponse::new(StatusCode::forbidden()));
+		}
+
+		if let Some(ref template) = route.template {
+			context.variables = template.matches(&context.request.start_line.uri).unwrap_or_default();
+		}
+
+		match route.endpoint {
+			Endpoint::Http(ref handler) => {
+				let app = context.app;
+				let request = context.request;
+				let personal = route.access != Access::Public;
+				Dispatch::Response(app.cache.respond(request, personal, |request| run_handler(context, request, handler)))
+			},
+			Endpoint::Proxy(ref proxy) => Dispatch::Proxy(proxy),
+			Endpoint::EventStream(ref handler) => match handler(context) {
+				Ok(events) => Dispatch::Events(sse::response_head(), events),
+				Err(response) => Dispatch::Response(response)
+			},
+			Endpoint::WebSocket(ref handler) => {
+				if !websocket::is_upgrade_request(context.request) {
+					return Dispatch::Response(websocket::upgrade_required());
+				}
+
+				match websocket::handshake(context.request) {
+					Ok(response) => Dispatch::Upgrade(response, handler),
+					Err(response) => Dispatch::Response(response)
+				}
+			}
+		}
+	}
+}
+
+// Runs a handler on a request standing in for the context's own, e.g. a conditional one revalidating a cached response
+fn run_handler(context: &mut RequestContext, request: &HttpRequest, handler: &Handler) -> HttpResponse {
+	let mut standing_in = RequestContext {
+		app: context.app,
+		request,
+		session: mem::take(&mut context.session),
+		user: context.user.take(),
+		variables: mem::take(&mut context.variables)
+	};
+	let response = handler(&mut standing_in);
+
+	context.session = standing_in.session;
+	context.user = standing_in.user;
+	context.variables = standing_in.variables;
+	response
+}
+
+// Browsers are sent to the login page; API clients are told which Authorization schemes are accepted
+fn challenge(request: &HttpRequest) -> HttpResponse {
+	let wants_html = request.headers.first("Accept").map(|a| a.contains("text/html")).unwrap_or(false);
+
+	if wants_html && request.headers.get("Authorization").is_none() {
+		let login = Uri::new("/login");
+		let location = login.append_query_pair("next", &request.start_line.uri.to_string()).unwrap_or(login);
+		HttpResponse::new(StatusCode::see_other()).with_header("Location", &location.to_string())
+	} else {
+		HttpResponse::new(StatusCode::unauthorized())
+			.with_header("WWW-Authenticate", "Basic realm=\"welling\", charset=\"UTF-8\"")
+			.with_header("WWW-Authenticate", "Bearer realm=\"welling\"")
+	}
+}
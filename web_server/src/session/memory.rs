@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use session::{SessionStore, SessionData};
+
+/// Keeps sessions in process memory; they are lost when the server restarts.
+pub struct MemorySessionStore {
+	sessions: Mutex<HashMap<String, (SessionData, Instant)>>
+}
+
+impl MemorySessionStore {
+	pub fn new() -> MemorySessionStore {
+		MemorySessionStore {
+			sessions: Mutex::new(HashMap::new())
+		}
+	}
+}
+
+impl SessionStore for MemorySessionStore {
+	fn load(&self, id: &str) -> Option<SessionData> {
+		let mut sessions = self.sessions.lock().unwrap();
+
+		let expired = match sessions.get(id) {
+			Some(&(ref data, expires)) if expires > Instant::now() => return Some(data.clone()),
+			Some(_) => true,
+			None => false
+		};
+
+		if expired {
+			sessions.remove(id);
+		}
+		None
+	}
+
+	fn save(&self, id: &str, data: &SessionData, ttl: Duration) {
+		let mut sessions = self.sessions.lock().unwrap();
+
+		let now = Instant::now();
+		sessions.retain(|_, s| s.1 > now);
+		sessions.insert(id.to_string(), (data.clone(), now + ttl));
+	}
+
+	fn remove(&self, id: &str) {
+		self.sessions.lock().unwrap().remove(id);
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::thread;
+
+	#[test]
+	fn sessions_expire() {
+		let store = MemorySessionStore::new();
+		let mut data = SessionData::new();
+		data.insert(String::from("user"), String::from("luke"));
+
+		store.save("a", &data, Duration::from_secs(60));
+		store.save("b", &data, Duration::from_millis(1));
+		thread::sleep(Duration::from_millis(5));
+
+		assert_eq!(store.load("a"), Some(data));
+		assert_eq!(store.load("b"), None);
+
+		store.remove("a");
+		assert_eq!(store.load("a"), None);
+	}
+}
@@ -0,0 +1,152 @@
+mod memory;
+
+pub use self::memory::MemorySessionStore;
+
+use std::collections::HashMap;
+use std::time::Duration;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use encoding::to_hex;
+use http::model::*;
+
+pub type SessionData = HashMap<String, String>;
+
+/// How long a session is kept after its last change, unless the manager is given another time.
+const SESSION_TTL: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// Where session data lives between requests.
+//TODO: add a store backed by the database crate
+pub trait SessionStore: Send + Sync {
+	fn load(&self, id: &str) -> Option<SessionData>;
+	fn save(&self, id: &str, data: &SessionData, ttl: Duration);
+	fn remove(&self, id: &str);
+}
+
+#[derive(Default)]
+pub struct Session {
+	id: String,
+	data: SessionData,
+	is_new: bool,
+	destroyed: bool
+}
+
+impl Session {
+	fn new(id: String, data: Option<SessionData>) -> Session {
+		Session {
+			id,
+			is_new: data.is_none(),
+			data: data.unwrap_or_default(),
+			destroyed: false
+		}
+	}
+
+	/// Whether the client has yet to be sent the session's cookie.
+	#[allow(dead_code)]
+	pub fn is_new(&self) -> bool {
+		self.is_new
+	}
+
+	pub fn get(&self, key: &str) -> Option<&str> {
+		self.data.get(key).map(|v| v.as_str())
+	}
+
+	pub fn set(&mut self, key: &str, value: &str) {
+		self.data.insert(key.to_string(), value.to_string());
+	}
+
+	#[allow(dead_code)]
+	pub fn remove(&mut self, key: &str) -> Option<String> {
+		self.data.remove(key)
+	}
+
+	/// Drops the session's data; committing it will also expire the client's cookie.
+	pub fn destroy(&mut self) {
+		self.data.clear();
+		self.destroyed = true;
+	}
+}
+
+/// Ties sessions to clients through a signed cookie holding the session id.
+pub struct SessionManager {
+	store: Box<dyn SessionStore>,
+	key: CookieKey,
+	cookie_name: String,
+	ttl: Duration,
+	secure_cookie: bool,
+	random: SystemRandom
+}
+
+impl SessionManager {
+	pub fn new(store: Box<dyn SessionStore>, key: CookieKey) -> SessionManager {
+		SessionManager {
+			store,
+			key,
+			cookie_name: String::from("welling_session"),
+			ttl: SESSION_TTL,
+			secure_cookie: false,
+			random: SystemRandom::new()
+		}
+	}
+
+	#[allow(dead_code)]
+	pub fn with_ttl(mut self, ttl: Duration) -> SessionManager {
+		self.ttl = ttl;
+		self
+	}
+
+	/// Only send the session cookie over https connections, e.g. when the server is behind a proxy that terminates TLS.
+	pub fn with_secure_cookie(mut self, secure: bool) -> SessionManager {
+		self.secure_cookie = secure;
+		self
+	}
+
+	/// The session for the request's cookie, or a new empty session if it has none or it expired.
+	pub fn load(&self, request: &HttpRequest) -> Session {
+		let existing = request.cookies().get_signed(&self.cookie_name, &self.key)
+			.and_then(|id| self.store.load(&id).map(|data| (id, data)));
+
+		match existing {
+			Some((id, data)) => Session::new(id, Some(data)),
+			None => Session::new(self.generate_id(), None)
+		}
+	}
+
+	/// Moves the session's data to a new id, e.g. on login, so an id an attacker planted before it is useless.
+	pub fn regenerate(&self, session: &mut Session) {
+		self.store.remove(&session.id);
+		session.id = self.generate_id();
+		session.is_new = true;
+	}
+
+	/// Saves the session and sets the client's cookie. Empty new sessions are not stored.
+	pub fn commit(&self, session: &Session, response: &mut HttpResponse) {
+		if session.destroyed {
+			self.store.remove(&session.id);
+			if !session.is_new {
+				response.set_cookie(&Cookie::removal(&self.cookie_name, Some("/")));
+			}
+			return;
+		}
+
+		if session.is_new && session.data.is_empty() {
+			return;
+		}
+
+		self.store.save(&session.id, &session.data, self.ttl);
+
+		let mut cookie = Cookie::new(&self.cookie_name, &session.id);
+		cookie.path = Some(String::from("/"));
+		cookie.max_age = Some(self.ttl.as_secs() as i64);
+		cookie.http_only = true;
+		cookie.secure = self.secure_cookie;
+		cookie.same_site = Some(SameSite::Lax);
+		self.key.sign(&mut cookie);
+		response.set_cookie(&cookie);
+	}
+
+	fn generate_id(&self) -> String {
+		let mut id = [0_u8; 32];
+		self.random.fill(&mut id).expect("system random number generator failed");
+		to_hex(&id)
+	}
+}
@@ -0,0 +1,30 @@
+use std::io;
+use std::process;
+use std::thread;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+
+/// Waits for signals on a thread of its own: SIGHUP calls `reload`, and the first SIGINT or SIGTERM calls `stop`.
+/// Another one after that exits straight away, for when stopping gracefully is taking too long.
+pub fn handle<R, S>(reload: R, stop: S) -> io::Result<()>
+	where R: Fn() + Send + 'static, S: Fn() + Send + 'static {
+	let mut signals = Signals::new([SIGHUP, SIGINT, SIGTERM])?;
+
+	thread::Builder::new().name(String::from("signals")).spawn(move || {
+		let mut stopping = false;
+		for signal in signals.forever() {
+			match signal {
+				SIGHUP => reload(),
+				_ if stopping => {
+					warn!("Stopping without waiting for open connections");
+					process::exit(1);
+				},
+				_ => {
+					stopping = true;
+					stop();
+				}
+			}
+		}
+	})?;
+	Ok(())
+}
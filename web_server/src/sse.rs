@@ -0,0 +1,212 @@
+use std::io;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use http::model::*;
+
+//how often to check the client is still there while there is nothing to send
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+//proxies tend to drop connections that are quiet for much longer
+const HEARTBEAT: Duration = Duration::from_secs(15);
+
+/// One event, e.g. `Event::new("3 tasks due").with_event("reminder").with_id("42")`.
+/// Nothing is kept to replay: a browser that reconnects sends the last id it saw as Last-Event-ID, and it's up to the handler to catch it up.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Event {
+	id: Option<String>,
+	event: Option<String>,
+	data: String,
+	retry: Option<Duration>
+}
+
+impl Event {
+	/// A plain `message` event.
+	pub fn new(data: &str) -> Event {
+		Event { id: None, event: None, data: data.to_string(), retry: None }
+	}
+
+	/// What the browser sends back as Last-Event-ID when it reconnects.
+	#[allow(dead_code)]
+	pub fn with_id(mut self, id: &str) -> Event {
+		self.id = Some(single_line(id));
+		self
+	}
+
+	/// The event type listeners are registered for, instead of `message`.
+	pub fn with_event(mut self, event: &str) -> Event {
+		self.event = Some(single_line(event));
+		self
+	}
+
+	/// How long the browser should wait before reconnecting, from now on.
+	#[allow(dead_code)]
+	pub fn with_retry(mut self, retry: Duration) -> Event {
+		self.retry = Some(retry);
+		self
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut out = String::new();
+		if let Some(ref id) = self.id {
+			out.push_str(&format!("id: {}\n", id));
+		}
+		if let Some(ref event) = self.event {
+			out.push_str(&format!("event: {}\n", event));
+		}
+		if let Some(retry) = self.retry {
+			out.push_str(&format!("retry: {}\n", retry.as_millis()));
+		}
+		//each line of the data gets a field of its own, which the browser joins back up with newlines
+		for line in self.data.split('\n') {
+			out.push_str(&format!("data: {}\n", line.trim_end_matches('\r')));
+		}
+		out.push('\n');
+		out.into_bytes()
+	}
+}
+
+// A newline would end the field early and start another
+fn single_line(value: &str) -> String {
+	value.chars().filter(|&c| c != '\n' && c != '\r').collect()
+}
+
+/// What an event stream handler returns: the events to send, as they arrive on a channel.
+/// The stream ends once every sender is dropped, or the client goes away.
+pub struct EventStream {
+	next: Box<dyn FnMut(Duration) -> Result<Event, RecvTimeoutError> + Send>,
+	retry: Option<Duration>,
+	heartbeat: Duration
+}
+
+impl EventStream {
+	/// Streams whatever arrives on the channel, made into events as it arrives.
+	pub fn map<T, F>(events: Receiver<T>, mut to_event: F) -> EventStream
+		where T: Send + 'static, F: FnMut(T) -> Event + Send + 'static {
+		EventStream {
+			next: Box::new(move |timeout| events.recv_timeout(timeout).map(&mut to_event)),
+			retry: None,
+			heartbeat: HEARTBEAT
+		}
+	}
+
+	/// Tells the browser how long to wait before reconnecting, before any events are sent.
+	pub fn with_retry(mut self, retry: Duration) -> EventStream {
+		self.retry = Some(retry);
+		self
+	}
+}
+
+/// The head of an event stream. It has no length: the stream goes on until the connection closes.
+pub fn response_head() -> HttpResponse {
+	HttpResponse::new(StatusCode::ok())
+		.with_header("Content-Type", "text/event-stream")
+		.with_header("Cache-Control", "no-cache")
+		.with_header("Connection", "close")
+}
+
+/// Writes events to the client as they arrive, with a heartbeat comment whenever it has been quiet a while.
+/// Returns when the handler stops sending or the client disconnects, so the worker can go on to something else.
+pub fn stream(client: &TcpStream, mut events: EventStream) -> io::Result<()> {
+	let mut out = client;
+	if let Some(retry) = events.retry {
+		out.write_all(format!("retry: {}\n\n", retry.as_millis()).as_bytes())?;
+		out.flush()?;
+	}
+
+	let mut quiet_since = Instant::now();
+	loop {
+		match (events.next)(POLL_INTERVAL.min(events.heartbeat)) {
+			Ok(event) => {
+				out.write_all(&event.to_bytes())?;
+				quiet_since = Instant::now();
+			},
+			Err(RecvTimeoutError::Timeout) => if quiet_since.elapsed() >= events.heartbeat {
+				out.write_all(b": heartbeat\n\n")?;
+				quiet_since = Instant::now();
+			},
+			Err(RecvTimeoutError::Disconnected) => return Ok(())
+		}
+		out.flush()?;
+
+		//writes to a closed connection can go on succeeding for a while, so look for the close directly
+		if has_disconnected(client) {
+			return Ok(());
+		}
+	}
+}
+
+// Whether the client has closed its end. Anything it sends is thrown away; it has nothing to say on an event stream.
+fn has_disconnected(client: &TcpStream) -> bool {
+	if client.set_nonblocking(true).is_err() {
+		return true;
+	}
+	let mut buf = [0; 1];
+	let mut reader = client;
+	let closed = match client.peek(&mut buf) {
+		Ok(0) => true,
+		//drain it, so what's left to peek at next time is the close
+		Ok(_) => reader.read(&mut [0; 512]).map(|n| n == 0).unwrap_or(true),
+		Err(ref e) => e.kind() != io::ErrorKind::WouldBlock
+	};
+	client.set_nonblocking(false).is_err() || closed
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::net::TcpListener;
+	use std::sync::mpsc::channel;
+	use std::thread;
+
+	fn connected() -> (TcpStream, TcpStream) {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+		let (server, _) = listener.accept().unwrap();
+		(server, client)
+	}
+
+	#[test]
+	fn events_are_written_field_by_field() {
+		let event = Event::new("first\nsecond").with_id("7\r\n").with_event("remind\ner").with_retry(Duration::from_secs(3));
+		assert_eq!(String::from_utf8(event.to_bytes()).unwrap(), "id: 7\nevent: reminder\nretry: 3000\ndata: first\ndata: second\n\n");
+		assert_eq!(Event::new("hi").to_bytes(), b"data: hi\n\n".to_vec());
+	}
+
+	#[test]
+	fn events_are_streamed_until_the_senders_are_gone() {
+		let (server, mut client) = connected();
+		let (sender, receiver) = channel::<String>();
+
+		let streaming = thread::spawn(move || {
+			let mut events = EventStream::map(receiver, |data| Event::new(&data).with_event("greeting")).with_retry(Duration::from_secs(5));
+			events.heartbeat = Duration::from_millis(50);
+			stream(&server, events)
+		});
+		thread::sleep(Duration::from_millis(120));
+		sender.send(String::from("hello")).unwrap();
+		drop(sender);
+		streaming.join().unwrap().unwrap();
+
+		let mut received = String::new();
+		client.read_to_string(&mut received).unwrap();
+		assert!(received.starts_with("retry: 5000\n\n: heartbeat\n\n"), "{:?}", received);
+		assert!(received.ends_with("event: greeting\ndata: hello\n\n"), "{:?}", received);
+	}
+
+	#[test]
+	fn a_client_going_away_frees_the_worker() {
+		let (server, client) = connected();
+		let (sender, receiver) = channel();
+
+		let streaming = thread::spawn(move || stream(&server, EventStream::map(receiver, |data: String| Event::new(&data))));
+		drop(client);
+
+		//the sender is still open, so only the disconnect can end the stream
+		let started = Instant::now();
+		streaming.join().unwrap().ok();
+		assert!(started.elapsed() < Duration::from_secs(3));
+		drop(sender);
+	}
+}
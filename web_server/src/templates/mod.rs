@@ -0,0 +1,203 @@
+pub(crate) mod parser;
+mod render;
+mod value;
+
+pub use self::value::Value;
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use self::parser::Node;
+use self::render::Renderer;
+
+// views compiled into the binary by build.rs, as (name, source) pairs
+mod embedded {
+	include!(concat!(env!("OUT_DIR"), "/views.rs"));
+}
+
+macro_rules! view_data {
+	($($k:expr => $v:expr),* $(,)*) => {{
+		let mut data = ::templates::Value::map();
+		$( data.insert($k, $v); )*
+		data
+	}};
+}
+
+#[derive(Debug)]
+pub struct TemplateError {
+	pub template: String,
+	pub line: Option<usize>,
+	pub message: String
+}
+
+impl TemplateError {
+	fn new(template: &str, message: String) -> TemplateError {
+		TemplateError {
+			template: template.to_string(),
+			line: None,
+			message
+		}
+	}
+
+	fn in_template(mut self, template: &str) -> TemplateError {
+		if self.template.is_empty() {
+			self.template = template.to_string();
+		}
+		self
+	}
+}
+
+impl Display for TemplateError {
+	fn fmt(&self, f: &mut Formatter) -> FmtResult {
+		match self.line {
+			Some(l) => write!(f, "{} (line {}): {}", self.template, l, self.message),
+			None => write!(f, "{}: {}", self.template, self.message)
+		}
+	}
+}
+
+impl From<io::Error> for TemplateError {
+	fn from(e: io::Error) -> TemplateError {
+		TemplateError::new("", format!("{}", e))
+	}
+}
+
+/// Compiled templates, looked up by their path relative to the views directory (e.g. "Index.html").
+pub struct TemplateEngine {
+	templates: HashMap<String, Vec<Node>>
+}
+
+impl TemplateEngine {
+	pub fn new() -> TemplateEngine {
+		TemplateEngine {
+			templates: HashMap::new()
+		}
+	}
+
+	/// Compiles every file under `dir`.
+	pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<TemplateEngine, TemplateError> {
+		let mut engine = TemplateEngine::new();
+		engine.add_dir(dir.as_ref(), "")?;
+		Ok(engine)
+	}
+
+	/// Compiles the views that build.rs embedded in the binary.
+	pub fn embedded() -> Result<TemplateEngine, TemplateError> {
+		let mut engine = TemplateEngine::new();
+		for &(name, source) in embedded::VIEWS {
+			engine.add_template(name, source)?;
+		}
+		Ok(engine)
+	}
+
+	pub fn add_template(&mut self, name: &str, source: &str) -> Result<(), TemplateError> {
+		match parser::parse(source) {
+			Ok(nodes) => {
+				self.templates.insert(name.to_string(), nodes);
+				Ok(())
+			},
+			Err(e) => Err(TemplateError {
+				template: name.to_string(),
+				line: Some(e.line),
+				message: e.message
+			})
+		}
+	}
+
+	/// Renders a template, html-escaping output if the template is html or xml.
+	pub fn render(&self, name: &str, data: &Value) -> Result<String, TemplateError> {
+		let escape = [".html", ".htm", ".xml"].iter().any(|ext| name.ends_with(ext));
+
+		let mut out = String::new();
+		Renderer::new(self, data, escape).render_template(name, &mut out)?;
+		Ok(out)
+	}
+
+	pub(crate) fn get(&self, name: &str) -> Option<&Vec<Node>> {
+		self.templates.get(name)
+	}
+
+	fn add_dir(&mut self, dir: &Path, prefix: &str) -> Result<(), TemplateError> {
+		for entry in fs::read_dir(dir)? {
+			let path = entry?.path();
+			let file_name = match path.file_name().and_then(|n| n.to_str()) {
+				Some(n) => format!("{}{}", prefix, n),
+				None => continue
+			};
+
+			if path.is_dir() {
+				self.add_dir(&path, &format!("{}/", file_name))?;
+			} else {
+				let source = fs::read_to_string(&path)?;
+				self.add_template(&file_name, &source)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn engine(templates: &[(&str, &str)]) -> TemplateEngine {
+		let mut engine = TemplateEngine::new();
+		for &(name, source) in templates {
+			engine.add_template(name, source).unwrap();
+		}
+		engine
+	}
+
+	#[test]
+	fn variables_are_escaped_in_html() {
+		let e = engine(&[("t.html", "<p>{{ user.name }}</p>{{ user.name | raw }}")]);
+		let data = view_data!("user" => Value::map().with("name", "<b>Luke</b>"));
+		assert_eq!(e.render("t.html", &data).unwrap(), "<p>&lt;b&gt;Luke&lt;/b&gt;</p><b>Luke</b>");
+	}
+
+	#[test]
+	fn loops_and_conditionals() {
+		let e = engine(&[("t.txt", "{% for r in rooms %}{{ loop.index }}:{{ r }}{% if not loop.last %},{% endif %}{% else %}none{% endfor %}")]);
+		let data = view_data!("rooms" => vec!("Kitchen", "Bedroom"));
+		assert_eq!(e.render("t.txt", &data).unwrap(), "1:Kitchen,2:Bedroom");
+		assert_eq!(e.render("t.txt", &Value::map()).unwrap(), "none");
+	}
+
+	#[test]
+	fn elif_and_comparison() {
+		let e = engine(&[("t.txt", "{% if kind == \"a\" %}A{% elif kind != \"b\" and count %}C{% else %}B{% endif %}")]);
+		assert_eq!(e.render("t.txt", &view_data!("kind" => "a")).unwrap(), "A");
+		assert_eq!(e.render("t.txt", &view_data!("kind" => "b", "count" => 1)).unwrap(), "B");
+		assert_eq!(e.render("t.txt", &view_data!("kind" => "c", "count" => 1)).unwrap(), "C");
+	}
+
+	#[test]
+	fn layouts_and_includes() {
+		let e = engine(&[
+			("layout.html", "<title>{% block title %}Default{% endblock %}</title>{% block body %}{% endblock %}"),
+			("partial.html", "[{{ name }}]"),
+			("page.html", "{% extends \"layout.html\" %}{% block body %}{% include \"partial.html\" %}{% endblock %}")
+		]);
+		assert_eq!(e.render("page.html", &view_data!("name" => "x")).unwrap(), "<title>Default</title>[x]");
+	}
+
+	#[test]
+	fn parse_errors_report_line() {
+		let mut e = TemplateEngine::new();
+		let err = e.add_template("bad.html", "<p>\n{% if x %}\n").unwrap_err();
+		assert_eq!(err.line, Some(2));
+
+		let err = e.add_template("bad.html", "{% endfor %}").unwrap_err();
+		assert_eq!(err.line, Some(1));
+	}
+
+	#[test]
+	fn missing_include_is_an_error() {
+		let e = engine(&[("t.html", "{% include \"nope.html\" %}")]);
+		assert!(e.render("t.html", &Value::map()).is_err());
+	}
+}
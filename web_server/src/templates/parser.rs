@@ -0,0 +1,435 @@
+// Template syntax
+//	{{ expr }}				output, html-escaped unless the raw filter is applied: {{ expr | raw }}
+//	{% if expr %}			with optional {% elif expr %} and {% else %}, closed by {% endif %}
+//	{% for x in expr %}		with optional {% else %} for empty lists, closed by {% endfor %}
+//	{% include "name" %}	renders another template with the current data
+//	{% extends "name" %}	must come first; the child's {% block name %}...{% endblock %} replace the parent's
+//	{# comment #}
+//
+// This file is also pulled into build.rs so that broken views fail the build, so it must only depend on std.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+	Text(String),
+	Output { expr: Expr, filters: Vec<String> },
+	If { branches: Vec<(Expr, Vec<Node>)>, otherwise: Vec<Node> },
+	For { var: String, iterable: Expr, body: Vec<Node>, empty: Vec<Node> },
+	Include(String),
+	Extends(String),
+	Block { name: String, body: Vec<Node> }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+	Path(Vec<String>),
+	Str(String),
+	Int(i64),
+	Bool(bool),
+	Not(Box<Expr>),
+	And(Box<Expr>, Box<Expr>),
+	Or(Box<Expr>, Box<Expr>),
+	Eq(Box<Expr>, Box<Expr>),
+	NotEq(Box<Expr>, Box<Expr>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+	pub line: usize,
+	pub message: String
+}
+
+impl ParseError {
+	fn new(line: usize, message: String) -> ParseError {
+		ParseError { line, message }
+	}
+}
+
+impl Display for ParseError {
+	fn fmt(&self, f: &mut Formatter) -> FmtResult {
+		write!(f, "line {}: {}", self.line, self.message)
+	}
+}
+
+pub fn parse(source: &str) -> Result<Vec<Node>, ParseError> {
+	let tokens = tokenize(source)?;
+	let mut pos = 0;
+	let (nodes, _) = parse_nodes(&tokens, &mut pos, &[])?;
+
+	for (ix, node) in nodes.iter().enumerate() {
+		if let Node::Extends(_) = *node {
+			let only_text_before = nodes[..ix].iter().all(|n| match *n {
+				Node::Text(ref t) => t.trim().is_empty(),
+				_ => false
+			});
+			if !only_text_before {
+				return Err(ParseError::new(1, String::from("extends must be the first tag in a template")));
+			}
+		}
+	}
+
+	Ok(nodes)
+}
+
+
+enum Token {
+	Text(String),
+	Output(String, usize),
+	Tag(String, usize)
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+	let mut tokens = Vec::new();
+	let mut rest = source;
+	let mut line = 1;
+
+	while !rest.is_empty() {
+		let next_open = ["{{", "{%", "{#"].iter()
+			.filter_map(|d| rest.find(d))
+			.min();
+
+		let start = match next_open {
+			Some(s) => s,
+			None => {
+				tokens.push(Token::Text(rest.to_string()));
+				break;
+			}
+		};
+
+		if start > 0 {
+			tokens.push(Token::Text(rest[..start].to_string()));
+			line += count_lines(&rest[..start]);
+		}
+
+		let close = match &rest[start..start + 2] {
+			"{{" => "}}",
+			"{%" => "%}",
+			_ => "#}"
+		};
+
+		let inner_start = start + 2;
+		let inner_len = match rest[inner_start..].find(close) {
+			Some(l) => l,
+			None => return Err(ParseError::new(line, format!("unclosed '{}'", &rest[start..inner_start])))
+		};
+		let inner = &rest[inner_start..inner_start + inner_len];
+
+		match close {
+			"}}" => tokens.push(Token::Output(inner.trim().to_string(), line)),
+			"%}" => tokens.push(Token::Tag(inner.trim().to_string(), line)),
+			_ => ()
+		}
+
+		line += count_lines(inner);
+		rest = &rest[inner_start + inner_len + 2..];
+	}
+
+	Ok(tokens)
+}
+
+fn count_lines(s: &str) -> usize {
+	s.bytes().filter(|b| *b == b'\n').count()
+}
+
+// The tag that ended a run of nodes, e.g. `endif`, and the line it was on
+type Terminator = (String, usize);
+
+// Parses nodes until one of the terminator tags (endif, else, ...) is found; the terminator is consumed and returned
+fn parse_nodes(tokens: &[Token], pos: &mut usize, terminators: &[&str]) -> Result<(Vec<Node>, Option<Terminator>), ParseError> {
+	let mut nodes = Vec::new();
+
+	while *pos < tokens.len() {
+		let token = &tokens[*pos];
+		*pos += 1;
+
+		match *token {
+			Token::Text(ref t) => nodes.push(Node::Text(t.clone())),
+			Token::Output(ref o, line) => nodes.push(parse_output(o, line)?),
+			Token::Tag(ref tag, line) => {
+				let (keyword, args) = split_keyword(tag);
+
+				if terminators.contains(&keyword) {
+					return Ok((nodes, Some((tag.clone(), line))));
+				}
+
+				match keyword {
+					"if" => nodes.push(parse_if(args, tokens, pos, line)?),
+					"for" => nodes.push(parse_for(args, tokens, pos, line)?),
+					"include" => nodes.push(Node::Include(parse_name(args, line)?)),
+					"extends" => nodes.push(Node::Extends(parse_name(args, line)?)),
+					"block" => {
+						let name = args.trim();
+						if !is_identifier(name) {
+							return Err(ParseError::new(line, format!("invalid block name '{}'", name)));
+						}
+						let (body, end) = parse_nodes(tokens, pos, &["endblock"])?;
+						expect_terminator(end, "block", line)?;
+						nodes.push(Node::Block { name: name.to_string(), body });
+					},
+					"else" | "elif" => return Err(ParseError::new(line, format!("unexpected {{% {} %}}", keyword))),
+					_ if keyword.starts_with("end") => return Err(ParseError::new(line, format!("unexpected {{% {} %}}", keyword))),
+					_ => return Err(ParseError::new(line, format!("unknown tag '{}'", keyword)))
+				}
+			}
+		}
+	}
+
+	Ok((nodes, None))
+}
+
+fn expect_terminator(end: Option<Terminator>, opened: &str, line: usize) -> Result<String, ParseError> {
+	match end {
+		Some((tag, _)) => Ok(tag),
+		None => Err(ParseError::new(line, format!("{{% {} %}} is never closed", opened)))
+	}
+}
+
+fn split_keyword(tag: &str) -> (&str, &str) {
+	match tag.find(char::is_whitespace) {
+		Some(ix) => (&tag[..ix], tag[ix..].trim()),
+		None => (tag, "")
+	}
+}
+
+fn parse_if(condition: &str, tokens: &[Token], pos: &mut usize, line: usize) -> Result<Node, ParseError> {
+	let mut branches = Vec::new();
+	let mut otherwise = Vec::new();
+	let mut condition = parse_expr(condition, line)?;
+
+	loop {
+		let (body, end) = parse_nodes(tokens, pos, &["elif", "else", "endif"])?;
+		let end = expect_terminator(end, "if", line)?;
+		branches.push((condition, body));
+
+		let (keyword, args) = split_keyword(&end);
+		match keyword {
+			"elif" => condition = parse_expr(args, line)?,
+			"else" => {
+				let (body, end) = parse_nodes(tokens, pos, &["endif"])?;
+				expect_terminator(end, "if", line)?;
+				otherwise = body;
+				break;
+			},
+			_ => break
+		}
+	}
+
+	Ok(Node::If { branches, otherwise })
+}
+
+fn parse_for(args: &str, tokens: &[Token], pos: &mut usize, line: usize) -> Result<Node, ParseError> {
+	let parts: Vec<&str> = args.splitn(3, char::is_whitespace).collect();
+	if parts.len() != 3 || parts[1] != "in" || !is_identifier(parts[0]) {
+		return Err(ParseError::new(line, String::from("expected {% for <name> in <expression> %}")));
+	}
+
+	let iterable = parse_expr(parts[2], line)?;
+
+	let (body, end) = parse_nodes(tokens, pos, &["else", "endfor"])?;
+	let end = expect_terminator(end, "for", line)?;
+
+	let mut empty = Vec::new();
+	if end == "else" {
+		let (body, end) = parse_nodes(tokens, pos, &["endfor"])?;
+		expect_terminator(end, "for", line)?;
+		empty = body;
+	}
+
+	Ok(Node::For { var: parts[0].to_string(), iterable, body, empty })
+}
+
+fn parse_name(args: &str, line: usize) -> Result<String, ParseError> {
+	match parse_expr(args, line)? {
+		Expr::Str(s) => Ok(s),
+		_ => Err(ParseError::new(line, String::from("expected a quoted template name")))
+	}
+}
+
+fn parse_output(raw: &str, line: usize) -> Result<Node, ParseError> {
+	let mut tokens = lex_expr(raw, line)?;
+
+	let mut filters = Vec::new();
+	while let Some(ix) = tokens.iter().rposition(|t| *t == ExprToken::Pipe) {
+		match tokens.get(ix + 1) {
+			Some(ExprToken::Ident(name)) if tokens.len() == ix + 2 => filters.insert(0, name.clone()),
+			_ => return Err(ParseError::new(line, String::from("expected a filter name after '|'")))
+		}
+		tokens.truncate(ix);
+	}
+
+	let expr = parse_expr_tokens(tokens, line)?;
+	Ok(Node::Output { expr, filters })
+}
+
+fn is_identifier(s: &str) -> bool {
+	let mut chars = s.chars();
+	match chars.next() {
+		Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+		_ => false
+	}
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum ExprToken {
+	Ident(String),
+	Str(String),
+	Int(i64),
+	Eq,
+	NotEq,
+	Pipe,
+	Open,
+	Close
+}
+
+fn lex_expr(raw: &str, line: usize) -> Result<Vec<ExprToken>, ParseError> {
+	let chars: Vec<char> = raw.chars().collect();
+	let mut tokens = Vec::new();
+	let mut ix = 0;
+
+	while ix < chars.len() {
+		let c = chars[ix];
+		match c {
+			' ' | '\t' | '\r' | '\n' => ix += 1,
+			'|' => { tokens.push(ExprToken::Pipe); ix += 1; },
+			'(' => { tokens.push(ExprToken::Open); ix += 1; },
+			')' => { tokens.push(ExprToken::Close); ix += 1; },
+			'=' | '!' if chars.get(ix + 1) == Some(&'=') => {
+				tokens.push(if c == '=' { ExprToken::Eq } else { ExprToken::NotEq });
+				ix += 2;
+			},
+			'"' | '\'' => {
+				let end = match chars[ix + 1..].iter().position(|e| *e == c) {
+					Some(e) => ix + 1 + e,
+					None => return Err(ParseError::new(line, String::from("unterminated string literal")))
+				};
+				tokens.push(ExprToken::Str(chars[ix + 1..end].iter().collect()));
+				ix = end + 1;
+			},
+			'0' ..= '9' | '-' => {
+				let start = ix;
+				ix += 1;
+				while ix < chars.len() && chars[ix].is_ascii_digit() { ix += 1; }
+				let literal: String = chars[start..ix].iter().collect();
+				match literal.parse::<i64>() {
+					Ok(i) => tokens.push(ExprToken::Int(i)),
+					Err(_) => return Err(ParseError::new(line, format!("invalid number '{}'", literal)))
+				}
+			},
+			_ if c.is_ascii_alphabetic() || c == '_' => {
+				let start = ix;
+				while ix < chars.len() && (chars[ix].is_ascii_alphanumeric() || chars[ix] == '_' || chars[ix] == '.') { ix += 1; }
+				tokens.push(ExprToken::Ident(chars[start..ix].iter().collect()));
+			},
+			_ => return Err(ParseError::new(line, format!("unexpected character '{}' in expression", c)))
+		}
+	}
+
+	Ok(tokens)
+}
+
+fn parse_expr(raw: &str, line: usize) -> Result<Expr, ParseError> {
+	let tokens = lex_expr(raw, line)?;
+	parse_expr_tokens(tokens, line)
+}
+
+fn parse_expr_tokens(tokens: Vec<ExprToken>, line: usize) -> Result<Expr, ParseError> {
+	if tokens.is_empty() {
+		return Err(ParseError::new(line, String::from("empty expression")));
+	}
+
+	let mut parser = ExprParser { tokens, pos: 0, line };
+	let expr = parser.or()?;
+	if parser.pos != parser.tokens.len() {
+		return Err(ParseError::new(line, format!("unexpected {:?} in expression", parser.tokens[parser.pos])));
+	}
+	Ok(expr)
+}
+
+// or := and ("or" and)*, and := not ("and" not)*, not := "not" not | cmp, cmp := primary (("==" | "!=") primary)?
+struct ExprParser {
+	tokens: Vec<ExprToken>,
+	pos: usize,
+	line: usize
+}
+
+impl ExprParser {
+	fn peek_keyword(&self, keyword: &str) -> bool {
+		matches!(self.tokens.get(self.pos), Some(ExprToken::Ident(i)) if i == keyword)
+	}
+
+	fn or(&mut self) -> Result<Expr, ParseError> {
+		let mut left = self.and()?;
+		while self.peek_keyword("or") {
+			self.pos += 1;
+			left = Expr::Or(Box::new(left), Box::new(self.and()?));
+		}
+		Ok(left)
+	}
+
+	fn and(&mut self) -> Result<Expr, ParseError> {
+		let mut left = self.not()?;
+		while self.peek_keyword("and") {
+			self.pos += 1;
+			left = Expr::And(Box::new(left), Box::new(self.not()?));
+		}
+		Ok(left)
+	}
+
+	fn not(&mut self) -> Result<Expr, ParseError> {
+		if self.peek_keyword("not") {
+			self.pos += 1;
+			return Ok(Expr::Not(Box::new(self.not()?)));
+		}
+		self.comparison()
+	}
+
+	fn comparison(&mut self) -> Result<Expr, ParseError> {
+		let left = self.primary()?;
+		match self.tokens.get(self.pos) {
+			Some(&ExprToken::Eq) => {
+				self.pos += 1;
+				Ok(Expr::Eq(Box::new(left), Box::new(self.primary()?)))
+			},
+			Some(&ExprToken::NotEq) => {
+				self.pos += 1;
+				Ok(Expr::NotEq(Box::new(left), Box::new(self.primary()?)))
+			},
+			_ => Ok(left)
+		}
+	}
+
+	fn primary(&mut self) -> Result<Expr, ParseError> {
+		let token = match self.tokens.get(self.pos) {
+			Some(t) => t.clone(),
+			None => return Err(ParseError::new(self.line, String::from("expression ended unexpectedly")))
+		};
+		self.pos += 1;
+
+		match token {
+			ExprToken::Str(s) => Ok(Expr::Str(s)),
+			ExprToken::Int(i) => Ok(Expr::Int(i)),
+			ExprToken::Ident(ref i) if i == "true" => Ok(Expr::Bool(true)),
+			ExprToken::Ident(ref i) if i == "false" => Ok(Expr::Bool(false)),
+			ExprToken::Ident(i) => {
+				let segments: Vec<String> = i.split('.').map(String::from).collect();
+				if segments.iter().any(|s| s.is_empty()) {
+					return Err(ParseError::new(self.line, format!("invalid variable '{}'", i)));
+				}
+				Ok(Expr::Path(segments))
+			},
+			ExprToken::Open => {
+				let inner = self.or()?;
+				match self.tokens.get(self.pos) {
+					Some(&ExprToken::Close) => {
+						self.pos += 1;
+						Ok(inner)
+					},
+					_ => Err(ParseError::new(self.line, String::from("expected ')'")))
+				}
+			},
+			t => Err(ParseError::new(self.line, format!("unexpected {:?} in expression", t)))
+		}
+	}
+}
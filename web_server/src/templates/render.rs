@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use templates::parser::{Node, Expr};
+use templates::value::Value;
+use templates::{TemplateEngine, TemplateError};
+
+const MAX_DEPTH: usize = 16;
+
+pub(crate) struct Renderer<'a> {
+	engine: &'a TemplateEngine,
+	data: &'a Value,
+	scopes: Vec<(String, Value)>,
+	blocks: HashMap<String, &'a [Node]>,
+	escape: bool,
+	depth: usize
+}
+
+impl<'a> Renderer<'a> {
+	pub(crate) fn new(engine: &'a TemplateEngine, data: &'a Value, escape: bool) -> Renderer<'a> {
+		Renderer {
+			engine,
+			data,
+			scopes: Vec::new(),
+			blocks: HashMap::new(),
+			escape,
+			depth: 0
+		}
+	}
+
+	pub(crate) fn render_template(&mut self, name: &str, out: &mut String) -> Result<(), TemplateError> {
+		if self.depth >= MAX_DEPTH {
+			return Err(TemplateError::new(name, format!("includes nested more than {} deep", MAX_DEPTH)));
+		}
+
+		let engine = self.engine;
+		let nodes = match engine.get(name) {
+			Some(n) => n,
+			None => return Err(TemplateError::new(name, String::from("template not found")))
+		};
+
+		self.depth += 1;
+
+		// A child template only contributes its blocks; the output comes from the parent
+		let parent = nodes.iter().filter_map(|n| match *n {
+			Node::Extends(ref p) => Some(p),
+			_ => None
+		}).next();
+
+		let result = match parent {
+			Some(parent) => {
+				for node in nodes {
+					if let Node::Block { ref name, ref body } = *node {
+						// blocks from templates further down the chain win
+						self.blocks.entry(name.clone()).or_insert(&body[..]);
+					}
+				}
+				self.render_template(parent, out)
+			},
+			None => self.render_nodes(nodes, out).map_err(|e| e.in_template(name))
+		};
+
+		self.depth -= 1;
+		result
+	}
+
+	fn render_nodes(&mut self, nodes: &'a [Node], out: &mut String) -> Result<(), TemplateError> {
+		for node in nodes {
+			match *node {
+				Node::Text(ref t) => out.push_str(t),
+				Node::Output { ref expr, ref filters } => {
+					let value = self.eval(expr);
+					self.write_output(value, filters, out)?;
+				},
+				Node::If { ref branches, ref otherwise } => {
+					let taken = branches.iter().find(|b| self.eval(&b.0).is_truthy());
+					match taken {
+						Some(branch) => self.render_nodes(&branch.1, out)?,
+						None => self.render_nodes(otherwise, out)?
+					}
+				},
+				Node::For { ref var, ref iterable, ref body, ref empty } => {
+					let items = match self.eval(iterable) {
+						Value::List(l) => l,
+						Value::Map(m) => m.into_iter().map(|(k, v)| Value::map().with("key", k).with("value", v)).collect(),
+						Value::Null => Vec::new(),
+						v => vec!(v)
+					};
+
+					if items.is_empty() {
+						self.render_nodes(empty, out)?;
+					}
+
+					let length = items.len();
+					for (ix, item) in items.into_iter().enumerate() {
+						let loop_info = Value::map()
+							.with("index", ix + 1)
+							.with("index0", ix)
+							.with("first", ix == 0)
+							.with("last", ix + 1 == length)
+							.with("length", length);
+
+						self.scopes.push((String::from("loop"), loop_info));
+						self.scopes.push((var.clone(), item));
+						let result = self.render_nodes(body, out);
+						self.scopes.pop();
+						self.scopes.pop();
+						result?;
+					}
+				},
+				Node::Include(ref name) => self.render_template(name, out)?,
+				Node::Block { ref name, ref body } => {
+					let body = match self.blocks.get(name) {
+						Some(b) => *b,
+						None => &body[..]
+					};
+					self.render_nodes(body, out)?;
+				},
+				Node::Extends(_) => ()
+			}
+		}
+
+		Ok(())
+	}
+
+	fn write_output(&self, value: Value, filters: &[String], out: &mut String) -> Result<(), TemplateError> {
+		let mut value = value;
+		let mut escape = self.escape;
+
+		for filter in filters {
+			value = match filter.as_ref() {
+				"raw" => { escape = false; value },
+				"upper" => Value::Str(value.to_string().to_uppercase()),
+				"lower" => Value::Str(value.to_string().to_lowercase()),
+				"length" => Value::Int(value.len() as i64),
+				_ => return Err(TemplateError::new("", format!("unknown filter '{}'", filter)))
+			};
+		}
+
+		let text = value.to_string();
+		if escape {
+			escape_html(&text, out);
+		} else {
+			out.push_str(&text);
+		}
+		Ok(())
+	}
+
+	fn eval(&self, expr: &Expr) -> Value {
+		match *expr {
+			Expr::Path(ref segments) => self.lookup(segments),
+			Expr::Str(ref s) => Value::Str(s.clone()),
+			Expr::Int(i) => Value::Int(i),
+			Expr::Bool(b) => Value::Bool(b),
+			Expr::Not(ref e) => Value::Bool(!self.eval(e).is_truthy()),
+			Expr::And(ref l, ref r) => Value::Bool(self.eval(l).is_truthy() && self.eval(r).is_truthy()),
+			Expr::Or(ref l, ref r) => Value::Bool(self.eval(l).is_truthy() || self.eval(r).is_truthy()),
+			Expr::Eq(ref l, ref r) => Value::Bool(values_equal(&self.eval(l), &self.eval(r))),
+			Expr::NotEq(ref l, ref r) => Value::Bool(!values_equal(&self.eval(l), &self.eval(r)))
+		}
+	}
+
+	// Undefined variables render as nothing, the same as a null
+	fn lookup(&self, segments: &[String]) -> Value {
+		let first = &segments[0];
+		let root = match self.scopes.iter().rev().find(|s| s.0 == *first) {
+			Some(scope) => Some(&scope.1),
+			None => self.data.get(first)
+		};
+
+		let mut current = match root {
+			Some(v) => v,
+			None => return Value::Null
+		};
+
+		for segment in &segments[1..] {
+			current = match current.get(segment) {
+				Some(v) => v,
+				None => return Value::Null
+			};
+		}
+
+		current.clone()
+	}
+}
+
+fn values_equal(left: &Value, right: &Value) -> bool {
+	match (left, right) {
+		(&Value::Int(l), &Value::Float(r)) | (&Value::Float(r), &Value::Int(l)) => (l as f64) == r,
+		_ => left == right
+	}
+}
+
+pub(crate) fn escape_html(text: &str, out: &mut String) {
+	for c in text.chars() {
+		match c {
+			'&' => out.push_str("&amp;"),
+			'<' => out.push_str("&lt;"),
+			'>' => out.push_str("&gt;"),
+			'"' => out.push_str("&quot;"),
+			'\'' => out.push_str("&#39;"),
+			_ => out.push(c)
+		}
+	}
+}
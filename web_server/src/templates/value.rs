@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Data handed from a handler to a template.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+	Null,
+	Bool(bool),
+	Int(i64),
+	Float(f64),
+	Str(String),
+	List(Vec<Value>),
+	Map(BTreeMap<String, Value>)
+}
+
+impl Value {
+	pub fn map() -> Value {
+		Value::Map(BTreeMap::new())
+	}
+
+	/// Adds a key to a map value, builder style. Panics if the value is not a map.
+	pub fn with<K: Into<String>, V: Into<Value>>(mut self, key: K, value: V) -> Value {
+		self.insert(key, value);
+		self
+	}
+
+	pub fn insert<K: Into<String>, V: Into<Value>>(&mut self, key: K, value: V) {
+		match *self {
+			Value::Map(ref mut m) => { m.insert(key.into(), value.into()); },
+			_ => panic!("insert called on a template value that is not a map")
+		}
+	}
+
+	pub fn get(&self, key: &str) -> Option<&Value> {
+		match *self {
+			Value::Map(ref m) => m.get(key),
+			Value::List(ref l) => key.parse::<usize>().ok().and_then(|ix| l.get(ix)),
+			_ => None
+		}
+	}
+
+	pub fn is_truthy(&self) -> bool {
+		match *self {
+			Value::Null => false,
+			Value::Bool(b) => b,
+			Value::Int(i) => i != 0,
+			Value::Float(f) => f != 0.0,
+			Value::Str(ref s) => !s.is_empty(),
+			Value::List(ref l) => !l.is_empty(),
+			Value::Map(ref m) => !m.is_empty()
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		match *self {
+			Value::Str(ref s) => s.chars().count(),
+			Value::List(ref l) => l.len(),
+			Value::Map(ref m) => m.len(),
+			_ => 0
+		}
+	}
+}
+
+impl Display for Value {
+	fn fmt(&self, f: &mut Formatter) -> FmtResult {
+		match *self {
+			Value::Null => Ok(()),
+			Value::Bool(b) => write!(f, "{}", b),
+			Value::Int(i) => write!(f, "{}", i),
+			Value::Float(v) => write!(f, "{}", v),
+			Value::Str(ref s) => write!(f, "{}", s),
+			Value::List(ref l) => {
+				for (ix, v) in l.iter().enumerate() {
+					if ix > 0 { write!(f, ", ")?; }
+					write!(f, "{}", v)?;
+				}
+				Ok(())
+			},
+			Value::Map(_) => write!(f, "[map]")
+		}
+	}
+}
+
+macro_rules! value_from {
+	($t:ty, $v:ident => $e:expr) => {
+		impl From<$t> for Value {
+			fn from($v: $t) -> Value { $e }
+		}
+	}
+}
+
+value_from!(bool, b => Value::Bool(b));
+value_from!(i32, i => Value::Int(i as i64));
+value_from!(i64, i => Value::Int(i));
+value_from!(u32, i => Value::Int(i as i64));
+value_from!(usize, i => Value::Int(i as i64));
+value_from!(f64, v => Value::Float(v));
+value_from!(String, s => Value::Str(s));
+value_from!(BTreeMap<String, Value>, m => Value::Map(m));
+
+impl<'a> From<&'a str> for Value {
+	fn from(s: &'a str) -> Value {
+		Value::Str(s.to_string())
+	}
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+	fn from(l: Vec<T>) -> Value {
+		Value::List(l.into_iter().map(Into::into).collect())
+	}
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+	fn from(o: Option<T>) -> Value {
+		match o {
+			Some(v) => v.into(),
+			None => Value::Null
+		}
+	}
+}
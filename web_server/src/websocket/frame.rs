@@ -0,0 +1,169 @@
+use std::io::{Read, Write};
+
+use websocket::WebSocketError;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OpCode {
+	Continuation,
+	Text,
+	Binary,
+	Close,
+	Ping,
+	Pong
+}
+
+impl OpCode {
+	fn from_bits(bits: u8) -> Option<OpCode> {
+		match bits {
+			0x0 => Some(OpCode::Continuation),
+			0x1 => Some(OpCode::Text),
+			0x2 => Some(OpCode::Binary),
+			0x8 => Some(OpCode::Close),
+			0x9 => Some(OpCode::Ping),
+			0xA => Some(OpCode::Pong),
+			_ => None
+		}
+	}
+
+	fn bits(&self) -> u8 {
+		match *self {
+			OpCode::Continuation => 0x0,
+			OpCode::Text => 0x1,
+			OpCode::Binary => 0x2,
+			OpCode::Close => 0x8,
+			OpCode::Ping => 0x9,
+			OpCode::Pong => 0xA
+		}
+	}
+
+	pub fn is_control(&self) -> bool {
+		matches!(*self, OpCode::Close | OpCode::Ping | OpCode::Pong)
+	}
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Frame {
+	pub fin: bool,
+	pub opcode: OpCode,
+	pub payload: Vec<u8>
+}
+
+impl Frame {
+	pub fn new(opcode: OpCode, payload: Vec<u8>) -> Frame {
+		Frame { fin: true, opcode, payload }
+	}
+
+	/// Reads one frame sent by a client. Client frames must be masked (RFC 6455 5.1); the payload is returned unmasked.
+	pub fn read<R: Read>(reader: &mut R, max_payload: usize) -> Result<Frame, WebSocketError> {
+		let mut head = [0_u8; 2];
+		reader.read_exact(&mut head)?;
+
+		let fin = head[0] & 0x80 != 0;
+		//no extensions are negotiated, so the reserved bits must be clear
+		if head[0] & 0x70 != 0 {
+			return Err(WebSocketError::Protocol("reserved bits set"));
+		}
+		let opcode = match OpCode::from_bits(head[0] & 0x0F) {
+			Some(o) => o,
+			None => return Err(WebSocketError::Protocol("unknown opcode"))
+		};
+
+		if head[1] & 0x80 == 0 {
+			return Err(WebSocketError::Protocol("client frames must be masked"));
+		}
+
+		let len = match head[1] & 0x7F {
+			126 => {
+				let mut ext = [0_u8; 2];
+				reader.read_exact(&mut ext)?;
+				u16::from_be_bytes(ext) as u64
+			},
+			127 => {
+				let mut ext = [0_u8; 8];
+				reader.read_exact(&mut ext)?;
+				let len = u64::from_be_bytes(ext);
+				if len >> 63 != 0 {
+					return Err(WebSocketError::Protocol("payload length has the most significant bit set"));
+				}
+				len
+			},
+			l => l as u64
+		};
+
+		if opcode.is_control() && (len > 125 || !fin) {
+			return Err(WebSocketError::Protocol("control frames must be short and unfragmented"));
+		}
+		if len > max_payload as u64 {
+			return Err(WebSocketError::TooLarge);
+		}
+
+		let mut mask = [0_u8; 4];
+		reader.read_exact(&mut mask)?;
+
+		let mut payload = vec![0_u8; len as usize];
+		reader.read_exact(&mut payload)?;
+		for (ix, b) in payload.iter_mut().enumerate() {
+			*b ^= mask[ix % 4];
+		}
+
+		Ok(Frame { fin, opcode, payload })
+	}
+
+	/// Writes the frame the way a server sends it: unmasked.
+	pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), WebSocketError> {
+		let mut bytes = Vec::with_capacity(self.payload.len() + 10);
+		bytes.push(if self.fin { 0x80 } else { 0 } | self.opcode.bits());
+
+		let len = self.payload.len();
+		if len < 126 {
+			bytes.push(len as u8);
+		} else if len <= u16::MAX as usize {
+			bytes.push(126);
+			bytes.extend_from_slice(&(len as u16).to_be_bytes());
+		} else {
+			bytes.push(127);
+			bytes.extend_from_slice(&(len as u64).to_be_bytes());
+		}
+
+		bytes.extend_from_slice(&self.payload);
+		writer.write_all(&bytes)?;
+		writer.flush()?;
+		Ok(())
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reads_masked_client_frames() {
+		//single-frame masked text message "Hello" from RFC 6455 5.7
+		let mut raw: &[u8] = &[0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+		let frame = Frame::read(&mut raw, 1024).unwrap();
+		assert_eq!(frame, Frame::new(OpCode::Text, b"Hello".to_vec()));
+
+		//the same frame unmasked is rejected
+		let mut raw: &[u8] = &[0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f];
+		assert!(Frame::read(&mut raw, 1024).is_err());
+
+		let mut raw: &[u8] = &[0x89, 0xFE, 0x00, 0x7E, 0, 0, 0, 0];
+		assert!(Frame::read(&mut raw, 1024).is_err(), "control frames can't use extended lengths");
+	}
+
+	#[test]
+	fn writes_unmasked_frames_with_extended_lengths() {
+		let mut out = Vec::new();
+		Frame::new(OpCode::Text, b"Hello".to_vec()).write(&mut out).unwrap();
+		assert_eq!(out, vec![0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]);
+
+		let mut out = Vec::new();
+		Frame::new(OpCode::Binary, vec![0; 256]).write(&mut out).unwrap();
+		assert_eq!(&out[..4], &[0x82, 0x7E, 0x01, 0x00]);
+
+		let mut out = Vec::new();
+		Frame::new(OpCode::Binary, vec![0; 65536]).write(&mut out).unwrap();
+		assert_eq!(&out[..10], &[0x82, 0x7F, 0, 0, 0, 0, 0, 1, 0, 0]);
+	}
+}
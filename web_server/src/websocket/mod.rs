@@ -0,0 +1,395 @@
+mod frame;
+mod notifications;
+
+pub use self::frame::{Frame, OpCode};
+pub use self::notifications::Notifications;
+
+use std::fmt;
+use std::io;
+use std::io::{Chain, Cursor, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use ring::digest;
+
+use encoding::{to_base64, from_base64};
+use http::model::*;
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const VERSION: &str = "13";
+//applies to whole messages, so fragmenting doesn't get around it
+const MAX_MESSAGE_LEN: usize = 1 << 20;
+
+pub mod close_code {
+	pub const NORMAL: u16 = 1000;
+	pub const GOING_AWAY: u16 = 1001;
+	pub const PROTOCOL_ERROR: u16 = 1002;
+	pub const INVALID_DATA: u16 = 1007;
+	pub const TOO_LARGE: u16 = 1009;
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Message {
+	Text(String),
+	Binary(Vec<u8>),
+	/// Already answered with a pong by the time it is returned.
+	Ping(Vec<u8>),
+	Pong(Vec<u8>),
+	/// The client's close code and reason, if it sent one. The close has already been echoed.
+	Close(Option<(u16, String)>)
+}
+
+#[derive(Debug)]
+pub enum WebSocketError {
+	Io(io::Error),
+	Protocol(&'static str),
+	InvalidUtf8,
+	TooLarge,
+	/// The client has already closed the connection.
+	Closed
+}
+
+impl WebSocketError {
+	/// The code to fail the connection with, for errors caused by the client.
+	fn close_code(&self) -> Option<u16> {
+		match *self {
+			WebSocketError::Protocol(_) => Some(close_code::PROTOCOL_ERROR),
+			WebSocketError::InvalidUtf8 => Some(close_code::INVALID_DATA),
+			WebSocketError::TooLarge => Some(close_code::TOO_LARGE),
+			_ => None
+		}
+	}
+}
+
+impl From<io::Error> for WebSocketError {
+	fn from(e: io::Error) -> WebSocketError {
+		WebSocketError::Io(e)
+	}
+}
+
+impl fmt::Display for WebSocketError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			WebSocketError::Io(ref e) => write!(f, "websocket io error: {}", e),
+			WebSocketError::Protocol(m) => write!(f, "websocket protocol error: {}", m),
+			WebSocketError::InvalidUtf8 => write!(f, "websocket text message is not valid UTF-8"),
+			WebSocketError::TooLarge => write!(f, "websocket message longer than {} bytes", MAX_MESSAGE_LEN),
+			WebSocketError::Closed => write!(f, "websocket is closed")
+		}
+	}
+}
+
+/// Whether the request asks to switch the connection to the websocket protocol.
+pub fn is_upgrade_request(request: &HttpRequest) -> bool {
+	has_token(request, "Upgrade", "websocket")
+}
+
+/// Validates an opening handshake (RFC 6455 4.2.1) and builds the 101 response for it.
+pub fn handshake(request: &HttpRequest) -> Result<HttpResponse, HttpResponse> {
+	let start_line = &request.start_line;
+	if start_line.method != AllowedMethod::GET || (start_line.major_version, start_line.minor_version) < (1, 1)
+		|| !is_upgrade_request(request) || !has_token(request, "Connection", "upgrade") {
+		return Err(HttpResponse::new(StatusCode::bad_request()));
+	}
+
+	if request.headers.first("Sec-WebSocket-Version") != Some(VERSION) {
+		return Err(upgrade_required());
+	}
+
+	let key = match request.headers.first("Sec-WebSocket-Key") {
+		Some(k) if from_base64(k).map(|k| k.len() == 16).unwrap_or(false) => k,
+		_ => return Err(HttpResponse::new(StatusCode::bad_request()))
+	};
+
+	//browsers send cookies with cross-site websocket requests, so only our own pages may open one
+	if let Some(origin) = request.headers.first("Origin") {
+		let origin_host = origin.split_once("://").map(|(_, host)| host).unwrap_or("");
+		match request.headers.first("Host") {
+			Some(host) if host.eq_ignore_ascii_case(origin_host) => {},
+			_ => return Err(HttpResponse::new(StatusCode::forbidden()))
+		}
+	}
+
+	Ok(HttpResponse::new(StatusCode::switching_protocols())
+		.with_header("Upgrade", "websocket")
+		.with_header("Connection", "Upgrade")
+		.with_header("Sec-WebSocket-Accept", &accept_key(key)))
+}
+
+/// The response for a plain http request to a websocket endpoint.
+pub fn upgrade_required() -> HttpResponse {
+	HttpResponse::new(StatusCode::upgrade_required())
+		.with_header("Upgrade", "websocket")
+		.with_header("Connection", "Upgrade")
+		.with_header("Sec-WebSocket-Version", VERSION)
+}
+
+pub fn accept_key(key: &str) -> String {
+	let hashed = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, format!("{}{}", key, ACCEPT_GUID).as_bytes());
+	to_base64(hashed.as_ref())
+}
+
+fn has_token(request: &HttpRequest, header: &str, token: &str) -> bool {
+	request.headers.get(header)
+		.map(|h| h.values().iter().flat_map(|v| v.split(',')).any(|t| t.trim().eq_ignore_ascii_case(token)))
+		.unwrap_or(false)
+}
+
+
+/// The server end of a websocket connection.
+pub struct WebSocket<S: Read + Write> {
+	//bytes the http parser read past the handshake come first
+	reader: Chain<Cursor<Vec<u8>>, S>,
+	sender: WebSocketSender<S>,
+	//a text or binary message still waiting on continuation frames
+	fragment: Option<(OpCode, Vec<u8>)>,
+	close_received: bool
+}
+
+impl<S: Read + Write> WebSocket<S> {
+	/// `reader` and `writer` are two handles to the same connection, e.g. a `TcpStream` and its `try_clone()`.
+	pub fn new(reader: S, writer: S, buffered: Vec<u8>) -> WebSocket<S> {
+		WebSocket {
+			reader: Cursor::new(buffered).chain(reader),
+			sender: WebSocketSender {
+				writer: Arc::new(Mutex::new(writer)),
+				close_sent: Arc::new(AtomicBool::new(false))
+			},
+			fragment: None,
+			close_received: false
+		}
+	}
+
+	/// A handle for sending from other threads while this one waits on `recv`.
+	pub fn sender(&self) -> WebSocketSender<S> {
+		self.sender.clone()
+	}
+
+	#[allow(dead_code)]
+	pub fn send(&self, message: Message) -> Result<(), WebSocketError> {
+		self.sender.send(message)
+	}
+
+	pub fn close(&self, code: u16, reason: &str) -> Result<(), WebSocketError> {
+		self.sender.close(code, reason)
+	}
+
+	/// Waits for the next message. Fragmented messages are reassembled and pings are answered.
+	/// If the client breaks the protocol the connection is closed with the matching code before the error is returned.
+	pub fn recv(&mut self) -> Result<Message, WebSocketError> {
+		let result = self.read_message();
+		if let Err(ref e) = result {
+			if let Some(code) = e.close_code() {
+				let _ = self.sender.close(code, "");
+			}
+		}
+		result
+	}
+
+	fn read_message(&mut self) -> Result<Message, WebSocketError> {
+		if self.close_received {
+			return Err(WebSocketError::Closed);
+		}
+
+		loop {
+			let frame = Frame::read(&mut self.reader, MAX_MESSAGE_LEN)?;
+
+			match frame.opcode {
+				OpCode::Ping => {
+					self.sender.send_frame(&Frame::new(OpCode::Pong, frame.payload.clone()))?;
+					return Ok(Message::Ping(frame.payload));
+				},
+				OpCode::Pong => return Ok(Message::Pong(frame.payload)),
+				OpCode::Close => {
+					self.close_received = true;
+					let reason = parse_close(&frame.payload)?;
+					let code = reason.as_ref().map(|r| r.0).unwrap_or(close_code::NORMAL);
+					self.sender.close(code, "")?;
+					return Ok(Message::Close(reason));
+				},
+				OpCode::Text | OpCode::Binary => {
+					if self.fragment.is_some() {
+						return Err(WebSocketError::Protocol("new message started before the last one finished"));
+					}
+					if frame.fin {
+						return to_message(frame.opcode, frame.payload);
+					}
+					self.fragment = Some((frame.opcode, frame.payload));
+				},
+				OpCode::Continuation => {
+					let (opcode, mut payload) = match self.fragment.take() {
+						Some(f) => f,
+						None => return Err(WebSocketError::Protocol("continuation frame without a message to continue"))
+					};
+					if payload.len() + frame.payload.len() > MAX_MESSAGE_LEN {
+						return Err(WebSocketError::TooLarge);
+					}
+					payload.extend_from_slice(&frame.payload);
+
+					if frame.fin {
+						return to_message(opcode, payload);
+					}
+					self.fragment = Some((opcode, payload));
+				}
+			}
+		}
+	}
+}
+
+fn to_message(opcode: OpCode, payload: Vec<u8>) -> Result<Message, WebSocketError> {
+	match opcode {
+		OpCode::Text => String::from_utf8(payload).map(Message::Text).map_err(|_| WebSocketError::InvalidUtf8),
+		_ => Ok(Message::Binary(payload))
+	}
+}
+
+fn parse_close(payload: &[u8]) -> Result<Option<(u16, String)>, WebSocketError> {
+	match payload.len() {
+		0 => return Ok(None),
+		1 => return Err(WebSocketError::Protocol("close frame with a partial status code")),
+		_ => {}
+	}
+
+	let code = u16::from_be_bytes([payload[0], payload[1]]);
+	match code {
+		1000 ..= 1003 | 1007 ..= 1011 | 3000 ..= 4999 => {},
+		_ => return Err(WebSocketError::Protocol("invalid close code"))
+	}
+
+	match String::from_utf8(payload[2..].to_vec()) {
+		Ok(reason) => Ok(Some((code, reason))),
+		Err(_) => Err(WebSocketError::InvalidUtf8)
+	}
+}
+
+
+/// Sends messages on a websocket; clones share the connection.
+pub struct WebSocketSender<S: Write> {
+	writer: Arc<Mutex<S>>,
+	close_sent: Arc<AtomicBool>
+}
+
+impl<S: Write> Clone for WebSocketSender<S> {
+	fn clone(&self) -> WebSocketSender<S> {
+		WebSocketSender {
+			writer: Arc::clone(&self.writer),
+			close_sent: Arc::clone(&self.close_sent)
+		}
+	}
+}
+
+impl<S: Write> WebSocketSender<S> {
+	pub fn send(&self, message: Message) -> Result<(), WebSocketError> {
+		match message {
+			Message::Text(t) => self.send_frame(&Frame::new(OpCode::Text, t.into_bytes())),
+			Message::Binary(b) => self.send_frame(&Frame::new(OpCode::Binary, b)),
+			Message::Ping(p) => self.send_frame(&Frame::new(OpCode::Ping, p)),
+			Message::Pong(p) => self.send_frame(&Frame::new(OpCode::Pong, p)),
+			Message::Close(Some((code, reason))) => self.close(code, &reason),
+			Message::Close(None) => self.close(close_code::NORMAL, "")
+		}
+	}
+
+	/// Starts (or completes) the closing handshake. Only the first close is sent.
+	pub fn close(&self, code: u16, reason: &str) -> Result<(), WebSocketError> {
+		let mut writer = self.writer.lock().unwrap();
+		if self.close_sent.swap(true, Ordering::SeqCst) {
+			return Ok(());
+		}
+
+		//control frame payloads are limited to 125 bytes
+		let mut reason_len = reason.len().min(123);
+		while !reason.is_char_boundary(reason_len) {
+			reason_len -= 1;
+		}
+
+		let mut payload = code.to_be_bytes().to_vec();
+		payload.extend_from_slice(&reason.as_bytes()[..reason_len]);
+		Frame::new(OpCode::Close, payload).write(&mut *writer)
+	}
+
+	pub fn is_closed(&self) -> bool {
+		self.close_sent.load(Ordering::SeqCst)
+	}
+
+	fn send_frame(&self, frame: &Frame) -> Result<(), WebSocketError> {
+		let mut writer = self.writer.lock().unwrap();
+		if self.close_sent.load(Ordering::SeqCst) {
+			return Err(WebSocketError::Closed);
+		}
+		frame.write(&mut *writer)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use routing::Routable;
+
+	fn client_frame(first: u8, payload: &[u8]) -> Vec<u8> {
+		let mask = [1_u8, 2, 3, 4];
+		let mut bytes = vec![first, 0x80 | payload.len() as u8];
+		bytes.extend_from_slice(&mask);
+		bytes.extend(payload.iter().enumerate().map(|(ix, b)| b ^ mask[ix % 4]));
+		bytes
+	}
+
+	fn socket(input: Vec<u8>) -> WebSocket<Cursor<Vec<u8>>> {
+		WebSocket::new(Cursor::new(input), Cursor::new(Vec::new()), Vec::new())
+	}
+
+	fn sent(socket: &WebSocket<Cursor<Vec<u8>>>) -> Vec<u8> {
+		socket.sender.writer.lock().unwrap().get_ref().clone()
+	}
+
+	#[test]
+	fn handshake_accept_key() {
+		//example from RFC 6455 1.3
+		assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+		let mut headers = HeaderCollection::init_empty();
+		headers.get_or_add("Host").add(String::from("localhost:7878"));
+		headers.get_or_add("Upgrade").add(String::from("websocket"));
+		headers.get_or_add("Connection").add(String::from("keep-alive, Upgrade"));
+		headers.get_or_add("Sec-WebSocket-Key").add(String::from("dGhlIHNhbXBsZSBub25jZQ=="));
+		headers.get_or_add("Sec-WebSocket-Version").add(String::from("13"));
+		headers.get_or_add("Origin").add(String::from("http://localhost:7878"));
+		let mut request = HttpRequest { start_line: Routable::get(&"/ws"), headers, body: Vec::new() };
+
+		let response = handshake(&request).ok().unwrap();
+		assert_eq!(response.status_line.status_code(), StatusCode::switching_protocols());
+		assert_eq!(response.headers.first("Sec-WebSocket-Accept"), Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+
+		request.headers.remove("Origin");
+		request.headers.get_or_add("Origin").add(String::from("http://evil.example"));
+		assert_eq!(handshake(&request).err().unwrap().status_line.status_code(), StatusCode::forbidden());
+	}
+
+	#[test]
+	fn fragments_reassembled_around_pings() {
+		let mut input = client_frame(0x01, b"Hel");
+		input.extend(client_frame(0x89, b"hi"));
+		input.extend(client_frame(0x80, b"lo"));
+		let mut socket = socket(input);
+
+		assert_eq!(socket.recv().unwrap(), Message::Ping(b"hi".to_vec()));
+		assert_eq!(socket.recv().unwrap(), Message::Text(String::from("Hello")));
+		assert_eq!(sent(&socket), vec![0x8A, 0x02, b'h', b'i']);
+	}
+
+	#[test]
+	fn close_echoed_and_protocol_errors_close() {
+		let mut socket = socket(client_frame(0x88, &[0x03, 0xE8, b'b', b'y', b'e']));
+		assert_eq!(socket.recv().unwrap(), Message::Close(Some((1000, String::from("bye")))));
+		assert_eq!(sent(&socket), vec![0x88, 0x02, 0x03, 0xE8]);
+		assert!(socket.send(Message::Text(String::from("late"))).is_err());
+
+		let mut socket = self::socket(client_frame(0x80, b"orphan"));
+		assert!(socket.recv().is_err());
+		assert_eq!(sent(&socket), vec![0x88, 0x02, 0x03, 0xEA]);
+
+		let mut socket = self::socket(client_frame(0x81, &[0xff, 0xfe]));
+		assert!(socket.recv().is_err());
+		assert_eq!(sent(&socket), vec![0x88, 0x02, 0x03, 0xEF]);
+	}
+}
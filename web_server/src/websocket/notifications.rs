@@ -0,0 +1,26 @@
+use std::sync::Mutex;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// Fans out messages to every open websocket subscribed to live updates.
+pub struct Notifications {
+	subscribers: Mutex<Vec<Sender<String>>>
+}
+
+impl Notifications {
+	pub fn new() -> Notifications {
+		Notifications {
+			subscribers: Mutex::new(Vec::new())
+		}
+	}
+
+	pub fn subscribe(&self) -> Receiver<String> {
+		let (sender, receiver) = channel();
+		self.subscribers.lock().unwrap().push(sender);
+		receiver
+	}
+
+	/// Sends the message to every subscriber, dropping the ones whose connection has gone.
+	pub fn publish(&self, message: &str) {
+		self.subscribers.lock().unwrap().retain(|s| s.send(message.to_string()).is_ok());
+	}
+}